@@ -0,0 +1,286 @@
+use crate::i18n::tr;
+use m3u8_rs::{DateRange, KeyMethod, MediaPlaylist, MediaSegment};
+use tracing::info;
+
+/// 一个广告时段，位置为切片时间线上的秒数；`end` 为 None 时持续到 CUE-IN 或同 ID 的结束标记
+struct Window {
+    id: Option<String>,
+    start: f64,
+    end: Option<f64>,
+}
+
+/// 按顺序检查切片是否落在广告时段内。识别以下标记：
+/// - `#EXT-X-CUE-OUT[:时长]` / `#EXT-X-CUE-IN`
+/// - 带 `SCTE35-OUT` 的 `#EXT-X-DATERANGE`，或 CLASS 属于 `--ad-class` 的 DATERANGE
+pub struct AdBreaks {
+    classes: Vec<String>,
+    windows: Vec<Window>,
+    elapsed: f64,
+    /// 最近一个 EXT-X-PROGRAM-DATE-TIME (毫秒时间戳) 及其在时间线上的位置
+    anchor: Option<(i64, f64)>,
+}
+
+impl AdBreaks {
+    /// `classes` 为空时只认 SCTE-35 标记；否则只认 CLASS 在其中的 DATERANGE
+    pub fn new(classes: &[String]) -> Self {
+        Self {
+            classes: classes.to_vec(),
+            windows: Vec::new(),
+            elapsed: 0.0,
+            anchor: None,
+        }
+    }
+
+    /// 处理下一个切片，返回它是否属于广告
+    pub fn observe(&mut self, seg: &MediaSegment) -> bool {
+        if let Some(pdt) = &seg.program_date_time {
+            self.anchor = Some((pdt.timestamp_millis(), self.elapsed));
+        }
+        for tag in &seg.unknown_tags {
+            match tag.tag.as_str() {
+                "X-CUE-OUT" => {
+                    let duration = tag.rest.as_deref().and_then(cue_duration);
+                    self.windows.push(Window {
+                        id: None,
+                        start: self.elapsed,
+                        end: duration.map(|d| self.elapsed + d),
+                    });
+                }
+                "X-CUE-IN" => {
+                    for window in &mut self.windows {
+                        if window.end.is_none_or(|end| end > self.elapsed) {
+                            window.end = Some(self.elapsed);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        if let Some(range) = &seg.daterange {
+            self.daterange(range);
+        }
+
+        let middle = self.elapsed + f64::from(seg.duration) / 2.0;
+        let ad = self
+            .windows
+            .iter()
+            .any(|w| w.start <= middle && w.end.is_none_or(|end| middle < end));
+        self.elapsed += f64::from(seg.duration);
+        let elapsed = self.elapsed;
+        self.windows
+            .retain(|w| w.end.is_none_or(|end| end > elapsed));
+        ad
+    }
+
+    fn daterange(&mut self, range: &DateRange) {
+        let start = match self.anchor {
+            Some((millis, at)) => {
+                at + (range.start_date.timestamp_millis() - millis) as f64 / 1000.0
+            }
+            None => self.elapsed,
+        };
+        let end = range
+            .duration
+            .or(range.planned_duration)
+            .map(|d| start + d)
+            .or_else(|| {
+                range.end_date.map(|end| {
+                    start
+                        + (end.timestamp_millis() - range.start_date.timestamp_millis()) as f64
+                            / 1000.0
+                })
+            });
+        let attribute = |name: &str| {
+            range
+                .other_attributes
+                .as_ref()
+                .is_some_and(|attrs| attrs.contains_key(name))
+        };
+
+        // 同一 ID 的后续 DATERANGE 补充结束时间
+        if let Some(window) = self
+            .windows
+            .iter_mut()
+            .find(|w| w.id.as_deref() == Some(range.id.as_str()))
+        {
+            if end.is_some() {
+                window.end = end;
+            } else if attribute("SCTE35-IN") {
+                window.end = Some(self.elapsed);
+            }
+            return;
+        }
+        let matched = if self.classes.is_empty() {
+            attribute("SCTE35-OUT")
+        } else {
+            range
+                .class
+                .as_ref()
+                .is_some_and(|class| self.classes.contains(class))
+        };
+        if matched {
+            self.windows.push(Window {
+                id: Some(range.id.clone()),
+                start,
+                end,
+            });
+        }
+    }
+}
+
+/// `#EXT-X-CUE-OUT:30` 或 `#EXT-X-CUE-OUT:DURATION=30`
+fn cue_duration(rest: &str) -> Option<f64> {
+    let value = rest
+        .split(',')
+        .find_map(|attr| attr.trim().strip_prefix("DURATION="))
+        .unwrap_or(rest);
+    value.trim().parse().ok()
+}
+
+/// 从点播播放列表中删掉广告切片，返回保留的各切片原来的媒体序列号。
+/// 删掉切片后下标不再对应媒体序列号，因此之后保留的切片会显式带上生效中的密钥
+/// (含按序列号计算的 IV) 与初始化段，每段广告之后的第一个切片标记为不连续点
+pub fn strip(playlist: &mut MediaPlaylist, classes: &[String]) -> Vec<u64> {
+    let mut breaks = AdBreaks::new(classes);
+    let mut key = None;
+    let mut map = None;
+    let mut dropped = 0usize;
+    let mut dropped_secs = 0.0;
+    let mut gap = false;
+    let mut kept = Vec::with_capacity(playlist.segments.len());
+    let mut sequences = Vec::with_capacity(playlist.segments.len());
+    for (i, mut seg) in std::mem::take(&mut playlist.segments)
+        .into_iter()
+        .enumerate()
+    {
+        if seg.key.is_some() {
+            key = seg.key.clone();
+        }
+        if seg.map.is_some() {
+            map = seg.map.clone();
+        }
+        if breaks.observe(&seg) {
+            dropped += 1;
+            dropped_secs += f64::from(seg.duration);
+            gap = true;
+            continue;
+        }
+        let seq = playlist.media_sequence + i as u64;
+        if dropped > 0 {
+            seg.key = key.clone().map(|mut k| {
+                if matches!(k.method, KeyMethod::AES128) && k.iv.is_none() {
+                    k.iv = Some(format!("0x{:032x}", seq));
+                }
+                k
+            });
+            if seg.map.is_none() {
+                seg.map = map.clone();
+            }
+        }
+        if gap && !kept.is_empty() {
+            seg.discontinuity = true;
+        }
+        gap = false;
+        kept.push(seg);
+        sequences.push(seq);
+    }
+    playlist.segments = kept;
+    if dropped > 0 {
+        info!(
+            "{}",
+            tr!("跳过 {} 个广告切片 (约 {:.0}s)", dropped, dropped_secs)
+        );
+    } else {
+        info!("{}", tr!("播放列表中没有找到广告标记"));
+    }
+    sequences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use m3u8_rs::{ExtTag, Key};
+
+    fn tag(name: &str, rest: Option<&str>) -> ExtTag {
+        ExtTag {
+            tag: name.into(),
+            rest: rest.map(Into::into),
+        }
+    }
+
+    /// 每个切片 10 秒，媒体序列号从 100 开始
+    fn playlist(count: usize) -> MediaPlaylist {
+        MediaPlaylist {
+            media_sequence: 100,
+            segments: (0..count)
+                .map(|i| MediaSegment {
+                    uri: format!("seg{}.ts", i),
+                    duration: 10.0,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn aes(uri: &str) -> Option<Key> {
+        Some(Key {
+            method: KeyMethod::AES128,
+            uri: Some(uri.into()),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn strip_keeps_original_sequences_and_pins_ivs() {
+        let mut list = playlist(6);
+        list.segments[0].key = aes("a.key");
+        list.segments[1].unknown_tags = vec![tag("X-CUE-OUT", Some("20"))];
+        list.segments[4].key = aes("b.key");
+        let sequences = strip(&mut list, &[]);
+        let uris: Vec<&str> = list.segments.iter().map(|s| s.uri.as_str()).collect();
+        assert_eq!(uris, ["seg0.ts", "seg3.ts", "seg4.ts", "seg5.ts"]);
+        assert_eq!(sequences, [100, 103, 104, 105]);
+        assert_eq!(list.media_sequence, 100);
+
+        let ivs: Vec<Option<&str>> = list
+            .segments
+            .iter()
+            .map(|s| s.key.as_ref().and_then(|k| k.iv.as_deref()))
+            .collect();
+        // 广告之前的切片仍按下标推算 IV，之后的切片按原来的序列号固定 IV
+        assert_eq!(
+            ivs,
+            [
+                None,
+                Some("0x00000000000000000000000000000067"),
+                Some("0x00000000000000000000000000000068"),
+                Some("0x00000000000000000000000000000069"),
+            ]
+        );
+        let uri = |i: usize| list.segments[i].key.as_ref().and_then(|k| k.uri.as_deref());
+        assert_eq!(uri(1), Some("a.key"));
+        assert_eq!(uri(3), Some("b.key"));
+    }
+
+    #[test]
+    fn strip_marks_discontinuity_after_each_break() {
+        let mut list = playlist(6);
+        list.segments[0].unknown_tags = vec![tag("X-CUE-OUT", None)];
+        list.segments[1].unknown_tags = vec![tag("X-CUE-IN", None)];
+        list.segments[3].unknown_tags = vec![tag("X-CUE-OUT", Some("DURATION=10"))];
+        let sequences = strip(&mut list, &[]);
+        assert_eq!(sequences, [101, 102, 104, 105]);
+        let discontinuities: Vec<bool> = list.segments.iter().map(|s| s.discontinuity).collect();
+        // 开头的广告之后没有已保留的切片，不需要标记
+        assert_eq!(discontinuities, [false, false, true, false]);
+    }
+
+    #[test]
+    fn strip_without_markers_keeps_everything() {
+        let mut list = playlist(3);
+        assert_eq!(strip(&mut list, &[]), [100, 101, 102]);
+        assert_eq!(list.segments.len(), 3);
+        assert!(list.segments.iter().all(|s| !s.discontinuity));
+    }
+}
@@ -0,0 +1,208 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::{HashMap, hash_map};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::Job;
+use crate::i18n::tr;
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum ArchiveFormat {
+    /// 写入输出文件旁的 `<输出名>.archive/` 目录
+    Dir,
+    /// 任务结束后打包为 `<输出名>.archive.tar`
+    Tar,
+}
+
+/// 存档中的一个文件与其来源地址
+#[derive(Serialize)]
+struct Entry {
+    file: String,
+    url: String,
+}
+
+/// 存档目录下的 `index.json`
+#[derive(Serialize, Default)]
+struct Index {
+    playlists: Vec<Entry>,
+    keys: Vec<Entry>,
+    /// 各输出 (视频、第二路变体流、音轨) 的切片地址表
+    segments: Vec<String>,
+}
+
+/// 切片地址表 `<输出名>.segments.json` 中的一项
+#[derive(Serialize)]
+pub struct SegmentRecord {
+    pub index: usize,
+    pub sequence: u64,
+    pub url: String,
+    pub duration: f32,
+    /// 存档中的密钥文件，未加密时为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iv: Option<String>,
+}
+
+struct Store {
+    dir: PathBuf,
+    index: Index,
+    /// 已保存的密钥内容与文件名，同一密钥只保存一次
+    keys: HashMap<Vec<u8>, String>,
+}
+
+/// `--save-manifest`：把任务下载到的播放列表、密钥与切片地址表原样保存在输出文件旁，
+/// 便于之后复现或排查下载问题。存档在任务第一次写入时创建 (清空上次的存档)，
+/// 同一任务的第二路变体流与音轨写入同一个存档；写入失败只输出警告，不影响下载
+pub struct Archive {
+    format: ArchiveFormat,
+    jobs: Mutex<HashMap<usize, Store>>,
+}
+
+impl Archive {
+    pub fn new(format: ArchiveFormat) -> Self {
+        Self {
+            format,
+            jobs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn playlist(&self, job: &Job, url: &str, content: &[u8]) {
+        self.record(job, |store| {
+            let name = url
+                .split(['?', '#'])
+                .next()
+                .and_then(|path| path.rsplit('/').next())
+                .map(sanitize)
+                .filter(|name| !name.is_empty())
+                .unwrap_or_else(|| "playlist.m3u8".to_string());
+            let file = format!("playlists/{:02}-{}", store.index.playlists.len() + 1, name);
+            store.write(&file, content)?;
+            store.index.playlists.push(Entry {
+                file,
+                url: url.to_string(),
+            });
+            Ok(())
+        });
+    }
+
+    /// 保存密钥并返回其在存档中的文件名
+    pub fn key(&self, job: &Job, url: &str, key: &[u8]) -> Option<String> {
+        let mut saved = None;
+        self.record(job, |store| {
+            if let Some(file) = store.keys.get(key) {
+                saved = Some(file.clone());
+                return Ok(());
+            }
+            let file = format!("keys/{:02}.key", store.keys.len() + 1);
+            store.write(&file, key)?;
+            store.keys.insert(key.to_vec(), file.clone());
+            store.index.keys.push(Entry {
+                file: file.clone(),
+                url: url.to_string(),
+            });
+            saved = Some(file);
+            Ok(())
+        });
+        saved
+    }
+
+    /// 保存 `job` 这一路输出的切片地址表
+    pub fn segments(&self, job: &Job, records: &[SegmentRecord]) {
+        self.record(job, |store| {
+            let stem = job.output.file_stem().unwrap_or_default().to_string_lossy();
+            let file = format!("{}.segments.json", stem);
+            store.write(&file, &serde_json::to_vec_pretty(records)?)?;
+            if !store.index.segments.contains(&file) {
+                store.index.segments.push(file);
+            }
+            Ok(())
+        });
+    }
+
+    /// 任务结束 (无论成败)：需要时打包为 tar
+    pub async fn finish(&self, job: usize) {
+        let Some(store) = self.lock().remove(&job) else {
+            return;
+        };
+        let result = match self.format {
+            ArchiveFormat::Dir => Ok(store.dir),
+            ArchiveFormat::Tar => tokio::task::spawn_blocking(move || pack(&store.dir))
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|r| r),
+        };
+        match result {
+            Ok(path) => info!("{}", tr!("已保存下载存档: {}", path.display())),
+            Err(e) => warn!("{}", tr!("打包下载存档失败: {:#}", e)),
+        }
+    }
+
+    fn record(&self, job: &Job, f: impl FnOnce(&mut Store) -> Result<()>) {
+        let mut jobs = self.lock();
+        let store = match jobs.entry(job.id) {
+            hash_map::Entry::Occupied(entry) => entry.into_mut(),
+            hash_map::Entry::Vacant(entry) => match Store::create(job) {
+                Ok(store) => entry.insert(store),
+                Err(e) => {
+                    warn!("{}", tr!("无法创建下载存档: {:#}", e));
+                    return;
+                }
+            },
+        };
+        let written = f(store)
+            .and_then(|()| store.write("index.json", &serde_json::to_vec_pretty(&store.index)?));
+        if let Err(e) = written {
+            warn!("{}", tr!("写入下载存档失败: {:#}", e));
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<usize, Store>> {
+        self.jobs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Store {
+    fn create(job: &Job) -> Result<Self> {
+        let dir = job.output.with_extension("archive");
+        if dir.exists() {
+            fs::remove_dir_all(&dir).with_context(|| tr!("无法清空下载存档: {}", dir.display()))?;
+        }
+        fs::create_dir_all(&dir).with_context(|| tr!("无法创建下载存档: {}", dir.display()))?;
+        Ok(Self {
+            dir,
+            index: Index::default(),
+            keys: HashMap::new(),
+        })
+    }
+
+    fn write(&self, file: &str, content: &[u8]) -> Result<()> {
+        let path = self.dir.join(file);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, content).with_context(|| tr!("无法写入: {}", path.display()))
+    }
+}
+
+/// 把存档目录打包为同名的 `.tar` 并删除目录
+fn pack(dir: &Path) -> Result<PathBuf> {
+    let path = dir.with_extension("archive.tar");
+    let file = fs::File::create(&path).with_context(|| tr!("无法创建: {}", path.display()))?;
+    let mut builder = tar::Builder::new(file);
+    builder.append_dir_all(".", dir)?;
+    builder.into_inner()?;
+    fs::remove_dir_all(dir)?;
+    Ok(path)
+}
+
+/// 播放列表文件名只保留常见字符
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+        .collect()
+}
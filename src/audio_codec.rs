@@ -0,0 +1,116 @@
+use clap::ValueEnum;
+use std::path::Path;
+use tracing::{debug, info, warn};
+
+use crate::i18n::tr;
+use crate::{Args, manifest, pipeline};
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum AudioCodec {
+    /// 输出容器支持源音频编码时直接复制，否则转为 AAC
+    Auto,
+    /// 直接复制，不重新编码
+    Copy,
+    Aac,
+    Opus,
+    Flac,
+}
+
+/// 未指定 --audio-bitrate 时的码率
+const AAC_BITRATE: &str = "256k";
+const OPUS_BITRATE: &str = "192k";
+
+/// 决定实际使用的音频编码：`auto` 时用 FFprobe 查看各输入的音频编码，
+/// 输出容器都能直接容纳时复制，否则 (包括无法查看的流水线输入) 转为 AAC
+pub async fn resolve(args: &Args, inputs: &[&str], output: &Path) -> AudioCodec {
+    // 响度标准化要经过滤镜，不能直接复制
+    if args.normalize_audio && matches!(args.audio_codec, AudioCodec::Auto | AudioCodec::Copy) {
+        if args.audio_codec == AudioCodec::Copy {
+            warn!("{}", tr!("--normalize-audio 需要重新编码音频，改为 AAC"));
+        }
+        return AudioCodec::Aac;
+    }
+    if args.audio_codec != AudioCodec::Auto {
+        return args.audio_codec;
+    }
+    // 指定了码率说明需要重新编码
+    if args.audio_bitrate > 0 {
+        return AudioCodec::Aac;
+    }
+    let ext = output
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let mut codecs = Vec::new();
+    for input in inputs {
+        if *input == pipeline::INPUT {
+            return AudioCodec::Aac;
+        }
+        let Some(path) = manifest::probe_target(Path::new(input)).await else {
+            return AudioCodec::Aac;
+        };
+        match manifest::probe(&path).await {
+            Ok(info) => codecs.extend(
+                info.streams
+                    .into_iter()
+                    .filter(|s| s.codec_type == "audio")
+                    .map(|s| s.codec_name),
+            ),
+            Err(e) => {
+                debug!("{}", tr!("无法查看源音频编码，转为 AAC: {:#}", e));
+                return AudioCodec::Aac;
+            }
+        }
+    }
+    if codecs.is_empty() || !codecs.iter().all(|c| supports(&ext, c)) {
+        return AudioCodec::Aac;
+    }
+    info!(
+        "{}",
+        tr!("源音频为 {}，直接复制不重新编码", codecs.join(", "))
+    );
+    AudioCodec::Copy
+}
+
+/// 输出容器能否直接容纳这种音频编码
+fn supports(ext: &str, codec: &str) -> bool {
+    match ext {
+        "mkv" => true,
+        "webm" => matches!(codec, "opus" | "vorbis"),
+        "mov" => matches!(codec, "aac" | "mp3" | "ac3" | "eac3" | "alac"),
+        "mp4" | "m4v" | "m4a" => matches!(
+            codec,
+            "aac" | "mp3" | "ac3" | "eac3" | "alac" | "opus" | "flac"
+        ),
+        _ => matches!(codec, "aac" | "mp3"),
+    }
+}
+
+/// 音频编码的 FFmpeg 参数
+pub fn ffmpeg_args(codec: AudioCodec, args: &Args) -> Vec<String> {
+    let bitrate = |default: &str| {
+        if args.audio_bitrate > 0 {
+            format!("{}k", args.audio_bitrate)
+        } else {
+            default.to_string()
+        }
+    };
+    match codec {
+        AudioCodec::Copy => vec!["-c:a".into(), "copy".into()],
+        AudioCodec::Auto | AudioCodec::Aac => {
+            vec![
+                "-c:a".into(),
+                "aac".into(),
+                "-b:a".into(),
+                bitrate(AAC_BITRATE),
+            ]
+        }
+        AudioCodec::Opus => vec![
+            "-c:a".into(),
+            "libopus".into(),
+            "-b:a".into(),
+            bitrate(OPUS_BITRATE),
+        ],
+        AudioCodec::Flac => vec!["-c:a".into(), "flac".into()],
+    }
+}
@@ -0,0 +1,61 @@
+use crate::i18n::tr;
+use anyhow::{Context, Result, bail};
+use std::path::Path;
+use std::str::FromStr;
+use tokio::fs;
+use tokio::process::Command;
+use tracing::info;
+
+/// `--cenc-key` 的一对 KID 与内容密钥 (均为 16 字节十六进制)
+#[derive(Clone, Debug)]
+pub struct CencKey {
+    kid: String,
+    key: String,
+}
+
+impl FromStr for CencKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            tr!(
+                "无效的 CENC 密钥: {} (格式为 KID:KEY，均为 32 位十六进制)",
+                s
+            )
+        };
+        let (kid, key) = s.trim().split_once(':').ok_or_else(invalid)?;
+        // KID 常以 UUID 形式给出
+        let kid = kid.replace('-', "").to_ascii_lowercase();
+        let key = key.trim().to_ascii_lowercase();
+        let valid = |v: &str| v.len() == 32 && v.chars().all(|c| c.is_ascii_hexdigit());
+        if !valid(&kid) || !valid(&key) {
+            return Err(invalid());
+        }
+        Ok(Self { kid, key })
+    }
+}
+
+/// 用 Bento4 的 mp4decrypt 原地解密合并后的 fMP4 文件 (cenc / cbcs)
+pub async fn decrypt(path: &Path, keys: &[CencKey]) -> Result<()> {
+    let output = path.with_extension("decrypted");
+    let mut command = Command::new("mp4decrypt");
+    for key in keys {
+        command.arg("--key").arg(format!("{}:{}", key.kid, key.key));
+    }
+    let result = command
+        .arg(path)
+        .arg(&output)
+        .output()
+        .await
+        .context(tr!("未找到 mp4decrypt，请安装 Bento4 并添加到 PATH"))?;
+    if !result.status.success() {
+        let _ = fs::remove_file(&output).await;
+        bail!(tr!(
+            "mp4decrypt 解密失败: {}",
+            String::from_utf8_lossy(&result.stderr).trim()
+        ));
+    }
+    fs::rename(&output, path).await?;
+    info!("{}", tr!("已用 CENC 密钥解密: {}", path.display()));
+    Ok(())
+}
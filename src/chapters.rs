@@ -0,0 +1,131 @@
+use crate::i18n::tr;
+use anyhow::Result;
+use m3u8_rs::{DateRange, MediaSegment, QuotedOrUnquoted};
+use std::fmt::Write;
+use std::path::Path;
+
+/// 与上一章节起点相距不足这么多秒时合并为同一章节
+const MIN_GAP: f64 = 1.0;
+
+/// 输出中的一个章节
+#[derive(Clone, Debug)]
+pub struct Chapter {
+    /// 章节在输出中的起点 (秒)
+    pub start: f64,
+    pub title: String,
+}
+
+/// 按切片顺序收集章节：EXT-X-DISCONTINUITY 处开始新章节，
+/// EXT-X-DATERANGE 按其 START-DATE 换算到输出时间并尽量使用其中的标题
+#[derive(Default)]
+pub struct ChapterBuilder {
+    chapters: Vec<Chapter>,
+    elapsed: f64,
+    /// 最近一个 EXT-X-PROGRAM-DATE-TIME (毫秒时间戳) 及其在输出中的位置
+    anchor: Option<(i64, f64)>,
+    parts: usize,
+}
+
+impl ChapterBuilder {
+    /// 依次加入写入输出的切片
+    pub fn push(&mut self, seg: &MediaSegment) {
+        if let Some(pdt) = &seg.program_date_time {
+            self.anchor = Some((pdt.timestamp_millis(), self.elapsed));
+        }
+        if seg.discontinuity && self.elapsed > 0.0 {
+            self.parts += 1;
+            self.add(self.elapsed, tr!("片段 {}", self.parts + 1), false);
+        }
+        if let Some(range) = &seg.daterange {
+            let start = match self.anchor {
+                Some((millis, at)) => {
+                    let offset = (range.start_date.timestamp_millis() - millis) as f64 / 1000.0;
+                    (at + offset).max(0.0)
+                }
+                None => self.elapsed,
+            };
+            self.add(start, title(range), true);
+        }
+        self.elapsed += f64::from(seg.duration);
+    }
+
+    fn add(&mut self, start: f64, title: String, named: bool) {
+        if let Some(last) = self
+            .chapters
+            .iter_mut()
+            .find(|c| (c.start - start).abs() < MIN_GAP)
+        {
+            // 同一位置既是不连续点又有 DATERANGE 时使用 DATERANGE 的标题
+            if named {
+                last.title = title;
+            }
+            return;
+        }
+        self.chapters.push(Chapter { start, title });
+    }
+
+    /// 没有任何分界时返回空列表；否则补上从 0 开始的第一章，并按起点排序
+    pub fn finish(mut self) -> Vec<Chapter> {
+        if self.chapters.is_empty() {
+            return Vec::new();
+        }
+        self.chapters
+            .retain(|c| c.start < self.elapsed || self.elapsed == 0.0);
+        self.chapters.sort_by(|a, b| a.start.total_cmp(&b.start));
+        if self.chapters.first().is_none_or(|c| c.start >= MIN_GAP) {
+            self.chapters.insert(
+                0,
+                Chapter {
+                    start: 0.0,
+                    title: tr!("片段 {}", 1),
+                },
+            );
+        }
+        self.chapters
+    }
+}
+
+/// DATERANGE 的标题：X-TITLE 等自定义属性优先，其次是 CLASS 与 ID
+fn title(range: &DateRange) -> String {
+    let named = range
+        .x_prefixed
+        .iter()
+        .chain(range.other_attributes.iter())
+        .flatten()
+        .find(|(key, _)| key.to_ascii_uppercase().contains("TITLE"))
+        .map(|(_, value)| match value {
+            QuotedOrUnquoted::Quoted(s) | QuotedOrUnquoted::Unquoted(s) => s.clone(),
+        });
+    named
+        .or_else(|| range.class.clone())
+        .unwrap_or_else(|| range.id.clone())
+}
+
+/// 写入 FFmpeg 元数据文件 (FFMETADATA1)，转码时作为额外输入提供章节
+pub async fn write_metadata(path: &Path, chapters: &[Chapter], duration: f64) -> Result<()> {
+    let mut text = String::from(";FFMETADATA1\n");
+    for (i, chapter) in chapters.iter().enumerate() {
+        let end = chapters.get(i + 1).map_or(duration, |next| next.start);
+        let _ = write!(
+            text,
+            "[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n",
+            (chapter.start * 1000.0) as u64,
+            (end.max(chapter.start) * 1000.0) as u64,
+            escape(&chapter.title)
+        );
+    }
+    tokio::fs::write(path, text).await?;
+    Ok(())
+}
+
+/// FFMETADATA 中 `=`、`;`、`#`、`\` 与换行需要转义
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
@@ -0,0 +1,466 @@
+use anyhow::{Result, bail};
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime};
+use m3u8_rs::MediaPlaylist;
+use std::ops::Range;
+use std::str::FromStr;
+use tracing::{info, warn};
+
+use crate::i18n::tr;
+use crate::watchdog;
+
+/// 裁剪后的输出在合并文件中的位置，由 FFmpeg 在转码时精确裁掉首尾切片多余的部分
+#[derive(Clone, Debug)]
+pub struct Clip {
+    /// 从合并文件开头跳过的秒数
+    pub start: f64,
+    /// 保留的秒数，None 表示到结尾
+    pub length: Option<f64>,
+}
+
+/// `--segments` 的切片下标范围 (从 0 开始，含两端)，两端都可以省略
+#[derive(Clone, Debug)]
+pub struct SegmentRange {
+    first: Option<usize>,
+    last: Option<usize>,
+}
+
+impl FromStr for SegmentRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || tr!("无效的切片范围: {} (例如 120-450、120-、-450)", s);
+        let (first, last) = s.trim().split_once('-').ok_or_else(invalid)?;
+        let parse = |n: &str| {
+            let n = n.trim();
+            if n.is_empty() {
+                Ok(None)
+            } else {
+                n.parse().map(Some).map_err(|_| invalid())
+            }
+        };
+        let range = Self {
+            first: parse(first)?,
+            last: parse(last)?,
+        };
+        if range
+            .first
+            .zip(range.last)
+            .is_some_and(|(first, last)| last < first)
+        {
+            return Err(invalid());
+        }
+        Ok(range)
+    }
+}
+
+impl SegmentRange {
+    /// 换算为共 `total` 个切片的播放列表中的下标区间
+    pub fn resolve(&self, total: usize) -> Result<Range<usize>> {
+        let first = self.first.unwrap_or(0);
+        if first >= total {
+            bail!(tr!(
+                "切片范围从 {} 开始，但播放列表只有 {} 个切片",
+                first,
+                total
+            ));
+        }
+        let end = self.last.map_or(total, |last| (last + 1).min(total));
+        Ok(first..end)
+    }
+}
+
+/// 解析时间偏移：`1:02:03.5`、`02:03`、`90`、`90.5` 或 `1h30m`
+pub fn parse_offset(text: &str) -> Result<f64, String> {
+    let text = text.trim();
+    if text.contains(':') {
+        let mut secs = 0.0;
+        for part in text.split(':') {
+            let value: f64 = part
+                .parse()
+                .map_err(|_| tr!("无效的时间: {} (例如 1:02:03、90、1h30m)", text))?;
+            secs = secs * 60.0 + value;
+        }
+        return Ok(secs);
+    }
+    if let Ok(secs) = text.parse::<f64>() {
+        return Ok(secs);
+    }
+    watchdog::parse_runtime(text).map(|d| d.as_secs_f64())
+}
+
+/// 解析 `--from` / `--to` 的绝对时间：`2024-05-01T20:00:00Z` (RFC 3339) 或本地时间 `2024-05-01 20:00[:00]`
+pub fn parse_wall_clock(text: &str) -> Result<DateTime<FixedOffset>, String> {
+    let text = text.trim();
+    if let Ok(at) = DateTime::parse_from_rfc3339(text) {
+        return Ok(at);
+    }
+    for format in ["%Y-%m-%d %H:%M", "%Y-%m-%d %H:%M:%S"] {
+        if let Some(at) = NaiveDateTime::parse_from_str(text, format)
+            .ok()
+            .and_then(|at| at.and_local_timezone(Local).earliest())
+        {
+            return Ok(at.fixed_offset());
+        }
+    }
+    Err(tr!(
+        "无效的时间: {} (例如 2024-05-01T20:00:00Z、2024-05-01 20:00)",
+        text
+    ))
+}
+
+/// 按 EXT-X-PROGRAM-DATE-TIME 把 `--from` / `--to` 的绝对时间换算为播放列表中的时间偏移 (秒)。
+/// 没有 PDT 的切片从前一个 PDT 按时长推算，第一个 PDT 之前的切片倒推；
+/// 早于回看窗口开头的 `--from` 从开头下载，晚于结尾的 `--to` 下载到结尾
+pub fn wall_clock(
+    playlist: &MediaPlaylist,
+    from: Option<DateTime<FixedOffset>>,
+    to: Option<DateTime<FixedOffset>>,
+) -> Result<(Option<f64>, Option<f64>)> {
+    if let (Some(from), Some(to)) = (from, to)
+        && to <= from
+    {
+        bail!(tr!("--to 必须晚于 --from"));
+    }
+    let segments = &playlist.segments;
+    let Some(first) = segments.iter().position(|s| s.program_date_time.is_some()) else {
+        bail!(tr!(
+            "播放列表没有 EXT-X-PROGRAM-DATE-TIME，无法按绝对时间裁剪"
+        ));
+    };
+    let before: f64 = segments[..first]
+        .iter()
+        .map(|s| f64::from(s.duration))
+        .sum();
+    let mut at = segments[first]
+        .program_date_time
+        .map_or(0, |pdt| pdt.timestamp_millis())
+        - (before * 1000.0) as i64;
+    // 每个切片的 (开始时刻毫秒数, 在播放列表中的偏移秒数, 时长秒数)
+    let mut times = Vec::with_capacity(segments.len());
+    let mut elapsed = 0.0;
+    for seg in segments {
+        if let Some(pdt) = &seg.program_date_time {
+            at = pdt.timestamp_millis();
+        }
+        let duration = f64::from(seg.duration);
+        times.push((at, elapsed, duration));
+        at += (duration * 1000.0) as i64;
+        elapsed += duration;
+    }
+    let window_start = times.first().map_or(0, |t| t.0);
+    let window_end = at;
+    let offset = |wall: DateTime<FixedOffset>| -> Option<f64> {
+        let millis = wall.timestamp_millis();
+        times
+            .iter()
+            .rev()
+            .find(|(start, _, _)| *start <= millis)
+            .map(|(start, offset, duration)| {
+                offset + ((millis - start) as f64 / 1000.0).min(*duration)
+            })
+    };
+    let format = |millis: i64| {
+        DateTime::from_timestamp_millis(millis)
+            .map(|t| {
+                t.with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string()
+            })
+            .unwrap_or_default()
+    };
+
+    let start = match from {
+        Some(from) if from.timestamp_millis() >= window_end => bail!(tr!(
+            "--from {} 晚于播放列表的结尾 ({})，节目还没有开始或回看窗口尚未更新",
+            from,
+            format(window_end)
+        )),
+        Some(from) if from.timestamp_millis() < window_start => {
+            warn!(
+                "{}",
+                tr!(
+                    "--from {} 早于回看窗口的开头 ({})，从开头下载",
+                    from,
+                    format(window_start)
+                )
+            );
+            None
+        }
+        Some(from) => offset(from),
+        None => None,
+    };
+    let end = match to {
+        Some(to) if to.timestamp_millis() <= window_start => bail!(tr!(
+            "--to {} 早于回看窗口的开头 ({})，节目已经移出回看窗口",
+            to,
+            format(window_start)
+        )),
+        Some(to) if to.timestamp_millis() > window_end => {
+            warn!(
+                "{}",
+                tr!(
+                    "--to {} 晚于播放列表的结尾 ({})，下载到结尾",
+                    to,
+                    format(window_end)
+                )
+            );
+            None
+        }
+        Some(to) => offset(to),
+        None => None,
+    };
+    info!(
+        "{}",
+        tr!(
+            "按绝对时间裁剪: 播放列表中的 {:.1}s - {}",
+            start.unwrap_or(0.0),
+            end.map_or(tr!("结尾").to_string(), |end| format!("{:.1}s", end))
+        )
+    );
+    Ok((start, end))
+}
+
+/// 只保留 `range` 内的切片。删掉的开头切片计入媒体序列号，
+/// 其中最后生效的密钥与初始化段补到第一个保留的切片上，解密所用的 IV 不变
+pub fn trim(playlist: &mut MediaPlaylist, range: Range<usize>) {
+    let mut key = None;
+    let mut map = None;
+    for seg in &playlist.segments[..range.start] {
+        if seg.key.is_some() {
+            key = seg.key.clone();
+        }
+        if seg.map.is_some() {
+            map = seg.map.clone();
+        }
+    }
+    playlist.segments.truncate(range.end);
+    playlist.segments.drain(..range.start);
+    playlist.media_sequence += range.start as u64;
+    if let Some(first) = playlist.segments.first_mut() {
+        if first.key.is_none() {
+            first.key = key;
+        }
+        if first.map.is_none() {
+            first.map = map;
+        }
+    }
+}
+
+/// 按 EXTINF 时长找出与 [start, end) 重叠的切片并删掉其余切片，返回转码时需要的精确裁剪
+pub fn clip(playlist: &mut MediaPlaylist, start: Option<f64>, end: Option<f64>) -> Result<Clip> {
+    let start = start.unwrap_or(0.0);
+    let total: f64 = playlist
+        .segments
+        .iter()
+        .map(|s| f64::from(s.duration))
+        .sum();
+    if end.is_some_and(|end| end <= start) {
+        bail!(tr!("--end-time 必须晚于 --start-time"));
+    }
+    if start >= total {
+        bail!(tr!(
+            "--start-time {:.1}s 超出播放列表时长 ({:.1}s)",
+            start,
+            total
+        ));
+    }
+
+    let mut first = None;
+    let mut last = 0;
+    let mut first_at = 0.0;
+    let mut elapsed = 0.0;
+    for (i, seg) in playlist.segments.iter().enumerate() {
+        let next = elapsed + f64::from(seg.duration);
+        if first.is_none() && next > start {
+            first = Some(i);
+            first_at = elapsed;
+        }
+        if end.is_none_or(|end| elapsed < end) {
+            last = i;
+        }
+        elapsed = next;
+    }
+    let first = first.unwrap_or(0);
+    info!(
+        "{}",
+        tr!(
+            "裁剪 {:.1}s - {}: 下载第 {} - {} 个切片",
+            start,
+            end.map_or(tr!("结尾").to_string(), |end| format!("{:.1}s", end)),
+            first,
+            last
+        )
+    );
+    trim(playlist, first..last + 1);
+    Ok(Clip {
+        start: start - first_at,
+        length: end.map(|end| end.min(total) - start),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use m3u8_rs::{Key, KeyMethod, MediaSegment};
+
+    fn at(text: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(text).unwrap()
+    }
+
+    /// 每个切片 10 秒，`pdts` 给出各切片的 PROGRAM-DATE-TIME
+    fn playlist(pdts: &[Option<&str>]) -> MediaPlaylist {
+        MediaPlaylist {
+            segments: pdts
+                .iter()
+                .enumerate()
+                .map(|(i, pdt)| MediaSegment {
+                    uri: format!("seg{}.ts", i),
+                    duration: 10.0,
+                    program_date_time: pdt.map(at),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn segment_range_parses_open_ends() {
+        let range: SegmentRange = "120-".parse().unwrap();
+        assert_eq!(range.resolve(200).unwrap(), 120..200);
+        let range: SegmentRange = "-450".parse().unwrap();
+        assert_eq!(range.resolve(100).unwrap(), 0..100);
+        let range: SegmentRange = " 3 - 5 ".parse().unwrap();
+        assert_eq!(range.resolve(10).unwrap(), 3..6);
+        assert!(
+            "300-"
+                .parse::<SegmentRange>()
+                .unwrap()
+                .resolve(100)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn segment_range_rejects_end_before_first() {
+        assert!("450-120".parse::<SegmentRange>().is_err());
+        assert!("12".parse::<SegmentRange>().is_err());
+        assert!("a-b".parse::<SegmentRange>().is_err());
+    }
+
+    #[test]
+    fn parse_offset_accepts_clock_seconds_and_units() {
+        assert_eq!(parse_offset("1:02:03.5"), Ok(3723.5));
+        assert_eq!(parse_offset("02:03"), Ok(123.0));
+        assert_eq!(parse_offset("90.5"), Ok(90.5));
+        assert_eq!(parse_offset("1h30m"), Ok(5400.0));
+        assert!(parse_offset("1:xx").is_err());
+        assert!(parse_offset("soon").is_err());
+    }
+
+    #[test]
+    fn parse_wall_clock_accepts_rfc3339() {
+        assert_eq!(
+            parse_wall_clock("2024-05-01T20:00:00+08:00"),
+            Ok(at("2024-05-01T20:00:00+08:00"))
+        );
+        assert!(parse_wall_clock("2024-05-01 20:00").is_ok());
+        assert!(parse_wall_clock("2024-05-01 20:00:30").is_ok());
+        assert!(parse_wall_clock("yesterday").is_err());
+    }
+
+    #[test]
+    fn wall_clock_extrapolates_segments_without_pdt() {
+        // 第一个切片没有 PDT，从第二个切片倒推；第三个切片之后时间连续
+        let list = playlist(&[None, Some("2024-05-01T20:00:10Z"), None, None]);
+        let (start, end) = wall_clock(
+            &list,
+            Some(at("2024-05-01T20:00:05Z")),
+            Some(at("2024-05-01T20:00:25Z")),
+        )
+        .unwrap();
+        assert_eq!(start, Some(5.0));
+        assert_eq!(end, Some(25.0));
+    }
+
+    #[test]
+    fn wall_clock_clamps_into_gaps_and_window() {
+        // 第二、三个切片之间时间不连续，落在空档里的时刻取前一个切片的结尾
+        let list = playlist(&[
+            Some("2024-05-01T20:00:00Z"),
+            Some("2024-05-01T20:00:10Z"),
+            Some("2024-05-01T20:01:00Z"),
+        ]);
+        let (start, end) = wall_clock(
+            &list,
+            Some(at("2024-05-01T20:00:30Z")),
+            Some(at("2024-05-01T20:01:05Z")),
+        )
+        .unwrap();
+        assert_eq!(start, Some(20.0));
+        assert_eq!(end, Some(25.0));
+
+        // 早于窗口开头的 --from、晚于结尾的 --to 不裁剪
+        let (start, end) = wall_clock(
+            &list,
+            Some(at("2024-05-01T19:00:00Z")),
+            Some(at("2024-05-01T21:00:00Z")),
+        )
+        .unwrap();
+        assert_eq!((start, end), (None, None));
+    }
+
+    #[test]
+    fn wall_clock_rejects_out_of_window_and_missing_pdt() {
+        let list = playlist(&[Some("2024-05-01T20:00:00Z"), None]);
+        assert!(wall_clock(&list, Some(at("2024-05-01T20:00:20Z")), None).is_err());
+        assert!(wall_clock(&list, None, Some(at("2024-05-01T20:00:00Z"))).is_err());
+        assert!(
+            wall_clock(
+                &list,
+                Some(at("2024-05-01T20:00:10Z")),
+                Some(at("2024-05-01T20:00:05Z")),
+            )
+            .is_err()
+        );
+        assert!(wall_clock(&playlist(&[None, None]), None, None).is_err());
+    }
+
+    #[test]
+    fn clip_keeps_overlapping_segments() {
+        let mut list = playlist(&[None; 5]);
+        let clip = clip(&mut list, Some(15.0), Some(32.0)).unwrap();
+        let uris: Vec<&str> = list.segments.iter().map(|s| s.uri.as_str()).collect();
+        assert_eq!(uris, ["seg1.ts", "seg2.ts", "seg3.ts"]);
+        assert_eq!(list.media_sequence, 1);
+        assert_eq!(clip.start, 5.0);
+        assert_eq!(clip.length, Some(17.0));
+
+        let mut list = playlist(&[None; 2]);
+        assert!(super::clip(&mut list, Some(20.0), None).is_err());
+        assert!(super::clip(&mut list, Some(5.0), Some(5.0)).is_err());
+    }
+
+    #[test]
+    fn trim_carries_the_last_key_forward() {
+        let mut list = playlist(&[None; 4]);
+        list.segments[0].key = Some(Key {
+            method: KeyMethod::AES128,
+            uri: Some("old.key".into()),
+            ..Default::default()
+        });
+        list.segments[1].key = Some(Key {
+            method: KeyMethod::AES128,
+            uri: Some("new.key".into()),
+            ..Default::default()
+        });
+        trim(&mut list, 2..4);
+        assert_eq!(list.segments.len(), 2);
+        assert_eq!(list.media_sequence, 2);
+        assert_eq!(
+            list.segments[0].key.as_ref().and_then(|k| k.uri.as_deref()),
+            Some("new.key")
+        );
+        assert!(list.segments[1].key.is_none());
+    }
+}
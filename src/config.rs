@@ -0,0 +1,102 @@
+use crate::i18n::tr;
+use anyhow::{Context, Result, bail};
+use clap::Command;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+/// 默认配置文件：`$XDG_CONFIG_HOME/m3u8-downloader/config.toml`，
+/// 未设置时为 `~/.config/m3u8-downloader/config.toml` (Windows 下为 `%APPDATA%`)
+pub fn default_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            if cfg!(windows) {
+                std::env::var_os("APPDATA").map(PathBuf::from)
+            } else {
+                std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config"))
+            }
+        })?;
+    Some(base.join("m3u8-downloader").join("config.toml"))
+}
+
+/// 读取配置文件，把顶层默认值与所选 profile 合并后展开为命令行参数。
+/// 返回的参数应放在用户参数之前，命令行上显式给出的值优先
+pub fn load(path: Option<&Path>, profile: Option<&str>, cmd: &Command) -> Result<Vec<OsString>> {
+    let (path, explicit) = match path {
+        Some(path) => (path.to_path_buf(), true),
+        None => match default_path() {
+            Some(path) => (path, false),
+            None => return Ok(Vec::new()),
+        },
+    };
+    if !explicit && !path.exists() {
+        if let Some(name) = profile {
+            bail!(tr!(
+                "未找到配置文件 {}，无法使用 profile: {}",
+                path.display(),
+                name
+            ));
+        }
+        return Ok(Vec::new());
+    }
+
+    let content = std::fs::read_to_string(&path)
+        .with_context(|| tr!("无法读取配置文件: {}", path.display()))?;
+    let mut table: Table =
+        toml::from_str(&content).with_context(|| tr!("配置文件格式错误: {}", path.display()))?;
+
+    let profiles = match table.remove("profiles") {
+        Some(Value::Table(profiles)) => profiles,
+        Some(_) => bail!(tr!("配置文件中 profiles 必须是表")),
+        None => Table::new(),
+    };
+    if let Some(name) = profile {
+        match profiles.get(name) {
+            Some(Value::Table(overrides)) => table.extend(overrides.clone()),
+            Some(_) => bail!(tr!("profile {} 必须是表", name)),
+            None => bail!(tr!(
+                "配置文件中没有 profile: {} (可用: {})",
+                name,
+                profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+            )),
+        }
+    }
+
+    let mut argv = Vec::new();
+    for (key, value) in &table {
+        let long = key.replace('_', "-");
+        let arg = cmd
+            .get_arguments()
+            .find(|a| a.get_long() == Some(long.as_str()))
+            .with_context(|| tr!("配置文件中未知的选项: {}", key))?;
+        if matches!(long.as_str(), "config" | "profile") {
+            bail!(tr!("配置文件中不能设置 {}", key));
+        }
+
+        let flag = OsString::from(format!("--{}", long));
+        if !arg.get_action().takes_values() {
+            match value {
+                Value::Boolean(true) => argv.push(flag),
+                Value::Boolean(false) => {}
+                _ => bail!(tr!("配置项 {} 应为 true 或 false", key)),
+            }
+            continue;
+        }
+
+        let values = match value {
+            Value::Array(items) => items.iter().collect(),
+            other => vec![other],
+        };
+        for value in values {
+            argv.push(flag.clone());
+            argv.push(match value {
+                Value::String(s) => s.into(),
+                Value::Integer(_) | Value::Float(_) | Value::Boolean(_) => value.to_string().into(),
+                _ => bail!(tr!("配置项 {} 的值类型不受支持", key)),
+            });
+        }
+    }
+    Ok(argv)
+}
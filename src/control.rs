@@ -0,0 +1,201 @@
+use crate::i18n::tr;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::BufRead;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+/// 任务的运行状态，数值越大优先级越高 (全局与单个任务的状态取较大者)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum State {
+    Running,
+    Paused,
+    Cancelled,
+}
+
+/// 任务已被取消 (键盘、信号或 REST 接口)
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", tr!("任务已取消"))
+    }
+}
+
+/// 错误链中是否包含 [`Cancelled`]
+pub fn is_cancelled(e: &anyhow::Error) -> bool {
+    e.is::<Cancelled>()
+}
+
+/// 暂停、恢复与取消：全局状态对所有任务生效，也可以单独控制某个任务 (守护进程模式)。
+/// 切片下载在每次请求之前检查状态，正在进行的请求不会被打断
+pub struct Control {
+    global: watch::Sender<State>,
+    jobs: Mutex<HashMap<usize, watch::Sender<State>>>,
+}
+
+impl Default for Control {
+    fn default() -> Self {
+        Self {
+            global: watch::channel(State::Running).0,
+            jobs: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl Control {
+    /// 设置状态，`job` 为 None 时作用于全局
+    pub fn set(&self, job: Option<usize>, state: State) {
+        match job {
+            None => {
+                self.global.send_replace(state);
+            }
+            Some(job) => {
+                let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+                jobs.entry(job)
+                    .or_insert_with(|| watch::channel(State::Running).0)
+                    .send_replace(state);
+            }
+        }
+    }
+
+    /// 任务当前生效的状态
+    pub fn state(&self, job: usize) -> State {
+        let (global, own) = self.watch(job);
+        (*global.borrow()).max(*own.borrow())
+    }
+
+    /// 任务结束后释放它的状态
+    pub fn forget(&self, job: usize) {
+        self.jobs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&job);
+    }
+
+    fn watch(&self, job: usize) -> (watch::Receiver<State>, watch::Receiver<State>) {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        let own = jobs
+            .entry(job)
+            .or_insert_with(|| watch::channel(State::Running).0)
+            .subscribe();
+        (self.global.subscribe(), own)
+    }
+
+    /// 发出下一个请求之前调用：暂停时等待恢复，已取消时返回 [`Cancelled`]
+    pub async fn checkpoint(&self, job: usize) -> Result<()> {
+        let (mut global, mut own) = self.watch(job);
+        loop {
+            match (*global.borrow_and_update()).max(*own.borrow_and_update()) {
+                State::Running => return Ok(()),
+                State::Cancelled => return Err(anyhow::anyhow!(Cancelled)),
+                State::Paused => {}
+            }
+            tokio::select! {
+                changed = global.changed() => changed?,
+                changed = own.changed() => changed?,
+            }
+        }
+    }
+
+    /// 任务被取消时返回，用于结束直播录制
+    pub async fn cancelled(&self, job: usize) {
+        let (mut global, mut own) = self.watch(job);
+        loop {
+            if (*global.borrow_and_update()).max(*own.borrow_and_update()) == State::Cancelled {
+                return;
+            }
+            let changed = tokio::select! {
+                changed = global.changed() => changed,
+                changed = own.changed() => changed,
+            };
+            if changed.is_err() {
+                return std::future::pending().await;
+            }
+        }
+    }
+}
+
+/// SIGUSR1 暂停、SIGUSR2 恢复所有任务
+#[cfg(unix)]
+pub fn spawn_signals(control: Arc<Control>) -> Result<()> {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut pause = signal(SignalKind::user_defined1())?;
+    let mut resume = signal(SignalKind::user_defined2())?;
+    tokio::spawn(async move {
+        loop {
+            let state = tokio::select! {
+                _ = pause.recv() => State::Paused,
+                _ = resume.recv() => State::Running,
+            };
+            announce(state);
+            control.set(None, state);
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn spawn_signals(_control: Arc<Control>) -> Result<()> {
+    Ok(())
+}
+
+/// 键盘输入的其余行 (例如选择候选地址时的输入)，启用键盘控制后由 [`read_line`] 读取
+static INPUT: OnceLock<Mutex<Receiver<String>>> = OnceLock::new();
+
+/// 交互模式下从终端读取命令：`p` 暂停/恢复，`q` 取消所有任务，均需回车确认。
+/// 使用独立线程读取，进程退出时不会等待输入
+pub fn spawn_keyboard(control: Arc<Control>) {
+    let (tx, rx) = mpsc::channel();
+    if INPUT.set(Mutex::new(rx)).is_err() {
+        return;
+    }
+    info!("{}", tr!("输入 p 回车暂停/恢复，输入 q 回车取消"));
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            let state = match line.trim() {
+                "p" | "P" if *control.global.borrow() == State::Paused => State::Running,
+                "p" | "P" => State::Paused,
+                "q" | "Q" => State::Cancelled,
+                _ => {
+                    let _ = tx.send(line);
+                    continue;
+                }
+            };
+            announce(state);
+            control.set(None, state);
+        }
+    });
+}
+
+/// 读取一行终端输入；启用键盘控制时从控制线程转发的输入中读取
+pub fn read_line() -> std::io::Result<String> {
+    match INPUT.get() {
+        Some(rx) => rx
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .recv()
+            .map_err(|_| std::io::ErrorKind::UnexpectedEof.into()),
+        None => {
+            let mut line = String::new();
+            std::io::stdin().read_line(&mut line)?;
+            Ok(line)
+        }
+    }
+}
+
+fn announce(state: State) {
+    match state {
+        State::Running => info!("{}", tr!("已恢复下载")),
+        State::Paused => info!("{}", tr!("已暂停下载，正在进行的切片请求完成后停止")),
+        State::Cancelled => warn!("{}", tr!("已取消所有任务")),
+    }
+}
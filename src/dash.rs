@@ -0,0 +1,604 @@
+use anyhow::{Context, Result, bail};
+use futures::future;
+use m3u8_rs::{MediaPlaylist, MediaSegment};
+use roxmltree::{Document, Node};
+use std::path::PathBuf;
+use tracing::{info, warn};
+use url::Url;
+
+use crate::events::JobEvent;
+use crate::exit::Exit;
+use crate::i18n::tr;
+use crate::recovery::Overrides;
+use crate::subtitles::BurnSubs;
+use crate::ui::Ui;
+use crate::{
+    Args, Job, MediaSource, Shared, Timing, convert_to_mp4, download_and_merge, manifest,
+    remove_merged, sequences,
+};
+
+/// 内容是否为 DASH MPD 清单
+pub fn is_mpd(url: &str, content: &[u8]) -> bool {
+    let head = String::from_utf8_lossy(&content[..content.len().min(1024)]);
+    url.split('?').next().unwrap_or_default().ends_with(".mpd") || head.contains("<MPD")
+}
+
+/// 媒体分段的 (URL, 时长秒)
+type Segments = Vec<(Url, f64)>;
+
+/// 选中的一路表示 (Representation)：初始化分段 + 媒体分段
+struct Track {
+    kind: &'static str,
+    bandwidth: u64,
+    init: Option<Url>,
+    segments: Segments,
+}
+
+impl Track {
+    /// 转成与 HLS 相同的切片列表，初始化分段放在最前面
+    fn into_playlist(self) -> MediaPlaylist {
+        let mut segments = Vec::with_capacity(self.segments.len() + 1);
+        if let Some(init) = self.init {
+            segments.push(MediaSegment {
+                uri: init.to_string(),
+                ..Default::default()
+            });
+        }
+        segments.extend(
+            self.segments
+                .into_iter()
+                .map(|(url, duration)| MediaSegment {
+                    uri: url.to_string(),
+                    duration: duration as f32,
+                    ..Default::default()
+                }),
+        );
+        MediaPlaylist {
+            segments,
+            end_list: true,
+            ..Default::default()
+        }
+    }
+}
+
+/// 下载 MPD 中码率最高的视频与音频表示，分别合并后一起交给 FFmpeg 转码
+pub async fn process(
+    content: &[u8],
+    url: &str,
+    job: &Job,
+    args: &Args,
+    shared: &Shared,
+    ui: &Ui,
+    overrides: &Overrides,
+) -> Result<()> {
+    let mpd_url = Url::parse(url).context(tr!("DASH 清单需要网络 URL"))?;
+    let text = std::str::from_utf8(content).context(tr!("MPD 不是有效的 UTF-8 文本"))?;
+    let tracks = parse(text, &mpd_url, !args.cenc_key.is_empty())?;
+    for track in &tracks {
+        info!(
+            "{}",
+            tr!(
+                "DASH {} 表示: 带宽 {} bps, {} 个分段",
+                track.kind,
+                track.bandwidth,
+                track.segments.len()
+            )
+        );
+    }
+
+    let duration: f64 = tracks[0].segments.iter().map(|(_, d)| d).sum();
+    shared.events.emit(JobEvent::PlaylistParsed {
+        job: job.id,
+        segments: tracks.iter().map(|t| t.segments.len()).sum(),
+        duration,
+    });
+
+    let subtitles = match args.burn_subs.as_deref().map(BurnSubs::parse) {
+        Some(BurnSubs::File(path)) => Some(path),
+        Some(BurnSubs::Rendition(_)) => bail!(tr!("DASH 的 --burn-subs 只支持本地字幕文件")),
+        None => None,
+    };
+    let parts: Vec<Job> = tracks
+        .iter()
+        .map(|t| Job {
+            id: job.id,
+            url: url.to_string(),
+            output: job.output.with_extension(format!("{}.mp4", t.kind)),
+        })
+        .collect();
+    future::try_join_all(tracks.into_iter().zip(&parts).map(|(track, part)| {
+        let playlist = track.into_playlist();
+        let media = MediaSource {
+            sequences: sequences(&playlist),
+            playlist,
+            base_url: None,
+            playlist_url: None,
+            fallbacks: Vec::new(),
+        };
+        download_and_merge(media, args, part, shared, ui)
+    }))
+    .await?;
+
+    let inputs: Vec<PathBuf> = parts.iter().map(Job::merged_input).collect();
+    let timing = Timing {
+        duration,
+        subtitles,
+        ..Timing::default()
+    };
+    convert_to_mp4(&inputs, job, &timing, args, overrides, &shared.events, ui).await?;
+    if let Err(e) = manifest::write(&job.output, url, &[]).await {
+        warn!("{}", tr!("生成清单失败: {:#}", e));
+    }
+
+    for part in &parts {
+        remove_merged(part).await;
+    }
+    Ok(())
+}
+
+fn child<'a, 'i>(node: Node<'a, 'i>, name: &str) -> Option<Node<'a, 'i>> {
+    node.children().find(|n| n.has_tag_name(name))
+}
+
+fn children<'a, 'i: 'a>(node: Node<'a, 'i>, name: &'a str) -> impl Iterator<Item = Node<'a, 'i>> {
+    node.children().filter(move |n| n.has_tag_name(name))
+}
+
+/// 按层级叠加 BaseURL
+fn with_base(base: &Url, node: Node) -> Result<Url> {
+    match child(node, "BaseURL").and_then(|n| n.text()) {
+        Some(href) => Ok(base.join(href.trim())?),
+        None => Ok(base.clone()),
+    }
+}
+
+fn parse(text: &str, mpd_url: &Url, cenc: bool) -> Result<Vec<Track>> {
+    let doc = Document::parse(text).context(tr!("解析 MPD 失败"))?;
+    let mpd = doc.root_element();
+    if mpd.attribute("type") == Some("dynamic") {
+        bail!(tr!("暂不支持直播 DASH (type=dynamic)"));
+    }
+    let base = with_base(mpd_url, mpd)?;
+
+    let periods: Vec<Node> = children(mpd, "Period").collect();
+    let period = *periods.first().context(tr!("MPD 中没有 Period"))?;
+    if periods.len() > 1 {
+        warn!(
+            "{}",
+            tr!("MPD 包含 {} 个 Period，只下载第一个", periods.len())
+        );
+    }
+    let period_duration = period
+        .attribute("duration")
+        .or_else(|| mpd.attribute("mediaPresentationDuration"))
+        .and_then(parse_duration);
+    let base = with_base(&base, period)?;
+
+    let mut best: Vec<(&'static str, Node, Node)> = Vec::new();
+    // 有 CENC 密钥时照常下载，合并后由 mp4decrypt 解密
+    let protected = !cenc;
+    for set in children(period, "AdaptationSet") {
+        if protected && child(set, "ContentProtection").is_some() {
+            return Err(drm_protected());
+        }
+        for rep in children(set, "Representation") {
+            let Some(kind) = content_kind(set, rep) else {
+                continue;
+            };
+            if protected && child(rep, "ContentProtection").is_some() {
+                return Err(drm_protected());
+            }
+            match best.iter_mut().find(|(k, _, _)| *k == kind) {
+                Some(slot) if bandwidth(rep) > bandwidth(slot.2) => *slot = (kind, set, rep),
+                Some(_) => {}
+                None => best.push((kind, set, rep)),
+            }
+        }
+    }
+    // 视频放在前面，作为转码的第一个输入
+    best.sort_by_key(|(kind, _, _)| *kind != "video");
+    if best.is_empty() {
+        bail!(tr!("MPD 中没有可用的视频或音频表示"));
+    }
+
+    best.into_iter()
+        .map(|(kind, set, rep)| {
+            let base = with_base(&with_base(&base, set)?, rep)?;
+            let (init, segments) = segment_list(set, rep, &base, period_duration)?;
+            Ok(Track {
+                kind,
+                bandwidth: bandwidth(rep),
+                init,
+                segments,
+            })
+        })
+        .collect()
+}
+
+fn content_kind(set: Node, rep: Node) -> Option<&'static str> {
+    let hint = [
+        set.attribute("contentType"),
+        rep.attribute("mimeType"),
+        set.attribute("mimeType"),
+    ];
+    hint.into_iter().flatten().find_map(|h| {
+        if h.starts_with("video") {
+            Some("video")
+        } else if h.starts_with("audio") {
+            Some("audio")
+        } else {
+            None
+        }
+    })
+}
+
+fn bandwidth(rep: Node) -> u64 {
+    rep.attribute("bandwidth")
+        .and_then(|b| b.parse().ok())
+        .unwrap_or(0)
+}
+
+/// 生成一路表示的初始化分段与媒体分段 (URL, 时长秒)
+fn segment_list(
+    set: Node,
+    rep: Node,
+    base: &Url,
+    period_duration: Option<f64>,
+) -> Result<(Option<Url>, Segments)> {
+    let rep_id = rep.attribute("id").unwrap_or_default();
+    let bw = bandwidth(rep);
+
+    // SegmentTemplate 可以写在 Representation 或 AdaptationSet 上，前者优先
+    let templates: Vec<Node> = [child(rep, "SegmentTemplate"), child(set, "SegmentTemplate")]
+        .into_iter()
+        .flatten()
+        .collect();
+    if !templates.is_empty() {
+        let attr = |name: &str| templates.iter().find_map(|t| t.attribute(name));
+        let media = attr("media").context(tr!("SegmentTemplate 缺少 media"))?;
+        let timescale: u64 = attr("timescale").and_then(|v| v.parse().ok()).unwrap_or(1);
+        let start_number: u64 = attr("startNumber")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(1);
+        let init = attr("initialization")
+            .map(|i| base.join(&expand(i, rep_id, bw, 0, 0)))
+            .transpose()?;
+
+        let mut list = Vec::new();
+        if let Some(timeline) = templates.iter().find_map(|t| child(*t, "SegmentTimeline")) {
+            let end = period_duration.map(|d| (d * timescale as f64) as u64);
+            let mut time = 0u64;
+            let mut number = start_number;
+            let entries: Vec<Node> = children(timeline, "S").collect();
+            for (i, s) in entries.iter().enumerate() {
+                if let Some(t) = s.attribute("t").and_then(|v| v.parse().ok()) {
+                    time = t;
+                }
+                let d: u64 = s
+                    .attribute("d")
+                    .and_then(|v| v.parse().ok())
+                    .context(tr!("SegmentTimeline 的 S 缺少 d"))?;
+                let r: i64 = s.attribute("r").and_then(|v| v.parse().ok()).unwrap_or(0);
+                // r=-1 表示重复到下一个 S 的起点或 Period 结束
+                let repeat = if r >= 0 {
+                    r as u64
+                } else {
+                    let until = entries
+                        .get(i + 1)
+                        .and_then(|n| n.attribute("t"))
+                        .and_then(|v| v.parse().ok())
+                        .or(end)
+                        .context(tr!("无法确定 SegmentTimeline 的重复次数"))?;
+                    until
+                        .saturating_sub(time)
+                        .div_ceil(d.max(1))
+                        .saturating_sub(1)
+                };
+                for _ in 0..=repeat {
+                    list.push((
+                        base.join(&expand(media, rep_id, bw, number, time))?,
+                        d as f64 / timescale as f64,
+                    ));
+                    time += d;
+                    number += 1;
+                }
+            }
+        } else {
+            let d: u64 = attr("duration").and_then(|v| v.parse().ok()).context(tr!(
+                "SegmentTemplate 既没有 SegmentTimeline 也没有 duration"
+            ))?;
+            let seg_secs = d as f64 / timescale as f64;
+            let total = period_duration.context(tr!("MPD 缺少时长，无法计算分段数量"))?;
+            let count = (total / seg_secs).ceil() as u64;
+            for i in 0..count {
+                list.push((
+                    base.join(&expand(media, rep_id, bw, start_number + i, i * d))?,
+                    seg_secs.min(total - i as f64 * seg_secs),
+                ));
+            }
+        }
+        return Ok((init, list));
+    }
+
+    if let Some(seg_list) = child(rep, "SegmentList").or_else(|| child(set, "SegmentList")) {
+        let timescale: f64 = seg_list
+            .attribute("timescale")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(1.0);
+        let seg_secs = seg_list
+            .attribute("duration")
+            .and_then(|v| v.parse::<f64>().ok())
+            .map(|d| d / timescale)
+            .unwrap_or(0.0);
+        let init = child(seg_list, "Initialization")
+            .and_then(|n| n.attribute("sourceURL"))
+            .map(|u| base.join(u))
+            .transpose()?;
+        let list = children(seg_list, "SegmentURL")
+            .filter_map(|n| n.attribute("media"))
+            .map(|m| Ok((base.join(m)?, seg_secs)))
+            .collect::<Result<_>>()?;
+        return Ok((init, list));
+    }
+
+    // SegmentBase 或没有分段信息：整个表示就是 BaseURL 指向的单个文件
+    Ok((None, vec![(base.clone(), period_duration.unwrap_or(0.0))]))
+}
+
+/// 展开 `$RepresentationID$` `$Bandwidth$` `$Number%05d$` `$Time$` 等模板标识符
+fn expand(template: &str, rep_id: &str, bandwidth: u64, number: u64, time: u64) -> String {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('$') else {
+            out.push_str(&rest[start..]);
+            return out;
+        };
+        let token = &after[..end];
+        let (name, format) = token.split_once('%').unwrap_or((token, ""));
+        let width: usize = format
+            .trim_start_matches('0')
+            .trim_end_matches('d')
+            .parse()
+            .unwrap_or(0);
+        match name {
+            "" => out.push('$'),
+            "RepresentationID" => out.push_str(rep_id),
+            "Bandwidth" => out.push_str(&format!("{:0width$}", bandwidth, width = width)),
+            "Number" => out.push_str(&format!("{:0width$}", number, width = width)),
+            "Time" => out.push_str(&format!("{:0width$}", time, width = width)),
+            _ => {
+                out.push('$');
+                out.push_str(token);
+                out.push('$');
+            }
+        }
+        rest = &after[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// 解析 ISO 8601 时长，例如 `PT1H2M3.5S`
+fn parse_duration(text: &str) -> Option<f64> {
+    let text = text.strip_prefix('P')?;
+    let mut total = 0.0;
+    let mut number = String::new();
+    let mut in_time = false;
+    for c in text.chars() {
+        match c {
+            'T' => in_time = true,
+            '0'..='9' | '.' => number.push(c),
+            _ => {
+                let value: f64 = number.parse().ok()?;
+                number.clear();
+                total += value
+                    * match (c, in_time) {
+                        ('D', false) => 86_400.0,
+                        ('H', true) => 3_600.0,
+                        ('M', true) => 60.0,
+                        ('S', true) => 1.0,
+                        _ => return None,
+                    };
+            }
+        }
+    }
+    Some(total)
+}
+
+/// 受 DRM 保护的 DASH 内容，退出码归入 DRM 类别
+fn drm_protected() -> anyhow::Error {
+    anyhow::anyhow!(tr!(
+        "DASH 内容受 DRM 保护 (ContentProtection)，无法下载；持有内容密钥时可用 --cenc-key 解密"
+    ))
+    .context(Exit::Drm)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mpd_url() -> Url {
+        Url::parse("https://cdn.example.com/vod/manifest.mpd").unwrap()
+    }
+
+    fn uris(track: &Track) -> Vec<&str> {
+        track.segments.iter().map(|(url, _)| url.as_str()).collect()
+    }
+
+    #[test]
+    fn expand_replaces_identifiers() {
+        assert_eq!(
+            expand("$RepresentationID$/seg_$Number%05d$.m4s", "v1", 0, 42, 0),
+            "v1/seg_00042.m4s"
+        );
+        assert_eq!(
+            expand("$Bandwidth$/$Time$.m4s", "", 800_000, 0, 90_000),
+            "800000/90000.m4s"
+        );
+        assert_eq!(expand("a$$b_$Unknown$", "", 0, 1, 0), "a$b_$Unknown$");
+        assert_eq!(expand("open_$Number", "", 0, 1, 0), "open_$Number");
+    }
+
+    #[test]
+    fn parse_duration_reads_iso8601() {
+        assert_eq!(parse_duration("PT1H2M3.5S"), Some(3723.5));
+        assert_eq!(parse_duration("P1DT1S"), Some(86_401.0));
+        assert_eq!(parse_duration("PT10S"), Some(10.0));
+        assert_eq!(parse_duration("1H"), None);
+        assert_eq!(parse_duration("PT1X"), None);
+    }
+
+    #[test]
+    fn timeline_repeats_until_next_entry_and_period_end() {
+        let mpd = r#"<MPD type="static" mediaPresentationDuration="PT10S">
+          <Period>
+            <AdaptationSet contentType="video">
+              <SegmentTemplate timescale="1000" initialization="init_$RepresentationID$.mp4"
+                               media="chunk_$Time$.m4s">
+                <SegmentTimeline>
+                  <S t="0" d="2000" r="-1"/>
+                  <S t="6000" d="1000"/>
+                  <S t="7000" d="1500" r="-1"/>
+                </SegmentTimeline>
+              </SegmentTemplate>
+              <Representation id="hd" bandwidth="1000"/>
+            </AdaptationSet>
+          </Period>
+        </MPD>"#;
+        let tracks = parse(mpd, &mpd_url(), false).unwrap();
+        assert_eq!(tracks.len(), 1);
+        let track = &tracks[0];
+        assert_eq!(
+            track.init.as_ref().map(Url::as_str),
+            Some("https://cdn.example.com/vod/init_hd.mp4")
+        );
+        let names: Vec<&str> = uris(track)
+            .into_iter()
+            .map(|u| u.rsplit('/').next().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "chunk_0.m4s",
+                "chunk_2000.m4s",
+                "chunk_4000.m4s",
+                "chunk_6000.m4s",
+                "chunk_7000.m4s",
+                "chunk_8500.m4s",
+            ]
+        );
+        let durations: Vec<f64> = track.segments.iter().map(|(_, d)| *d).collect();
+        assert_eq!(durations, [2.0, 2.0, 2.0, 1.0, 1.5, 1.5]);
+    }
+
+    #[test]
+    fn duration_template_counts_segments_from_start_number() {
+        let mpd = r#"<MPD mediaPresentationDuration="PT10S">
+          <BaseURL>media/</BaseURL>
+          <Period>
+            <AdaptationSet mimeType="audio/mp4">
+              <Representation id="aac" bandwidth="128000">
+                <BaseURL>audio/</BaseURL>
+                <SegmentTemplate duration="4" startNumber="5" media="seg_$Number%05d$.m4s"/>
+              </Representation>
+            </AdaptationSet>
+          </Period>
+        </MPD>"#;
+        let tracks = parse(mpd, &mpd_url(), false).unwrap();
+        let track = &tracks[0];
+        assert_eq!(track.kind, "audio");
+        assert_eq!(
+            uris(track),
+            [
+                "https://cdn.example.com/vod/media/audio/seg_00005.m4s",
+                "https://cdn.example.com/vod/media/audio/seg_00006.m4s",
+                "https://cdn.example.com/vod/media/audio/seg_00007.m4s",
+            ]
+        );
+        let durations: Vec<f64> = track.segments.iter().map(|(_, d)| *d).collect();
+        assert_eq!(durations, [4.0, 4.0, 2.0]);
+    }
+
+    #[test]
+    fn picks_highest_bandwidth_and_puts_video_first() {
+        let mpd = r#"<MPD mediaPresentationDuration="PT4S">
+          <Period>
+            <AdaptationSet mimeType="audio/mp4">
+              <Representation id="a" bandwidth="64000"><BaseURL>a.mp4</BaseURL></Representation>
+            </AdaptationSet>
+            <AdaptationSet mimeType="video/mp4">
+              <Representation id="low" bandwidth="500000"><BaseURL>low.mp4</BaseURL></Representation>
+              <Representation id="high" bandwidth="3000000">
+                <SegmentList duration="2">
+                  <Initialization sourceURL="high/init.mp4"/>
+                  <SegmentURL media="high/1.m4s"/>
+                  <SegmentURL media="high/2.m4s"/>
+                </SegmentList>
+              </Representation>
+            </AdaptationSet>
+          </Period>
+        </MPD>"#;
+        let tracks = parse(mpd, &mpd_url(), false).unwrap();
+        let kinds: Vec<&str> = tracks.iter().map(|t| t.kind).collect();
+        assert_eq!(kinds, ["video", "audio"]);
+        assert_eq!(tracks[0].bandwidth, 3_000_000);
+        assert_eq!(
+            uris(&tracks[0]),
+            [
+                "https://cdn.example.com/vod/high/1.m4s",
+                "https://cdn.example.com/vod/high/2.m4s",
+            ]
+        );
+        // SegmentBase 或没有分段信息时整个文件作为一个分段
+        assert_eq!(uris(&tracks[1]), ["https://cdn.example.com/vod/a.mp4"]);
+        assert_eq!(tracks[1].segments[0].1, 4.0);
+    }
+
+    #[test]
+    fn rejects_live_and_protected_content() {
+        let live = r#"<MPD type="dynamic"><Period/></MPD>"#;
+        assert!(parse(live, &mpd_url(), false).is_err());
+
+        let protected = r#"<MPD mediaPresentationDuration="PT4S">
+          <Period>
+            <AdaptationSet mimeType="video/mp4">
+              <ContentProtection schemeIdUri="urn:mpeg:dash:mp4protection:2011"/>
+              <Representation id="v" bandwidth="1"><BaseURL>v.mp4</BaseURL></Representation>
+            </AdaptationSet>
+          </Period>
+        </MPD>"#;
+        let err = parse(protected, &mpd_url(), false).err().unwrap();
+        assert!(matches!(err.downcast_ref::<Exit>(), Some(Exit::Drm)));
+        // 有 CENC 密钥时照常解析，合并后再解密
+        assert_eq!(parse(protected, &mpd_url(), true).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn into_playlist_puts_init_first() {
+        let track = Track {
+            kind: "video",
+            bandwidth: 1,
+            init: Some(Url::parse("https://a/init.mp4").unwrap()),
+            segments: vec![(Url::parse("https://a/1.m4s").unwrap(), 2.0)],
+        };
+        let playlist = track.into_playlist();
+        assert!(playlist.end_list);
+        assert_eq!(playlist.segments.len(), 2);
+        assert_eq!(playlist.segments[0].uri, "https://a/init.mp4");
+        assert_eq!(playlist.segments[0].duration, 0.0);
+        assert_eq!(playlist.segments[1].duration, 2.0);
+    }
+
+    #[test]
+    fn is_mpd_checks_extension_or_root() {
+        assert!(is_mpd("https://a/manifest.mpd?token=1", b""));
+        assert!(is_mpd(
+            "https://a/manifest",
+            b"<?xml version=\"1.0\"?><MPD>"
+        ));
+        assert!(!is_mpd("https://a/index.m3u8", b"#EXTM3U"));
+    }
+}
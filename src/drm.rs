@@ -0,0 +1,74 @@
+use anyhow::{Context, Result, bail};
+use m3u8_rs::{Key, KeyMethod, MasterPlaylist, MediaPlaylist};
+
+use crate::exit::Exit;
+use crate::i18n::tr;
+
+/// KEYFORMAT 与 DRM 系统的对应关系
+const KEY_FORMATS: &[(&str, &str)] = &[
+    ("com.apple.streamingkeydelivery", "FairPlay"),
+    ("urn:uuid:edef8ba9-79d6-4ace-a3c8-27dcd51d21ed", "Widevine"),
+    ("com.microsoft.playready", "PlayReady"),
+    ("urn:uuid:9a04f079-9840-4286-ab92-e65be0885f95", "PlayReady"),
+];
+
+/// 检查 Master Playlist 中的 EXT-X-SESSION-KEY，在选择变体流之前发现 DRM。
+/// 指定了 `--cenc-key` (`cenc`) 时由 mp4decrypt 解密，不再检查
+pub fn check_master(master: &MasterPlaylist, cenc: bool) -> Result<()> {
+    if cenc {
+        return Ok(());
+    }
+    master
+        .session_key
+        .iter()
+        .try_for_each(|k| check(&k.0))
+        .context(Exit::Drm)
+}
+
+/// 检查切片的 EXT-X-KEY，在下载切片之前发现 DRM
+pub fn check_media(media: &MediaPlaylist, cenc: bool) -> Result<()> {
+    if cenc {
+        return Ok(());
+    }
+    media
+        .segments
+        .iter()
+        .filter_map(|s| s.key.as_ref())
+        .try_for_each(check)
+        .context(Exit::Drm)
+}
+
+fn check(key: &Key) -> Result<()> {
+    if let Some(system) = system(key) {
+        bail!(tr!(
+            "此视频流受 DRM 保护 ({})，切片无法解密，已在下载前停止。\
+             DRM 内容只能在授权的播放器中观看；如果持有 CENC 内容密钥，可用 --cenc-key 解密",
+            system
+        ));
+    }
+    match &key.method {
+        KeyMethod::None | KeyMethod::AES128 => Ok(()),
+        KeyMethod::SampleAES => bail!(tr!("暂不支持 SAMPLE-AES 加密的视频流，已在下载前停止")),
+        KeyMethod::Other(method) => bail!(tr!("不支持的加密方式 {}，已在下载前停止", method)),
+    }
+}
+
+/// 识别密钥所属的 DRM 系统：KEYFORMAT、FairPlay 的 skd:// 地址或 SAMPLE-AES-CTR (CENC)
+fn system(key: &Key) -> Option<&'static str> {
+    let format = key.keyformat.as_deref().unwrap_or("identity");
+    if let Some((_, name)) = KEY_FORMATS
+        .iter()
+        .find(|(f, _)| f.eq_ignore_ascii_case(format))
+    {
+        return Some(*name);
+    }
+    if key.uri.as_deref().is_some_and(|u| u.starts_with("skd://")) {
+        return Some("FairPlay");
+    }
+    match &key.method {
+        KeyMethod::Other(method) if method.eq_ignore_ascii_case("SAMPLE-AES-CTR") => {
+            Some("SAMPLE-AES-CTR / CENC")
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,143 @@
+use anyhow::Result;
+use indicatif::HumanBytes;
+use m3u8_rs::{KeyMethod, MediaPlaylist};
+use url::Url;
+
+use crate::i18n::tr;
+use crate::query::Inherited;
+use crate::{
+    AccelType, Args, Encoding, Job, RetryPolicy, Shared, Timing, audio_codec, detect_acceleration,
+    keys, source, space, transcode_args,
+};
+
+/// `--dry-run`：解析播放列表并获取密钥，估算大小并打印将要执行的 FFmpeg 命令，不下载切片
+pub async fn report(
+    media: &MediaPlaylist,
+    base_url: Option<Url>,
+    job: &Job,
+    timing: &Timing,
+    args: &Args,
+    shared: &Shared,
+) -> Result<()> {
+    let query = Inherited::new(&args.propagate_query, &job.url);
+    let segments = &media.segments;
+    let encrypted = segments
+        .iter()
+        .filter_map(|s| s.key.as_ref())
+        .any(|k| !matches!(k.method, KeyMethod::None));
+    let distinct = if args.cenc_key.is_empty() {
+        keys::prefetch(
+            shared,
+            base_url.as_ref(),
+            &query,
+            segments.iter().filter_map(|s| s.key.as_ref()),
+            &RetryPolicy::from_args(args),
+        )
+        .await?
+    } else {
+        0
+    };
+
+    let source = source::open(
+        args.segment_base.as_deref(),
+        base_url,
+        &job.url,
+        query,
+        shared.proxies.clone(),
+        shared.renewed.clone(),
+        shared.hooks.clone(),
+    )?;
+    let estimate = space::estimate(&shared.client, source.as_ref(), segments).await;
+
+    // 与实际合并时一致：有不连续点时转码输入为 concat 列表
+    let input = if segments.iter().skip(1).any(|s| s.discontinuity) {
+        job.concat_list()
+    } else {
+        job.merged_ts()
+    };
+    let input = input.to_string_lossy().into_owned();
+    let chapters = job.output.with_extension("chapters.txt");
+    let chapters = chapters.to_string_lossy();
+    // 两遍编码只支持 libx264，两遍的命令都列出
+    let (accel, passes) = if args.two_pass {
+        (AccelType::CPU, vec![Some(1), Some(2)])
+    } else {
+        let accel = detect_acceleration().await?.first().copied();
+        (accel.unwrap_or(AccelType::CPU), vec![None])
+    };
+    let audio = audio_codec::resolve(args, &[input.as_str()], &job.output).await;
+    let commands = passes
+        .into_iter()
+        .map(|pass| {
+            let encoding = Encoding {
+                accel,
+                pass,
+                audio,
+                loudness: Vec::new(),
+                hdr: None,
+                copy_video: false,
+            };
+            transcode_args(
+                &[input.as_str()],
+                (!timing.chapters.is_empty()).then_some(&*chapters),
+                job,
+                timing,
+                args,
+                &encoding,
+            )
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    println!("{}", tr!("输出文件: {}", job.output.display()));
+    println!("{}", tr!("切片数: {}", segments.len()));
+    println!(
+        "{}",
+        tr!(
+            "总时长: {:.1}s (输出 {:.1}s)",
+            total_duration(media),
+            timing.duration
+        )
+    );
+    match (encrypted, distinct) {
+        (false, _) => println!("{}", tr!("加密: 无")),
+        (true, 0) => println!("{}", tr!("加密: 是 (使用手动提供的密钥)")),
+        (true, n) => println!("{}", tr!("加密: AES-128，已获取 {} 个密钥", n)),
+    }
+    match estimate {
+        Some(bytes) => println!("{}", tr!("预计大小: 约 {}", HumanBytes(bytes))),
+        None => println!(
+            "{}",
+            tr!("预计大小: 无法估算 (服务器未返回 Content-Length)")
+        ),
+    }
+    if !timing.chapters.is_empty() {
+        println!("{}", tr!("章节: {} 个", timing.chapters.len()));
+    }
+    println!("{}", tr!("FFmpeg 命令:"));
+    for command in &commands {
+        println!("ffmpeg {}", shell_join(command));
+    }
+    Ok(())
+}
+
+fn total_duration(media: &MediaPlaylist) -> f64 {
+    media.segments.iter().map(|s| f64::from(s.duration)).sum()
+}
+
+/// 按 shell 规则给含空格或特殊字符的参数加引号，便于直接复制执行
+fn shell_join(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| {
+            let plain = !arg.is_empty()
+                && arg
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_.,:/=+%@".contains(c));
+            if plain {
+                arg.clone()
+            } else {
+                format!("'{}'", arg.replace('\'', "'\\''"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,24 @@
+use crate::i18n::tr;
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+use std::io::Read;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// 声明了 Content-Encoding 的响应由 reqwest 自动解压 (gzip/br/zstd/deflate)。
+/// 部分源站直接提供压缩后的播放列表 (例如 `.m3u8.gz`) 却不声明编码，这里按文件头识别并解压；
+/// 只用于播放列表，密钥与加密切片的内容近似随机，不能按文件头判断
+pub fn decode_playlist(body: Vec<u8>) -> Result<Vec<u8>> {
+    if body.starts_with(GZIP_MAGIC) {
+        let mut out = Vec::new();
+        GzDecoder::new(body.as_slice())
+            .read_to_end(&mut out)
+            .context(tr!("无法解压 gzip 压缩的播放列表"))?;
+        Ok(out)
+    } else if body.starts_with(ZSTD_MAGIC) {
+        zstd::decode_all(body.as_slice()).context(tr!("无法解压 zstd 压缩的播放列表"))
+    } else {
+        Ok(body)
+    }
+}
@@ -0,0 +1,115 @@
+use serde::Serialize;
+use std::io::Write;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::task::JoinHandle;
+
+/// 任务生命周期与进度事件，供 MQTT、JSON 进度输出等外部订阅者使用
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum JobEvent {
+    Queued {
+        job: usize,
+        url: String,
+        output: String,
+    },
+    Started {
+        job: usize,
+        url: String,
+    },
+    PlaylistParsed {
+        job: usize,
+        segments: usize,
+        duration: f64,
+    },
+    SegmentDone {
+        job: usize,
+        segment: usize,
+        bytes: u64,
+        completed: u64,
+        total: u64,
+    },
+    Retry {
+        job: usize,
+        segment: usize,
+        attempt: u8,
+        error: String,
+    },
+    Merge {
+        job: usize,
+        completed: u64,
+        total: u64,
+    },
+    Transcode {
+        job: usize,
+        out_time: f64,
+        duration: f64,
+    },
+    Complete {
+        job: usize,
+        output: String,
+    },
+    Failed {
+        job: usize,
+        error: String,
+    },
+    Cancelled {
+        job: usize,
+    },
+}
+
+impl JobEvent {
+    /// 事件所属的任务
+    pub fn job(&self) -> usize {
+        match self {
+            JobEvent::Queued { job, .. }
+            | JobEvent::Started { job, .. }
+            | JobEvent::PlaylistParsed { job, .. }
+            | JobEvent::SegmentDone { job, .. }
+            | JobEvent::Retry { job, .. }
+            | JobEvent::Merge { job, .. }
+            | JobEvent::Transcode { job, .. }
+            | JobEvent::Complete { job, .. }
+            | JobEvent::Failed { job, .. }
+            | JobEvent::Cancelled { job } => *job,
+        }
+    }
+}
+
+/// 事件广播：没有订阅者时发送的事件直接丢弃
+#[derive(Clone)]
+pub struct Events {
+    tx: broadcast::Sender<JobEvent>,
+}
+
+impl Events {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(4096);
+        Self { tx }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<JobEvent> {
+        self.tx.subscribe()
+    }
+
+    pub fn emit(&self, event: JobEvent) {
+        let _ = self.tx.send(event);
+    }
+}
+
+/// `--progress json`：每个事件输出一行 JSON 到 stdout
+pub fn spawn_json_printer(mut rx: broadcast::Receiver<JobEvent>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            let event = match rx.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            };
+            if let Ok(line) = serde_json::to_string(&event) {
+                let mut stdout = std::io::stdout().lock();
+                let _ = writeln!(stdout, "{}", line);
+                let _ = stdout.flush();
+            }
+        }
+    })
+}
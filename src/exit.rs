@@ -0,0 +1,98 @@
+use std::fmt;
+use std::io::ErrorKind;
+
+use crate::control;
+use crate::i18n::tr;
+use crate::recovery::Failure;
+use crate::watchdog::{EXIT_MAX_RUNTIME, RuntimeExceeded};
+
+/// 失败类别，以 context 的形式附加在错误上，决定进程的退出码，
+/// 便于脚本区分可以重试的网络问题与重试也无济于事的失败
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Exit {
+    /// 无法获取播放列表 (网络或 HTTP 错误)
+    Playlist,
+    /// 播放列表或清单格式无效
+    Parse,
+    /// 受 DRM 保护或使用不支持的加密方式
+    Drm,
+    /// 切片下载失败
+    Segment,
+    /// 切片或 CENC 解密失败
+    Decrypt,
+    /// FFmpeg 缺失或转码失败
+    Ffmpeg,
+    /// 磁盘空间不足或无法写入
+    Disk,
+}
+
+impl Exit {
+    /// 对应的退出码
+    pub fn code(self) -> i32 {
+        match self {
+            Exit::Playlist => 10,
+            Exit::Parse => 11,
+            Exit::Drm => 12,
+            Exit::Segment => 13,
+            Exit::Decrypt => 14,
+            Exit::Ffmpeg => 15,
+            Exit::Disk => 16,
+        }
+    }
+
+    /// 从错误链中识别类别：磁盘写满等 IO 错误优先，其次是显式附加的类别，
+    /// 最后是恢复策略使用的失败类别
+    pub fn of(e: &anyhow::Error) -> Option<Exit> {
+        let disk = e
+            .chain()
+            .filter_map(|c| c.downcast_ref::<std::io::Error>())
+            .any(|e| {
+                matches!(
+                    e.kind(),
+                    ErrorKind::StorageFull
+                        | ErrorKind::QuotaExceeded
+                        | ErrorKind::ReadOnlyFilesystem
+                        | ErrorKind::FileTooLarge
+                )
+            });
+        if disk {
+            return Some(Exit::Disk);
+        }
+        if let Some(exit) = e.downcast_ref::<Exit>() {
+            return Some(*exit);
+        }
+        e.downcast_ref::<Failure>().map(|f| match f {
+            Failure::Forbidden | Failure::Stalled(_) => Exit::Segment,
+            Failure::Encoder => Exit::Ffmpeg,
+            Failure::Decrypt(_) => Exit::Decrypt,
+        })
+    }
+}
+
+impl fmt::Display for Exit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Exit::Playlist => tr!("无法获取播放列表"),
+            Exit::Parse => tr!("无法解析播放列表"),
+            Exit::Drm => tr!("受 DRM 保护或不支持的加密方式"),
+            Exit::Segment => tr!("切片下载失败"),
+            Exit::Decrypt => tr!("解密失败"),
+            Exit::Ffmpeg => tr!("FFmpeg 出错"),
+            Exit::Disk => tr!("磁盘错误"),
+        })
+    }
+}
+
+/// 被取消时的退出码，与 shell 中 Ctrl-C 中断的程序相同
+const EXIT_CANCELLED: i32 = 130;
+
+/// 进程退出码：超过 --max-runtime 为 124，被取消为 130，能识别类别时按类别，其余为 1
+pub fn code(e: &anyhow::Error) -> i32 {
+    if e.is::<RuntimeExceeded>() {
+        return EXIT_MAX_RUNTIME;
+    }
+    if control::is_cancelled(e) {
+        return EXIT_CANCELLED;
+    }
+    Exit::of(e).map_or(1, Exit::code)
+}
@@ -0,0 +1,125 @@
+use anyhow::{Context, Result, bail};
+use indicatif::ProgressBar;
+use std::io::IsTerminal;
+use tracing::info;
+use url::Url;
+
+use crate::control;
+use crate::i18n::tr;
+
+/// 清单文件扩展名，页面中出现这些扩展名的地址被视为候选
+const EXTENSIONS: &[&str] = &[".m3u8", ".mpd"];
+
+/// 下载到的内容是网页而不是播放列表
+pub fn is_html(content: &[u8]) -> bool {
+    let head = String::from_utf8_lossy(&content[..content.len().min(1024)]).to_lowercase();
+    let head = head.trim_start_matches('\u{feff}').trim_start();
+    !head.starts_with("#extm3u") && (head.contains("<!doctype html") || head.contains("<html"))
+}
+
+/// 地址的边界字符：引号、尖括号、括号、空白等
+fn is_boundary(b: u8) -> bool {
+    matches!(
+        b,
+        b'"' | b'\'' | b'`' | b'<' | b'>' | b'(' | b')' | b'\\' | b',' | b';' | b'{' | b'}'
+    ) || b.is_ascii_whitespace()
+}
+
+/// 扫描 HTML 与内嵌脚本 (包括播放器配置 JSON) 中的 .m3u8/.mpd 地址，按出现顺序去重
+pub fn candidates(page: &str, html: &[u8]) -> Vec<String> {
+    let page = Url::parse(page).ok();
+    // 还原 JSON 与 HTML 中常见的转义
+    let text = String::from_utf8_lossy(html)
+        .replace("\\/", "/")
+        .replace("\\u002F", "/")
+        .replace("\\u002f", "/")
+        .replace("&amp;", "&");
+    let bytes = text.as_bytes();
+
+    let mut found: Vec<String> = Vec::new();
+    for ext in EXTENSIONS {
+        for (pos, _) in text.match_indices(ext) {
+            let after = pos + ext.len();
+            if bytes.get(after).is_some_and(|b| b.is_ascii_alphanumeric()) {
+                continue;
+            }
+            let mut start = pos;
+            while start > 0 && !is_boundary(bytes[start - 1]) {
+                start -= 1;
+            }
+            let mut end = after;
+            while end < bytes.len() && !is_boundary(bytes[end]) {
+                end += 1;
+            }
+            let raw = &text[start..end];
+            let resolved = match &page {
+                Some(page) => page.join(raw).ok(),
+                None => Url::parse(raw).ok(),
+            };
+            if let Some(url) = resolved.filter(|u| u.scheme().starts_with("http")) {
+                let url = url.to_string();
+                if !found.contains(&url) {
+                    found.push(url);
+                }
+            }
+        }
+    }
+    found
+}
+
+/// 选择候选地址：`--pick` 优先；只有一个候选时直接使用；
+/// 在终端中交互选择，否则使用第一个
+pub fn choose(
+    candidates: &[String],
+    pick: Option<usize>,
+    interactive: bool,
+    pb: &ProgressBar,
+) -> Result<String> {
+    if candidates.is_empty() {
+        bail!(tr!("页面中没有找到 .m3u8 或 .mpd 地址"));
+    }
+    if let Some(n) = pick {
+        return candidates
+            .get(n.wrapping_sub(1))
+            .cloned()
+            .with_context(|| tr!("--pick {} 超出范围，共 {} 个候选", n, candidates.len()));
+    }
+    if candidates.len() == 1 {
+        return Ok(candidates[0].clone());
+    }
+
+    if !interactive || !std::io::stdin().is_terminal() {
+        for (i, c) in candidates.iter().enumerate() {
+            info!("    [{}] {}", i + 1, c);
+        }
+        info!(
+            "{}",
+            tr!(
+                "找到 {} 个候选地址，使用第 1 个 (可用 --pick 指定)",
+                candidates.len()
+            )
+        );
+        return Ok(candidates[0].clone());
+    }
+
+    pb.suspend(|| -> Result<String> {
+        eprintln!("{}", tr!("页面中找到多个播放列表:"));
+        for (i, c) in candidates.iter().enumerate() {
+            eprintln!("  [{}] {}", i + 1, c);
+        }
+        loop {
+            eprint!("{}", tr!("请选择 [1-{}] (默认 1): ", candidates.len()));
+            let line = control::read_line()?;
+            let line = line.trim();
+            if line.is_empty() {
+                return Ok(candidates[0].clone());
+            }
+            match line.parse::<usize>() {
+                Ok(n) if (1..=candidates.len()).contains(&n) => {
+                    return Ok(candidates[n - 1].clone());
+                }
+                _ => eprintln!("{}", tr!("无效的选择: {}", line)),
+            }
+        }
+    })
+}
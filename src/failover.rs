@@ -0,0 +1,111 @@
+use anyhow::{Result, bail};
+use m3u8_rs::{Key, MediaPlaylist, MediaSegment, Playlist, parse_playlist};
+use url::Url;
+
+use crate::i18n::tr;
+use crate::query::Inherited;
+use crate::{Args, Shared, download_playlist, keys};
+
+/// 从备用变体流换用的一个切片
+pub struct Replacement {
+    pub index: usize,
+    /// 切片的完整地址
+    pub uri: String,
+    pub material: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+/// 切片重试与补抓后仍然失败 (例如 CDN 调整后持续返回 404) 时，从另一路变体流中按媒体序列号
+/// 找出 `failed` 对应的切片，`sequences` 为各切片的媒体序列号。
+/// 各变体的媒体序列号是对齐的；fMP4 切片依赖各自的初始化段，不能混用
+pub async fn remap(
+    fallback: &Url,
+    args: &Args,
+    shared: &Shared,
+    query: &Inherited,
+    sequences: &[u64],
+    failed: &[usize],
+) -> Result<Vec<Replacement>> {
+    let content = download_playlist(fallback.as_str(), args).await?;
+    let media = match parse_playlist(&content) {
+        Ok((_, Playlist::MediaPlaylist(media))) => media,
+        _ => bail!(tr!("备用变体流不是有效的 Media Playlist")),
+    };
+    if media.segments.iter().any(|s| s.map.is_some()) {
+        bail!(tr!("备用变体流为 fMP4，不能与原变体流的切片混合"));
+    }
+    let mut replacements = Vec::new();
+    for (index, seg, key, seq) in pair(&media, sequences, failed) {
+        replacements.push(Replacement {
+            index,
+            uri: fallback.join(&seg.uri)?.to_string(),
+            material: keys::resolve(shared, Some(fallback), query, key, seq).await?,
+        });
+    }
+    Ok(replacements)
+}
+
+/// 备用变体流中与 `failed` 序列号相同的切片：(原切片下标, 备用切片, 生效中的密钥, 媒体序列号)
+fn pair<'a>(
+    media: &'a MediaPlaylist,
+    sequences: &[u64],
+    failed: &[usize],
+) -> Vec<(usize, &'a MediaSegment, Option<&'a Key>, u64)> {
+    let mut key = None;
+    let mut pairs = Vec::new();
+    for (i, seg) in media.segments.iter().enumerate() {
+        if seg.key.is_some() {
+            key = seg.key.as_ref();
+        }
+        let seq = media.media_sequence + i as u64;
+        if let Some(&index) = failed.iter().find(|&&idx| sequences[idx] == seq) {
+            pairs.push((index, seg, key, seq));
+        }
+    }
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use m3u8_rs::KeyMethod;
+
+    fn playlist(media_sequence: u64, count: usize) -> MediaPlaylist {
+        MediaPlaylist {
+            media_sequence,
+            segments: (0..count)
+                .map(|i| MediaSegment {
+                    uri: format!("low/{}.ts", media_sequence + i as u64),
+                    duration: 10.0,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn pair_matches_failed_segments_by_sequence() {
+        // 去除广告后保留的切片为 100、101、104、105；备用变体的窗口从 101 开始
+        let sequences = [100, 101, 104, 105];
+        let fallback = playlist(101, 5);
+        let pairs = pair(&fallback, &sequences, &[0, 2, 3]);
+        let found: Vec<(usize, &str, u64)> = pairs
+            .iter()
+            .map(|(index, seg, _, seq)| (*index, seg.uri.as_str(), *seq))
+            .collect();
+        assert_eq!(found, [(2, "low/104.ts", 104), (3, "low/105.ts", 105)]);
+    }
+
+    #[test]
+    fn pair_carries_the_key_in_effect() {
+        let mut fallback = playlist(10, 3);
+        fallback.segments[0].key = Some(Key {
+            method: KeyMethod::AES128,
+            uri: Some("low.key".into()),
+            ..Default::default()
+        });
+        let pairs = pair(&fallback, &[12], &[0]);
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].2.and_then(|k| k.uri.as_deref()), Some("low.key"));
+    }
+}
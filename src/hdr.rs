@@ -0,0 +1,136 @@
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::process::Stdio;
+use tokio::process::Command;
+use tracing::{debug, info};
+
+use crate::i18n::tr;
+use crate::{Args, pipeline};
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Tonemap {
+    /// 色调映射为 SDR (BT.709)
+    Sdr,
+}
+
+/// zimg 转为线性光后用 hable 曲线压缩高光，再转回 BT.709 的 8 位画面
+const TONEMAP_SDR: &str = "zscale=t=linear:npl=100,format=gbrpf32le,zscale=p=bt709,\
+    tonemap=tonemap=hable:desat=0,zscale=t=bt709:m=bt709:r=tv,format=yuv420p";
+
+/// 源画面的 HDR 色彩参数 (FFprobe 的名称)
+#[derive(Clone, Debug)]
+pub struct Hdr {
+    transfer: String,
+    primaries: String,
+    space: String,
+}
+
+#[derive(Deserialize)]
+struct ProbeOutput {
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+}
+
+#[derive(Deserialize)]
+struct ProbeStream {
+    color_transfer: Option<String>,
+    color_primaries: Option<String>,
+    color_space: Option<String>,
+}
+
+impl Hdr {
+    fn name(&self) -> &'static str {
+        if self.transfer == "arib-std-b67" {
+            "HLG"
+        } else {
+            "HDR10"
+        }
+    }
+
+    /// 指定了 `--tonemap sdr` 时在滤镜链中做色调映射
+    pub fn filter(&self, args: &Args) -> Option<&'static str> {
+        args.tonemap.map(|tonemap| match tonemap {
+            Tonemap::Sdr => TONEMAP_SDR,
+        })
+    }
+
+    /// 输出的色彩参数：色调映射后标记为 BT.709，否则输出 10 位画面并沿用源的色彩参数
+    pub fn ffmpeg_args(&self, args: &Args) -> Vec<String> {
+        let (primaries, transfer, space) = match args.tonemap {
+            Some(Tonemap::Sdr) => ("bt709", "bt709", "bt709"),
+            None => (
+                self.primaries.as_str(),
+                self.transfer.as_str(),
+                self.space.as_str(),
+            ),
+        };
+        let mut out = Vec::new();
+        if args.tonemap.is_none() {
+            out.extend(["-pix_fmt".to_string(), "yuv420p10le".to_string()]);
+        }
+        for (key, value) in [
+            ("-color_primaries", primaries),
+            ("-color_trc", transfer),
+            ("-colorspace", space),
+        ] {
+            out.extend([key.to_string(), value.to_string()]);
+        }
+        out
+    }
+}
+
+/// 用 FFprobe 查看第一路视频的传输特性，PQ (HDR10) 或 HLG 时返回色彩参数。
+/// 流水线转码的输入无法预先查看，按 SDR 处理
+pub async fn detect(input: &str) -> Option<Hdr> {
+    if input == pipeline::INPUT {
+        return None;
+    }
+    match probe(input).await {
+        Ok(Some(hdr)) => {
+            info!(
+                "{}",
+                tr!("源画面为 {} ({}, {})", hdr.name(), hdr.primaries, hdr.space)
+            );
+            Some(hdr)
+        }
+        Ok(None) => None,
+        Err(e) => {
+            debug!("{}", tr!("无法查看源画面的色彩参数，按 SDR 处理: {:#}", e));
+            None
+        }
+    }
+}
+
+async fn probe(input: &str) -> Result<Option<Hdr>> {
+    let mut command = Command::new("ffprobe");
+    command.args(["-v", "error"]);
+    if input.ends_with(".txt") {
+        command.args(["-f", "concat", "-safe", "0"]);
+    }
+    let output = command
+        .args(["-select_streams", "v:0", "-show_entries"])
+        .arg("stream=color_transfer,color_primaries,color_space")
+        .args(["-of", "json", input])
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .context(tr!("FFprobe 未找到，请确保已安装 FFmpeg 并添加到 PATH"))?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    let parsed: ProbeOutput =
+        serde_json::from_slice(&output.stdout).context(tr!("解析 FFprobe 输出失败"))?;
+    let Some(stream) = parsed.streams.into_iter().next() else {
+        return Ok(None);
+    };
+    let transfer = stream.color_transfer.unwrap_or_default();
+    if !matches!(transfer.as_str(), "smpte2084" | "arib-std-b67") {
+        return Ok(None);
+    }
+    Ok(Some(Hdr {
+        transfer,
+        primaries: stream.color_primaries.unwrap_or_else(|| "bt2020".into()),
+        space: stream.color_space.unwrap_or_else(|| "bt2020nc".into()),
+    }))
+}
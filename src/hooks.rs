@@ -0,0 +1,170 @@
+use anyhow::{Context, Result, bail};
+use futures::future::BoxFuture;
+use reqwest::header::HeaderMap;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::i18n::tr;
+use crate::script::Script;
+use crate::{Args, parse_headers};
+
+/// 即将发出的切片请求，钩子可以改写地址与请求头
+pub struct HookRequest {
+    pub url: String,
+    pub headers: HeaderMap,
+}
+
+/// 切片下载流程的扩展点，用于站点特定的处理 (改写地址、去掉切片开头的垃圾字节等)，
+/// 不需要修改下载流程本身。两个方法默认都不做任何处理
+pub trait Hook: Send + Sync {
+    /// 发出切片请求之前调用
+    fn before_request<'a>(&'a self, _request: &'a mut HookRequest) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    /// 切片下载完成、通过字节数校验之后，解密之前调用；返回错误时按下载失败重试
+    fn after_segment<'a>(
+        &'a self,
+        _url: &'a str,
+        _data: &'a mut Vec<u8>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// 按顺序执行的一组钩子
+#[derive(Default)]
+pub struct Hooks(Vec<Box<dyn Hook>>);
+
+impl Hooks {
+    /// `--script`、`--exec-before-request` 与 `--exec-after-segment`，按此顺序执行
+    pub fn from_args(args: &Args) -> Result<Self> {
+        let mut hooks: Vec<Box<dyn Hook>> = Vec::new();
+        if let Some(path) = &args.script {
+            hooks.push(Box::new(Script::load(path)?));
+        }
+        if let Some(command) = &args.exec_before_request {
+            hooks.push(Box::new(BeforeRequest(command.clone())));
+        }
+        if let Some(command) = &args.exec_after_segment {
+            hooks.push(Box::new(AfterSegment(command.clone())));
+        }
+        Ok(Self(hooks))
+    }
+
+    pub async fn before_request(&self, request: &mut HookRequest) -> Result<()> {
+        for hook in &self.0 {
+            hook.before_request(request).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn after_segment(&self, url: &str, data: &mut Vec<u8>) -> Result<()> {
+        for hook in &self.0 {
+            hook.after_segment(url, data).await?;
+        }
+        Ok(())
+    }
+}
+
+/// 通过 shell 执行命令：Unix 为 `sh -c`，Windows 为 `cmd /C`
+pub fn shell(command: &str) -> Command {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    shell.arg(command).kill_on_drop(true);
+    shell
+}
+
+/// `--exec-before-request`：环境变量 `M3U8_SEGMENT_URL` 为切片地址，
+/// stdout 中以 http(s):// 开头的行替换切片地址，`名称: 值` 形式的行作为请求头
+struct BeforeRequest(String);
+
+impl Hook for BeforeRequest {
+    fn before_request<'a>(&'a self, request: &'a mut HookRequest) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let output = shell(&self.0)
+                .env("M3U8_SEGMENT_URL", &request.url)
+                .output()
+                .await
+                .with_context(|| tr!("无法运行 --exec-before-request: {}", self.0))?;
+            if !output.status.success() {
+                bail!(tr!(
+                    "--exec-before-request 执行失败 ({}): {}",
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ));
+            }
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let mut headers = Vec::new();
+            for line in stdout.lines().map(str::trim).filter(|l| !l.is_empty()) {
+                if line.starts_with("http://") || line.starts_with("https://") {
+                    request.url = line.to_string();
+                } else if line.contains(':') {
+                    headers.push(line.to_string());
+                }
+            }
+            request.headers.extend(parse_headers(&headers)?);
+            Ok(())
+        })
+    }
+}
+
+/// `--exec-after-segment`：切片数据从 stdin 传入，环境变量 `M3U8_SEGMENT_URL` 为切片地址。
+/// stdout 非空时替换切片数据，为空时保持不变 (只做检查)；非零退出码表示切片无效，按下载失败重试
+struct AfterSegment(String);
+
+impl Hook for AfterSegment {
+    fn after_segment<'a>(
+        &'a self,
+        url: &'a str,
+        data: &'a mut Vec<u8>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let mut child = shell(&self.0)
+                .env("M3U8_SEGMENT_URL", url)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .with_context(|| tr!("无法运行 --exec-after-segment: {}", self.0))?;
+            let mut stdin = child
+                .stdin
+                .take()
+                .context(tr!("无法写入 --exec-after-segment"))?;
+            let input = std::mem::take(data);
+            // 边写入边读取输出，避免命令的 stdout 写满管道后双方互相等待
+            let (written, output) = tokio::join!(
+                async move {
+                    let result = stdin.write_all(&input).await;
+                    drop(stdin);
+                    (result, input)
+                },
+                child.wait_with_output()
+            );
+            // 只检查数据的命令可能不读完 stdin 就退出，写入失败 (管道已关闭) 不算错误
+            let (_, input) = written;
+            let output = output?;
+            if !output.status.success() {
+                bail!(tr!(
+                    "--exec-after-segment 判定切片无效 ({}): {}",
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ));
+            }
+            *data = if output.stdout.is_empty() {
+                input
+            } else {
+                output.stdout
+            };
+            Ok(())
+        })
+    }
+}
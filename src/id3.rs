@@ -0,0 +1,718 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::i18n::tr;
+use crate::{Job, Timing};
+
+const PACKET_SIZE: usize = 188;
+const SYNC_BYTE: u8 = 0x47;
+/// PMT 中表示 PES 承载 ID3 元数据的 stream_type
+const METADATA_STREAM_TYPE: u8 = 0x15;
+/// PTS 为 33 位、90kHz
+const PTS_MASK: u64 = (1 << 33) - 1;
+const PTS_HZ: f64 = 90_000.0;
+
+/// 时间元数据的导出格式
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Id3Export {
+    /// 全部 ID3 帧，写入 `<输出名>.id3.json`
+    Json,
+    /// 按标题 (TIT2) 划分音轨的 CUE 表，写入 `<输出名>.cue`
+    Cue,
+}
+
+/// 某一时刻出现的 ID3 标签
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct Cue {
+    /// 在输出中的时间位置 (秒)
+    pub time: f64,
+    /// 帧 ID (如 TIT2、TPE1、TXXX:StreamTitle) 与文本内容
+    pub frames: Vec<(String, String)>,
+}
+
+/// 从合并后的 TS 中提取 ID3 时间元数据并导出到输出文件旁。
+/// 广播流通常每个切片重复一次当前曲目，内容不变的连续标签只保留第一个
+pub async fn export(job: &Job, timing: &Timing, format: Id3Export) -> Result<()> {
+    let files = ts_files(&job.merged_input()).await?;
+    let mut cues = tokio::task::spawn_blocking(move || scan_files(&files)).await??;
+    cues.dedup_by(|b, a| a.frames == b.frames);
+
+    // 按裁剪范围平移，范围外的标签保留最后一个作为开头的状态
+    if let Some(clip) = &timing.clip {
+        let before = cues.iter().rposition(|c| c.time <= clip.start);
+        let end = clip.length.map(|l| clip.start + l);
+        cues = cues
+            .into_iter()
+            .enumerate()
+            .filter(|(i, c)| Some(*i) == before || c.time > clip.start)
+            .filter(|(_, c)| end.is_none_or(|end| c.time < end))
+            .map(|(_, c)| Cue {
+                time: (c.time - clip.start).max(0.0),
+                ..c
+            })
+            .collect();
+    }
+
+    if cues.is_empty() {
+        info!("{}", tr!("未在切片中找到 ID3 时间元数据"));
+        return Ok(());
+    }
+    let (path, content) = match format {
+        Id3Export::Json => (
+            job.output.with_extension("id3.json"),
+            serde_json::to_string_pretty(&cues)?,
+        ),
+        Id3Export::Cue => (
+            job.output.with_extension("cue"),
+            cue_sheet(&job.output, &cues),
+        ),
+    };
+    tokio::fs::write(&path, content)
+        .await
+        .with_context(|| tr!("无法写入 ID3 元数据: {}", path.display()))?;
+    info!(
+        "{}",
+        tr!(
+            "已导出 {} 条 ID3 时间元数据: {}",
+            cues.len(),
+            path.display()
+        )
+    );
+    Ok(())
+}
+
+/// 合并结果对应的 TS 文件：单个合并文件，或 concat 列表中的各段
+async fn ts_files(input: &Path) -> Result<Vec<PathBuf>> {
+    if input.extension().is_some_and(|e| e == "txt") {
+        let text = tokio::fs::read_to_string(input).await?;
+        Ok(text
+            .lines()
+            .filter_map(|l| l.strip_prefix("file '")?.strip_suffix('\''))
+            .map(|name| input.with_file_name(name.replace("'\\''", "'")))
+            .collect())
+    } else {
+        Ok(vec![input.to_path_buf()])
+    }
+}
+
+/// 依次扫描各段；每段的时间戳各自从零开始，按前面各段的时长累加
+fn scan_files(files: &[PathBuf]) -> Result<Vec<Cue>> {
+    let mut cues = Vec::new();
+    let mut offset = 0.0;
+    for path in files {
+        let file = File::open(path).with_context(|| tr!("无法打开 {}", path.display()))?;
+        let mut demuxer = Demuxer::default();
+        demuxer.scan(BufReader::new(file))?;
+        let duration = demuxer.duration();
+        cues.extend(demuxer.cues.into_iter().map(|c| Cue {
+            time: c.time + offset,
+            ..c
+        }));
+        offset += duration;
+    }
+    Ok(cues)
+}
+
+/// 只解析 PAT、PMT 与 PES 头，收集元数据流中的 ID3 标签
+#[derive(Default)]
+struct Demuxer {
+    pmt_pids: Vec<u16>,
+    metadata_pids: Vec<u16>,
+    /// 各元数据流正在拼接的 PES：(PTS, 负载)
+    pending: HashMap<u16, (Option<u64>, Vec<u8>)>,
+    /// 第一个 PES 的 PTS，作为时间零点
+    base: Option<u64>,
+    /// 距时间零点最远的 PTS 偏移
+    last: u64,
+    cues: Vec<Cue>,
+}
+
+impl Demuxer {
+    fn scan(&mut self, mut reader: impl Read) -> Result<()> {
+        let mut packet = [0u8; PACKET_SIZE];
+        let mut first = true;
+        loop {
+            match reader.read_exact(&mut packet) {
+                Ok(()) => {}
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e.into()),
+            }
+            if packet[0] != SYNC_BYTE {
+                // fMP4 等非 TS 内容没有 ID3 PES，直接跳过
+                if first {
+                    return Ok(());
+                }
+                if !resync(&mut reader, &mut packet)? {
+                    break;
+                }
+            }
+            first = false;
+            self.packet(&packet);
+        }
+        let pids: Vec<u16> = self.pending.keys().copied().collect();
+        for pid in pids {
+            self.flush(pid);
+        }
+        Ok(())
+    }
+
+    /// 以 PTS 计算的本段时长
+    fn duration(&self) -> f64 {
+        self.last as f64 / PTS_HZ
+    }
+
+    fn packet(&mut self, packet: &[u8; PACKET_SIZE]) {
+        let start = packet[1] & 0x40 != 0;
+        let pid = (u16::from(packet[1] & 0x1f) << 8) | u16::from(packet[2]);
+        let control = (packet[3] >> 4) & 0x03;
+        if control & 0x01 == 0 {
+            return;
+        }
+        let offset = if control & 0x02 != 0 {
+            5 + usize::from(packet[4])
+        } else {
+            4
+        };
+        let Some(payload) = packet.get(offset..) else {
+            return;
+        };
+
+        if pid == 0 {
+            if start {
+                self.pat(payload);
+            }
+        } else if self.pmt_pids.contains(&pid) {
+            if start {
+                self.pmt(payload);
+            }
+        } else if start {
+            let pes = pes_header(payload);
+            if let Some(pts) = pes.and_then(|(pts, _)| pts) {
+                let base = *self.base.get_or_insert(pts);
+                self.last = self.last.max(since(base, pts));
+            }
+            if self.metadata_pids.contains(&pid) {
+                self.flush(pid);
+                if let Some((pts, data)) = pes {
+                    self.pending.insert(pid, (pts, payload[data..].to_vec()));
+                }
+            }
+        } else if let Some((_, data)) = self.pending.get_mut(&pid) {
+            data.extend_from_slice(payload);
+        }
+    }
+
+    fn pat(&mut self, payload: &[u8]) {
+        let Some(section) = section(payload) else {
+            return;
+        };
+        for entry in section.get(8..).unwrap_or_default().chunks_exact(4) {
+            let program = u16::from_be_bytes([entry[0], entry[1]]);
+            let pid = u16::from_be_bytes([entry[2], entry[3]]) & 0x1fff;
+            if program != 0 && !self.pmt_pids.contains(&pid) {
+                self.pmt_pids.push(pid);
+            }
+        }
+    }
+
+    fn pmt(&mut self, payload: &[u8]) {
+        let Some(section) = section(payload) else {
+            return;
+        };
+        let Some(&[hi, lo]) = section.get(10..12) else {
+            return;
+        };
+        let mut i = 12 + ((usize::from(hi & 0x0f) << 8) | usize::from(lo));
+        while let Some(entry) = section.get(i..i + 5) {
+            let pid = u16::from_be_bytes([entry[1], entry[2]]) & 0x1fff;
+            if entry[0] == METADATA_STREAM_TYPE && !self.metadata_pids.contains(&pid) {
+                self.metadata_pids.push(pid);
+            }
+            i += 5 + ((usize::from(entry[3] & 0x0f) << 8) | usize::from(entry[4]));
+        }
+    }
+
+    /// 一个元数据 PES 收齐后解析其中的 ID3 标签
+    fn flush(&mut self, pid: u16) {
+        let Some((pts, data)) = self.pending.remove(&pid) else {
+            return;
+        };
+        let frames = parse_tag(&data);
+        if frames.is_empty() {
+            return;
+        }
+        let time = match (pts, self.base) {
+            (Some(pts), Some(base)) => since(base, pts) as f64 / PTS_HZ,
+            _ => self.duration(),
+        };
+        self.cues.push(Cue { time, frames });
+    }
+}
+
+/// `pts` 相对 `base` 的偏移，处理 33 位回绕；早于 `base` (如 B 帧) 时为 0
+fn since(base: u64, pts: u64) -> u64 {
+    let elapsed = pts.wrapping_sub(base) & PTS_MASK;
+    if elapsed > PTS_MASK / 2 { 0 } else { elapsed }
+}
+
+/// 同步字节错位时逐字节寻找下一个 0x47，返回是否找到完整的包
+fn resync(reader: &mut impl Read, packet: &mut [u8; PACKET_SIZE]) -> Result<bool> {
+    let mut byte = [0u8; 1];
+    loop {
+        match reader.read_exact(&mut byte) {
+            Ok(()) if byte[0] == SYNC_BYTE => break,
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(false),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    packet[0] = SYNC_BYTE;
+    match reader.read_exact(&mut packet[1..]) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// 去掉 pointer_field，返回到 CRC 之前的 PSI 段
+fn section(payload: &[u8]) -> Option<&[u8]> {
+    let pointer = usize::from(*payload.first()?);
+    let section = payload.get(1 + pointer..)?;
+    let length = (usize::from(section.get(1)? & 0x0f) << 8) | usize::from(*section.get(2)?);
+    section.get(..(3 + length).checked_sub(4)?)
+}
+
+/// 解析 PES 头，返回 (PTS, 负载起点)
+fn pes_header(payload: &[u8]) -> Option<(Option<u64>, usize)> {
+    if payload.get(..3)? != [0, 0, 1] {
+        return None;
+    }
+    let flags = *payload.get(7)?;
+    let header_len = usize::from(*payload.get(8)?);
+    let pts = if flags & 0x80 != 0 {
+        let p = payload.get(9..14)?;
+        Some(
+            ((u64::from(p[0] >> 1) & 0x07) << 30)
+                | (u64::from(p[1]) << 22)
+                | (u64::from(p[2] >> 1) << 15)
+                | (u64::from(p[3]) << 7)
+                | u64::from(p[4] >> 1),
+        )
+    } else {
+        None
+    };
+    Some((pts, 9 + header_len))
+}
+
+/// 解析 ID3v2.3 / v2.4 标签中的文本、链接、注释与可读的 PRIV 帧
+fn parse_tag(data: &[u8]) -> Vec<(String, String)> {
+    let mut frames = Vec::new();
+    let mut rest = data;
+    while rest.len() >= 10 && &rest[..3] == b"ID3" {
+        let version = rest[3];
+        let flags = rest[5];
+        let size = syncsafe(&rest[6..10]);
+        let Some(body) = rest.get(10..10 + size) else {
+            break;
+        };
+        rest = &rest[10 + size..];
+        if !(3..=4).contains(&version) {
+            continue;
+        }
+
+        let mut pos = 0;
+        if flags & 0x40 != 0 && body.len() >= 4 {
+            pos = if version == 4 {
+                syncsafe(&body[..4])
+            } else {
+                be32(&body[..4]) + 4
+            };
+        }
+        while let Some(header) = body.get(pos..pos + 10) {
+            if header[0] == 0 {
+                break;
+            }
+            let id = String::from_utf8_lossy(&header[..4]).into_owned();
+            let size = if version == 4 {
+                syncsafe(&header[4..8])
+            } else {
+                be32(&header[4..8])
+            };
+            let Some(content) = body.get(pos + 10..pos + 10 + size) else {
+                break;
+            };
+            pos += 10 + size;
+            if let Some(frame) = decode_frame(&id, content) {
+                frames.push(frame);
+            }
+        }
+    }
+    frames
+}
+
+fn decode_frame(id: &str, content: &[u8]) -> Option<(String, String)> {
+    let frame = match id {
+        "TXXX" | "WXXX" => {
+            let (&encoding, rest) = content.split_first()?;
+            let (description, value) = split_terminated(encoding, rest);
+            let value = if id == "WXXX" {
+                latin1(value)
+            } else {
+                text(encoding, value)
+            };
+            (format!("{}:{}", id, text(encoding, description)), value)
+        }
+        "COMM" => {
+            let (&encoding, rest) = content.split_first()?;
+            let (_, value) = split_terminated(encoding, rest.get(3..)?);
+            (id.to_string(), text(encoding, value))
+        }
+        "PRIV" => {
+            let end = content.iter().position(|&b| b == 0)?;
+            let value = std::str::from_utf8(&content[end + 1..]).ok()?;
+            if value.chars().any(char::is_control) {
+                return None;
+            }
+            (
+                format!("PRIV:{}", latin1(&content[..end])),
+                value.to_string(),
+            )
+        }
+        _ if id.starts_with('T') => {
+            let (&encoding, rest) = content.split_first()?;
+            (id.to_string(), text(encoding, rest))
+        }
+        _ if id.starts_with('W') => (id.to_string(), latin1(content)),
+        _ => return None,
+    };
+    Some(frame).filter(|(_, value)| !value.is_empty())
+}
+
+/// 按编码拆出以 NUL 结尾的描述和之后的内容
+fn split_terminated(encoding: u8, data: &[u8]) -> (&[u8], &[u8]) {
+    let end = if matches!(encoding, 1 | 2) {
+        (0..data.len() / 2)
+            .map(|i| i * 2)
+            .find(|&i| data[i] == 0 && data[i + 1] == 0)
+            .map(|i| (i, i + 2))
+    } else {
+        data.iter().position(|&b| b == 0).map(|i| (i, i + 1))
+    };
+    match end {
+        Some((end, next)) => (&data[..end], &data[next..]),
+        None => (data, &[]),
+    }
+}
+
+/// 按 ID3 文本编码解码；v2.4 的多个值以 NUL 分隔，合并为 ` / `
+fn text(encoding: u8, data: &[u8]) -> String {
+    let decoded = match encoding {
+        1 | 2 => {
+            let mut units: Vec<u16> = data
+                .chunks_exact(2)
+                .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                .collect();
+            // UTF-16 带 BOM，按大端读出 0xFFFE 时为小端
+            if units.first() == Some(&0xfffe) {
+                units.iter_mut().for_each(|u| *u = u.swap_bytes());
+            }
+            String::from_utf16_lossy(&units)
+                .trim_start_matches('\u{feff}')
+                .to_string()
+        }
+        3 => String::from_utf8_lossy(data).into_owned(),
+        // 不能用 `latin1`，它在第一个 NUL 处截断，会丢掉之后的值
+        _ => data.iter().map(|&b| char::from(b)).collect(),
+    };
+    decoded
+        .split('\0')
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join(" / ")
+}
+
+fn latin1(data: &[u8]) -> String {
+    data.iter()
+        .take_while(|&&b| b != 0)
+        .map(|&b| char::from(b))
+        .collect()
+}
+
+fn syncsafe(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .fold(0, |acc, &b| (acc << 7) | usize::from(b & 0x7f))
+}
+
+fn be32(bytes: &[u8]) -> usize {
+    bytes.iter().fold(0, |acc, &b| (acc << 8) | usize::from(b))
+}
+
+/// 以 TIT2 (标题) 划分音轨的 CUE 表，TPE1 作为演唱者；没有标题的标签不单独成轨
+fn cue_sheet(output: &Path, cues: &[Cue]) -> String {
+    let name = output
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut sheet = format!("FILE \"{}\" WAVE\n", name.replace('"', "'"));
+    let mut track = 0;
+    for cue in cues {
+        let frame = |id: &str| {
+            cue.frames
+                .iter()
+                .find(|(key, _)| key == id)
+                .map(|(_, v)| v.replace('"', "'"))
+        };
+        let Some(title) = frame("TIT2") else {
+            continue;
+        };
+        track += 1;
+        let _ = writeln!(sheet, "  TRACK {:02} AUDIO", track);
+        let _ = writeln!(sheet, "    TITLE \"{}\"", title);
+        if let Some(performer) = frame("TPE1") {
+            let _ = writeln!(sheet, "    PERFORMER \"{}\"", performer);
+        }
+        // CUE 的时间单位为 1/75 秒
+        let frames = (cue.time * 75.0).round() as u64;
+        let _ = writeln!(
+            sheet,
+            "    INDEX 01 {:02}:{:02}:{:02}",
+            frames / 75 / 60,
+            frames / 75 % 60,
+            frames % 75
+        );
+    }
+    if track == 0 {
+        warn!("{}", tr!("ID3 标签中没有标题 (TIT2)，CUE 表不含音轨"));
+    }
+    sheet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// ID3v2 帧：v2.4 的帧长度为 syncsafe 整数，v2.3 为普通大端整数
+    fn frame(version: u8, id: &str, content: &[u8]) -> Vec<u8> {
+        let len = content.len() as u32;
+        let size = if version == 4 {
+            [
+                (len >> 21) & 0x7f,
+                (len >> 14) & 0x7f,
+                (len >> 7) & 0x7f,
+                len & 0x7f,
+            ]
+            .map(|b| b as u8)
+        } else {
+            len.to_be_bytes()
+        };
+        let mut out = id.as_bytes().to_vec();
+        out.extend(size);
+        out.extend([0, 0]);
+        out.extend(content);
+        out
+    }
+
+    fn tag(version: u8, frames: &[Vec<u8>]) -> Vec<u8> {
+        let body: Vec<u8> = frames.concat();
+        let len = body.len() as u32;
+        let mut out = b"ID3".to_vec();
+        out.extend([version, 0, 0]);
+        out.extend(
+            [
+                (len >> 21) & 0x7f,
+                (len >> 14) & 0x7f,
+                (len >> 7) & 0x7f,
+                len & 0x7f,
+            ]
+            .map(|b| b as u8),
+        );
+        out.extend(body);
+        out
+    }
+
+    /// 只有负载的 TS 包，不足 184 字节时用 0xff 填满
+    fn packet(pid: u16, start: bool, payload: &[u8]) -> Vec<u8> {
+        let mut out = vec![
+            SYNC_BYTE,
+            (u8::from(start) << 6) | (pid >> 8) as u8,
+            pid as u8,
+            0x10,
+        ];
+        out.extend(payload);
+        out.resize(PACKET_SIZE, 0xff);
+        out
+    }
+
+    fn pat(pmt_pid: u16) -> Vec<u8> {
+        let mut section = vec![0, 0x00, 0xb0, 13, 0, 1, 0xc1, 0, 0, 0, 1];
+        section.extend([0xe0 | (pmt_pid >> 8) as u8, pmt_pid as u8]);
+        section.extend([0; 4]);
+        packet(0, true, &section)
+    }
+
+    fn pmt(pmt_pid: u16, metadata_pid: u16) -> Vec<u8> {
+        let mut section = vec![0, 0x02, 0xb0, 18, 0, 1, 0xc1, 0, 0, 0xe1, 0x00, 0xf0, 0];
+        section.extend([
+            METADATA_STREAM_TYPE,
+            0xe0 | (metadata_pid >> 8) as u8,
+            metadata_pid as u8,
+            0xf0,
+            0,
+        ]);
+        section.extend([0; 4]);
+        packet(pmt_pid, true, &section)
+    }
+
+    fn pes(pid: u16, pts: u64, data: &[u8]) -> Vec<u8> {
+        let mut payload = vec![0, 0, 1, 0xbd, 0, 0, 0x80, 0x80, 5];
+        payload.extend([
+            0x21 | ((pts >> 29) & 0x0e) as u8,
+            (pts >> 22) as u8,
+            ((pts >> 14) & 0xfe) as u8 | 1,
+            (pts >> 7) as u8,
+            ((pts << 1) & 0xfe) as u8 | 1,
+        ]);
+        payload.extend(data);
+        packet(pid, true, &payload)
+    }
+
+    #[test]
+    fn parses_v24_text_and_user_frames() {
+        let data = tag(
+            4,
+            &[
+                frame(4, "TIT2", b"\x03Song"),
+                frame(4, "TXXX", b"\x03StreamTitle\0Artist - Song"),
+                frame(4, "WOAR", b"https://artist.example"),
+                frame(4, "APIC", b"\x00image/png\0..."),
+            ],
+        );
+        assert_eq!(
+            parse_tag(&data),
+            [
+                ("TIT2".to_string(), "Song".to_string()),
+                ("TXXX:StreamTitle".to_string(), "Artist - Song".to_string()),
+                ("WOAR".to_string(), "https://artist.example".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_v23_utf16_and_multiple_values() {
+        // UTF-16 小端带 BOM
+        let mut title = vec![1, 0xff, 0xfe];
+        title.extend("曲目".encode_utf16().flat_map(u16::to_le_bytes));
+        let data = tag(
+            3,
+            &[
+                frame(3, "TIT2", &title),
+                frame(3, "TPE1", b"\x00A\0B"),
+                frame(3, "COMM", b"\x00engdesc\0hello"),
+            ],
+        );
+        assert_eq!(
+            parse_tag(&data),
+            [
+                ("TIT2".to_string(), "曲目".to_string()),
+                ("TPE1".to_string(), "A / B".to_string()),
+                ("COMM".to_string(), "hello".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_truncated_and_unsupported_tags() {
+        let mut data = tag(4, &[frame(4, "TIT2", b"\x03Song")]);
+        data.truncate(data.len() - 2);
+        assert!(parse_tag(&data).is_empty());
+        let v2 = tag(2, &[frame(4, "TIT2", b"\x03Song")]);
+        assert!(parse_tag(&v2).is_empty());
+        assert!(parse_tag(b"not a tag").is_empty());
+    }
+
+    #[test]
+    fn demuxer_collects_cues_with_relative_time() {
+        let first = tag(4, &[frame(4, "TIT2", b"\x03One")]);
+        let second = tag(4, &[frame(4, "TIT2", b"\x03Two")]);
+        let stream = [
+            pat(0x100),
+            pmt(0x100, 0x102),
+            pes(0x102, 90_000, &first),
+            // 其他流的 PES 只用来推算时长
+            pes(0x101, 90_000 * 2, &[]),
+            pes(0x102, 90_000 * 3, &second),
+        ]
+        .concat();
+        let mut demuxer = Demuxer::default();
+        demuxer.scan(stream.as_slice()).unwrap();
+        assert_eq!(
+            demuxer.cues,
+            [
+                Cue {
+                    time: 0.0,
+                    frames: vec![("TIT2".into(), "One".into())],
+                },
+                Cue {
+                    time: 2.0,
+                    frames: vec![("TIT2".into(), "Two".into())],
+                },
+            ]
+        );
+        assert_eq!(demuxer.duration(), 2.0);
+    }
+
+    #[test]
+    fn demuxer_skips_non_ts_input() {
+        let mut demuxer = Demuxer::default();
+        demuxer.scan(&[0u8; PACKET_SIZE * 2][..]).unwrap();
+        assert!(demuxer.cues.is_empty());
+    }
+
+    #[test]
+    fn since_handles_wraparound_and_earlier_pts() {
+        assert_eq!(since(PTS_MASK - 99, 50), 150);
+        assert_eq!(since(1_000, 900), 0);
+        assert_eq!(since(1_000, 91_000), 90_000);
+    }
+
+    #[test]
+    fn cue_sheet_splits_tracks_by_title() {
+        let cues = [
+            Cue {
+                time: 0.0,
+                frames: vec![("TIT2".into(), "Intro".into())],
+            },
+            Cue {
+                time: 30.0,
+                frames: vec![("TXXX:StreamTitle".into(), "no title".into())],
+            },
+            Cue {
+                time: 61.2,
+                frames: vec![
+                    ("TIT2".into(), "Say \"hi\"".into()),
+                    ("TPE1".into(), "Band".into()),
+                ],
+            },
+        ];
+        let sheet = cue_sheet(Path::new("out/show.m4a"), &cues);
+        assert_eq!(
+            sheet,
+            "FILE \"show.m4a\" WAVE\n\
+             \x20 TRACK 01 AUDIO\n\
+             \x20   TITLE \"Intro\"\n\
+             \x20   INDEX 01 00:00:00\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   TITLE \"Say 'hi'\"\n\
+             \x20   PERFORMER \"Band\"\n\
+             \x20   INDEX 01 01:01:15\n"
+        );
+    }
+}
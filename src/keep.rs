@@ -0,0 +1,102 @@
+use anyhow::{Context, Result};
+use m3u8_rs::MediaSegment;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tracing::info;
+
+use crate::Job;
+use crate::i18n::tr;
+
+/// `index.json` 中的一个切片
+#[derive(Serialize)]
+struct Entry {
+    index: usize,
+    sequence: u64,
+    /// 保留下来的切片文件，空缺切片为空
+    file: Option<String>,
+    uri: String,
+    duration: f32,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    discontinuity: bool,
+    /// 切片所用的初始化段 (fMP4)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    init: Option<String>,
+}
+
+/// `--keep-segments`：合并时把解密后的切片移到 `<目录>/<输出名>/`，而不是删除。
+/// 切片按播放列表顺序以补零的序号命名 (`00001.ts`，fMP4 为 `.m4s`，初始化段为 `init_1.mp4`)，
+/// 结束时写出 `index.json`，记录每个切片的媒体序列号、原地址与时长
+pub struct Keeper {
+    dir: PathBuf,
+    width: usize,
+    entries: Vec<Entry>,
+}
+
+impl Keeper {
+    pub async fn create(
+        root: &Path,
+        job: &Job,
+        segments: &[MediaSegment],
+        sequences: &[u64],
+        inits: &[(&m3u8_rs::Map, Vec<u8>)],
+        init_of: &[Option<usize>],
+    ) -> Result<Self> {
+        let stem = job.output.file_stem().unwrap_or_default();
+        let dir = root.join(stem);
+        fs::create_dir_all(&dir)
+            .await
+            .with_context(|| tr!("无法创建切片保留目录: {}", dir.display()))?;
+        for (n, (_, data)) in inits.iter().enumerate() {
+            fs::write(dir.join(init_name(n)), data).await?;
+        }
+        let entries = segments
+            .iter()
+            .enumerate()
+            .map(|(i, seg)| Entry {
+                index: i,
+                sequence: sequences[i],
+                file: None,
+                uri: seg.uri.clone(),
+                duration: seg.duration,
+                discontinuity: seg.discontinuity,
+                init: init_of[i].map(init_name),
+            })
+            .collect();
+        Ok(Self {
+            dir,
+            width: segments.len().to_string().len().max(5),
+            entries,
+        })
+    }
+
+    /// 把第 `index` 个切片的临时文件移到保留目录；`data` 为其内容，无法直接移动时写入副本
+    pub async fn keep(&mut self, index: usize, tmp: &Path, data: &[u8]) -> Result<()> {
+        let entry = &mut self.entries[index];
+        let ext = if entry.init.is_some() { "m4s" } else { "ts" };
+        let name = format!("{:0width$}.{}", index + 1, ext, width = self.width);
+        let path = self.dir.join(&name);
+        if fs::rename(tmp, &path).await.is_err() {
+            fs::write(&path, data)
+                .await
+                .with_context(|| tr!("无法保留切片: {}", path.display()))?;
+            let _ = fs::remove_file(tmp).await;
+        }
+        entry.file = Some(name);
+        Ok(())
+    }
+
+    pub async fn finish(self) -> Result<()> {
+        let path = self.dir.join("index.json");
+        fs::write(&path, serde_json::to_vec_pretty(&self.entries)?)
+            .await
+            .with_context(|| tr!("无法写入切片索引: {}", path.display()))?;
+        let kept = self.entries.iter().filter(|e| e.file.is_some()).count();
+        info!("{}", tr!("已保留 {} 个切片: {}", kept, self.dir.display()));
+        Ok(())
+    }
+}
+
+fn init_name(n: usize) -> String {
+    format!("init_{}.mp4", n + 1)
+}
@@ -0,0 +1,297 @@
+use anyhow::{Context, Result, bail};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use futures::future;
+use m3u8_rs::{Key, KeyMethod};
+use reqwest::Client;
+use reqwest::header::HeaderMap;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::OnceCell;
+use tracing::{debug, info, warn};
+use url::Url;
+
+use crate::i18n::tr;
+use crate::query::Inherited;
+use crate::{RetryPolicy, Shared, retry_backoff};
+
+/// AES-128 密钥与 IV 的长度
+const KEY_LEN: usize = 16;
+
+/// 通过 `--key`/`--iv` 手动提供的解密参数，指定后不再请求 EXT-X-KEY 中的密钥
+pub struct ManualKey {
+    key: Vec<u8>,
+    iv: Option<Vec<u8>>,
+}
+
+impl ManualKey {
+    /// 解析 `--key <hex|base64|@文件>` 与 `--iv <hex>`，未指定 `--key` 时返回 None
+    pub fn from_args(key: Option<&str>, iv: Option<&str>) -> Result<Option<Self>> {
+        let Some(key) = key else {
+            return Ok(None);
+        };
+        let key = match key.strip_prefix('@') {
+            Some(path) => {
+                let bytes =
+                    std::fs::read(path).with_context(|| tr!("无法读取密钥文件: {}", path))?;
+                // 密钥文件可以是 16 字节的原始密钥，也可以是 hex/base64 文本
+                if bytes.len() == KEY_LEN {
+                    bytes
+                } else {
+                    decode_key(String::from_utf8_lossy(&bytes).trim())?
+                }
+            }
+            None => decode_key(key)?,
+        };
+        let iv = iv.map(decode_iv).transpose()?;
+        Ok(Some(Self { key, iv }))
+    }
+
+    /// 切片的解密参数：`--iv` 优先，其次是播放列表中的 IV，最后按规范使用媒体序列号
+    pub fn material(&self, playlist_iv: Option<&str>, seq: u64) -> Result<(Vec<u8>, Vec<u8>)> {
+        let iv = match (&self.iv, playlist_iv) {
+            (Some(iv), _) => iv.clone(),
+            (None, Some(iv)) => decode_iv(iv)?,
+            (None, None) => sequence_iv(seq),
+        };
+        Ok((self.key.clone(), iv))
+    }
+}
+
+/// 已获取的密钥，按密钥地址缓存：每个地址只请求一次，并发请求同一地址时等待同一次结果。
+/// 指定目录时同时持久化到磁盘，下次运行直接复用，减少对限流密钥服务器的请求
+pub struct KeyCache {
+    entries: Mutex<HashMap<String, Arc<OnceCell<Vec<u8>>>>>,
+    dir: Option<PathBuf>,
+    /// `--key-header`：只附加在密钥请求上的请求头
+    headers: HeaderMap,
+}
+
+impl KeyCache {
+    pub fn new(dir: Option<PathBuf>, headers: HeaderMap) -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            dir,
+            headers,
+        }
+    }
+
+    pub async fn get(&self, client: &Client, url: &Url) -> Result<Vec<u8>> {
+        let cell = self
+            .entries
+            .lock()
+            .unwrap()
+            .entry(url.to_string())
+            .or_default()
+            .clone();
+        cell.get_or_try_init(|| self.load(client, url))
+            .await
+            .cloned()
+    }
+
+    async fn load(&self, client: &Client, url: &Url) -> Result<Vec<u8>> {
+        let path = self.path(url);
+        let cached = match &path {
+            Some(path) => tokio::fs::read(path).await.ok(),
+            None => None,
+        };
+        if let Some(bytes) = cached.filter(|b| b.len() == KEY_LEN) {
+            return Ok(bytes);
+        }
+
+        let response = client
+            .get(url.clone())
+            .headers(self.headers.clone())
+            .send()
+            .await?;
+        debug!("HTTP {} {}", response.status(), url);
+        let bytes = response
+            .error_for_status()?
+            .bytes()
+            .await
+            .with_context(|| tr!("密钥下载失败: {}", url))?;
+        if bytes.len() != KEY_LEN {
+            bail!(tr!(
+                "密钥应为 {} 字节，实际为 {} 字节: {}",
+                KEY_LEN,
+                bytes.len(),
+                url
+            ));
+        }
+        info!("{}", tr!("已获取密钥: {}", url));
+
+        let saved = match &path {
+            Some(path) => save(path, &bytes).await,
+            None => Ok(()),
+        };
+        if let Err(e) = saved {
+            warn!("{:#}", e);
+        }
+        Ok(bytes.to_vec())
+    }
+
+    /// 磁盘缓存文件名为密钥地址的 SHA-256
+    fn path(&self, url: &Url) -> Option<PathBuf> {
+        let dir = self.dir.as_ref()?;
+        Some(dir.join(hex::encode(Sha256::digest(url.as_str().as_bytes()))))
+    }
+
+    /// 丢弃所有已缓存的密钥 (包括磁盘上的)，之后重新向服务器请求
+    pub async fn clear(&self) {
+        let urls: Vec<String> = self
+            .entries
+            .lock()
+            .unwrap()
+            .drain()
+            .map(|(k, _)| k)
+            .collect();
+        for url in urls {
+            if let Some(path) = Url::parse(&url).ok().and_then(|u| self.path(&u)) {
+                let _ = tokio::fs::remove_file(path).await;
+            }
+        }
+    }
+}
+
+async fn save(path: &Path, bytes: &[u8]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    tokio::fs::write(path, bytes)
+        .await
+        .with_context(|| tr!("无法写入密钥缓存: {}", path.display()))
+}
+
+/// 切片的解密参数 (密钥, IV)，未加密时返回 None。
+/// 指定了 `--key` 时用手动密钥代替请求密钥地址，只对 AES-128 加密的切片生效；
+/// 未提供 IV 时按规范使用媒体序列号
+pub async fn resolve(
+    shared: &Shared,
+    base: Option<&Url>,
+    query: &Inherited,
+    key: Option<&Key>,
+    seq: u64,
+) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+    let Some(key) = key else {
+        return Ok(None);
+    };
+    match &key.method {
+        KeyMethod::None => return Ok(None),
+        KeyMethod::AES128 => {}
+        other => bail!(tr!("不支持的加密方式: {}", format!("{:?}", other))),
+    }
+    if let Some(manual) = &shared.manual_key {
+        return manual.material(key.iv.as_deref(), seq).map(Some);
+    }
+
+    let bytes = shared
+        .keys
+        .get(&shared.client, &key_url(base, query, key)?)
+        .await?;
+
+    let iv = match &key.iv {
+        Some(iv) => decode_iv(iv)?,
+        None => sequence_iv(seq),
+    };
+    Ok(Some((bytes, iv)))
+}
+
+/// 并发预取所有不同的密钥 (按解析后的地址去重，带重试)，返回密钥数量。
+/// 密钥服务器的问题在下载切片前就会暴露，轮换的密钥也不会阻塞切片下载
+pub async fn prefetch<'a>(
+    shared: &Shared,
+    base: Option<&Url>,
+    query: &Inherited,
+    keys: impl IntoIterator<Item = &'a Key>,
+    policy: &RetryPolicy,
+) -> Result<usize> {
+    if shared.manual_key.is_some() {
+        return Ok(0);
+    }
+    let mut urls: Vec<Url> = Vec::new();
+    for key in keys {
+        if !matches!(key.method, KeyMethod::AES128) {
+            continue;
+        }
+        let url = key_url(base, query, key)?;
+        if !urls.contains(&url) {
+            urls.push(url);
+        }
+    }
+    future::try_join_all(urls.iter().map(|url| fetch_with_retry(shared, url, policy))).await?;
+    Ok(urls.len())
+}
+
+async fn fetch_with_retry(shared: &Shared, url: &Url, policy: &RetryPolicy) -> Result<()> {
+    let mut last_error = None;
+    for attempt in 1..=policy.retries {
+        match shared.keys.get(&shared.client, url).await {
+            Ok(_) => return Ok(()),
+            Err(e) => {
+                warn!("{}", tr!("第{}次获取密钥失败: {:#}", attempt, e));
+                last_error = Some(e);
+            }
+        }
+        if attempt < policy.retries {
+            tokio::time::sleep(retry_backoff(attempt, policy.delay, policy.max_delay)).await;
+        }
+    }
+    let message = tr!("重试{}次后仍无法获取密钥: {}", policy.retries, url);
+    Err(match last_error {
+        Some(e) => e.context(message),
+        None => anyhow::anyhow!(message),
+    })
+}
+
+fn key_url(base: Option<&Url>, query: &Inherited, key: &Key) -> Result<Url> {
+    let uri = key.uri.as_deref().context(tr!("EXT-X-KEY 缺少 URI"))?;
+    let url = match base {
+        Some(base) => base.join(uri)?,
+        None => Url::parse(uri).with_context(|| tr!("密钥地址不是完整 URL: {}", uri))?,
+    };
+    Ok(query.apply(url))
+}
+
+/// 解析 hex (可带 0x 前缀) 或 base64 编码的 16 字节密钥
+fn decode_key(text: &str) -> Result<Vec<u8>> {
+    let hex_text = strip_hex_prefix(text);
+    let key = if hex_text.len() == KEY_LEN * 2 && hex_text.chars().all(|c| c.is_ascii_hexdigit()) {
+        hex::decode(hex_text)?
+    } else {
+        STANDARD
+            .decode(text)
+            .context(tr!("密钥既不是 32 位 hex 也不是有效的 base64"))?
+    };
+    if key.len() != KEY_LEN {
+        bail!(tr!(
+            "AES-128 密钥应为 {} 字节，实际为 {} 字节",
+            KEY_LEN,
+            key.len()
+        ));
+    }
+    Ok(key)
+}
+
+/// 解析 hex 编码的 16 字节 IV
+pub fn decode_iv(text: &str) -> Result<Vec<u8>> {
+    let iv = hex::decode(strip_hex_prefix(text)).context(tr!("IV hex 解析失败"))?;
+    if iv.len() != KEY_LEN {
+        bail!(tr!("IV 应为 {} 字节，实际为 {} 字节", KEY_LEN, iv.len()));
+    }
+    Ok(iv)
+}
+
+/// 播放列表未给出 IV 时，以媒体序列号的大端表示作为 IV
+pub fn sequence_iv(seq: u64) -> Vec<u8> {
+    let mut iv = vec![0u8; KEY_LEN];
+    iv[8..].copy_from_slice(&seq.to_be_bytes());
+    iv
+}
+
+fn strip_hex_prefix(text: &str) -> &str {
+    text.trim()
+        .trim_start_matches("0x")
+        .trim_start_matches("0X")
+}
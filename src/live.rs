@@ -0,0 +1,770 @@
+use anyhow::{Result, bail};
+use clap::ValueEnum;
+use futures::future;
+use futures::stream::{self, StreamExt};
+use indicatif::ProgressBar;
+use m3u8_rs::{Key, MediaPlaylist, MediaSegment, Playlist, parse_playlist};
+use std::fmt;
+use std::fs::File;
+use std::io::Write;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+use tracing::{Instrument, debug, info, info_span, warn};
+use url::Url;
+
+use crate::ads::AdBreaks;
+use crate::chapters::{Chapter, ChapterBuilder};
+use crate::control;
+use crate::events::JobEvent;
+use crate::i18n::tr;
+use crate::keys;
+use crate::llhls::{self, LowLatency};
+use crate::manifest::TimelineEntry;
+use crate::query::Inherited;
+use crate::recovery;
+use crate::source::{HttpSource, SegmentSource};
+use crate::stop::Limits;
+use crate::ui::{Icon, Stage, Ui};
+use crate::{
+    Args, Job, RetryPolicy, Shared, check_segment, decrypt_segment, download_playlist,
+    fetch_segment_with_retry, is_gap,
+};
+
+/// 首次加入直播时从距离直播边缘多少个切片开始录制
+const LIVE_EDGE_SEGMENTS: u64 = 3;
+
+/// 网络中断时重新获取播放列表的间隔
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// `--dvr-start`：首次加入直播时从哪里开始录制
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum DvrStart {
+    /// 从直播边缘附近开始
+    Edge,
+    /// 先录下回看窗口中现有的全部切片，再继续跟随直播边缘
+    Earliest,
+}
+
+/// 可供切换的变体流，按带宽从高到低排列
+#[derive(Clone)]
+pub struct VariantLadder {
+    pub variants: Vec<(u64, Url)>,
+    pub current: usize,
+}
+
+pub struct Recording {
+    pub duration: f64,
+    pub timeline: Vec<TimelineEntry>,
+    pub chapters: Vec<Chapter>,
+}
+
+/// 一路录制：一个变体流写入一个输出
+struct Track<'a> {
+    job: &'a Job,
+    ladder: VariantLadder,
+    output: File,
+    next_seq: Option<u64>,
+    recorded: f64,
+    written: u64,
+    /// 已写入合并文件的字节数
+    bytes: u64,
+    behind: u32,
+    timeline: Vec<TimelineEntry>,
+    chapters: ChapterBuilder,
+    /// `--skip-ads` 时的广告判断，以及已经判断过的序列号 (不含)
+    ads: Option<AdBreaks>,
+    observed: Option<u64>,
+    /// LL-HLS：正在录制的切片及其已写入的分片数
+    partial: Option<(u64, usize)>,
+    /// LL-HLS：下次阻塞式刷新等待的 (_HLS_msn, _HLS_part)
+    block_at: Option<(u64, usize)>,
+    /// 下次刷新请求增量更新 (EXT-X-SKIP)
+    skip: bool,
+    /// 出现过的密钥标签 (序列号, 密钥)，增量更新省略了较早切片上的密钥标签
+    key_changes: Vec<(u64, Key)>,
+    /// LL-HLS：按 EXT-X-PRELOAD-HINT 提前发出的分片请求
+    hinted: Option<(Url, JoinHandle<Result<Vec<u8>>>)>,
+    /// 网络中断的开始时刻，重新连接后清除
+    offline: Option<Instant>,
+    /// 因网络中断或跟不上直播窗口而丢失的切片数
+    missed: u64,
+    ended: bool,
+    pb: ProgressBar,
+}
+
+/// 一次下载并写入的内容：整个切片，或 LL-HLS 切片中的一个分片
+struct Piece {
+    seq: u64,
+    seg: MediaSegment,
+    key: Option<Key>,
+    /// 分片在切片中的下标，以及它是否是该切片的最后一个分片
+    part: Option<(usize, bool)>,
+}
+
+impl Piece {
+    fn part(seq: u64, part: &llhls::Part, key: Option<&Key>, index: usize, last: bool) -> Self {
+        Self {
+            seq,
+            seg: MediaSegment {
+                uri: part.uri.clone(),
+                duration: part.duration as f32,
+                ..MediaSegment::default()
+            },
+            key: key.cloned(),
+            part: Some((index, last)),
+        }
+    }
+}
+
+/// 一轮刷新的结果
+struct Step {
+    new_segments: bool,
+    switched: bool,
+    target: u64,
+    /// LL-HLS 的 PART-TARGET，有值时按分片时长刷新
+    part_target: Option<f64>,
+    /// 下次刷新由服务器阻塞到新分片可用，无需等待
+    blocking: bool,
+    /// 增量更新省略了尚未录制的切片，需要立即重新获取完整的播放列表
+    reload: bool,
+}
+
+/// 跟随直播播放列表持续录制，按媒体序列号顺序直接追加到合并文件，
+/// 直到出现 EXT-X-ENDLIST、满足停止条件或收到 Ctrl-C。
+/// 可同时录制多路变体：每轮一起刷新各自的播放列表，共用连接池与并发额度
+pub async fn record(
+    sources: Vec<(&Job, VariantLadder)>,
+    args: &Args,
+    shared: &Shared,
+    ui: &Ui,
+) -> Result<Vec<Recording>> {
+    let mut tracks = Vec::with_capacity(sources.len());
+    for (job, ladder) in sources {
+        tracks.push(Track {
+            job,
+            ladder,
+            output: File::create(job.merged_ts())?,
+            next_seq: None,
+            recorded: 0.0,
+            written: 0,
+            bytes: 0,
+            behind: 0,
+            timeline: Vec::new(),
+            chapters: ChapterBuilder::default(),
+            ads: args.skip_ads.then(|| AdBreaks::new(&args.ad_class)),
+            observed: None,
+            partial: None,
+            block_at: None,
+            skip: false,
+            key_changes: Vec::new(),
+            hinted: None,
+            offline: None,
+            missed: 0,
+            ended: false,
+            pb: ui.spinner(
+                Stage::Download,
+                tr!("录制直播流 {}...", format!("{:?}", job.output)),
+            )?,
+        });
+    }
+
+    let limits = Limits::new(args);
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    // 取消直播录制与 Ctrl-C 相同：停止录制，保留已录制的部分
+    let cancelled = shared.control.cancelled(tracks[0].job.id);
+    tokio::pin!(cancelled);
+
+    loop {
+        let results = future::join_all(tracks.iter_mut().map(|t| async move {
+            if t.ended {
+                return Ok(None);
+            }
+            let url = &t.ladder.variants[t.ladder.current].1;
+            let url = llhls::reload_url(url, t.block_at, t.skip);
+            // 播放列表可以随时重新获取，刷新失败都按网络中断处理
+            let (playlist, low) = fetch_media_playlist(url.as_str(), args)
+                .await
+                .map_err(|e| e.context(Offline))?;
+            t.step(playlist, low, args, shared, ui).await.map(Some)
+        }))
+        .await;
+
+        let mut steps = Vec::with_capacity(results.len());
+        let mut cancelled_now = false;
+        let mut offline_for = Duration::ZERO;
+        for (track, result) in tracks.iter_mut().zip(results) {
+            match result {
+                Ok(step) => {
+                    track.reconnected();
+                    steps.extend(step);
+                }
+                Err(e) if control::is_cancelled(&e) => cancelled_now = true,
+                Err(e) if e.is::<Offline>() || recovery::is_network(&e) => {
+                    offline_for = offline_for.max(track.disconnected(&e));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        if cancelled_now {
+            info!("{}", tr!("任务已取消，停止录制"));
+            break;
+        }
+        if !args.reconnect_timeout.is_zero() && offline_for > args.reconnect_timeout {
+            warn!(
+                "{}",
+                tr!(
+                    "网络中断超过 {:.0}s，停止录制，保留已录制的部分",
+                    offline_for.as_secs_f64()
+                )
+            );
+            break;
+        }
+
+        if tracks.iter().all(|t| t.ended) {
+            info!("{}", tr!("直播已结束 (EXT-X-ENDLIST)"));
+            break;
+        }
+        let recorded = tracks.iter().map(|t| t.recorded).fold(0.0, f64::max);
+        let bytes = tracks.iter().map(|t| t.bytes).max().unwrap_or(0);
+        if let Some(reason) = limits.reached(recorded, bytes) {
+            info!("{}", tr!("{}，停止录制", reason));
+            break;
+        }
+        if steps.iter().any(|s| s.switched || s.reload) {
+            continue;
+        }
+
+        let target = Duration::from_secs(steps.iter().map(|s| s.target).min().unwrap_or(1).max(1));
+        let wait = if steps.is_empty() {
+            RECONNECT_DELAY
+        } else if steps.iter().all(|s| s.blocking) {
+            Duration::ZERO
+        } else if let Some(part) = steps.iter().filter_map(|s| s.part_target).reduce(f64::min) {
+            Duration::from_secs_f64(part.max(0.1))
+        } else if steps.iter().any(|s| s.new_segments) {
+            target
+        } else {
+            target / 2
+        };
+        let wait = limits.until_deadline().map_or(wait, |left| wait.min(left));
+        tokio::select! {
+            biased;
+            _ = &mut ctrl_c => {
+                info!("{}", tr!("收到中断信号，停止录制"));
+                break;
+            }
+            _ = &mut cancelled => {
+                info!("{}", tr!("任务已取消，停止录制"));
+                break;
+            }
+            _ = tokio::time::sleep(wait) => {}
+        }
+    }
+
+    Ok(tracks
+        .into_iter()
+        .map(|t| {
+            if t.missed > 0 {
+                warn!(
+                    "{}",
+                    tr!(
+                        "{} {} 录制期间共丢失 {} 个切片，位置见清单中的 gap 事件",
+                        ui.icon(Icon::Warn),
+                        format!("{:?}", t.job.output),
+                        t.missed
+                    )
+                );
+            }
+            t.pb.finish_with_message(tr!(
+                "{} 直播录制结束 {}: {} 个切片, {:.0}s",
+                ui.icon(Icon::Ok),
+                format!("{:?}", t.job.output),
+                t.written,
+                t.recorded
+            ));
+            Recording {
+                duration: t.recorded,
+                timeline: t.timeline,
+                chapters: t.chapters.finish(),
+            }
+        })
+        .collect())
+}
+
+async fn fetch_media_playlist(url: &str, args: &Args) -> Result<(MediaPlaylist, LowLatency)> {
+    let content = download_playlist(url, args).await?;
+    match parse_playlist(&content) {
+        Ok((_, Playlist::MediaPlaylist(mp))) => {
+            Ok((mp, LowLatency::parse(&String::from_utf8_lossy(&content))))
+        }
+        Ok(_) => bail!(tr!("直播地址不是 Media Playlist")),
+        Err(e) => bail!(tr!("解析直播播放列表失败: {}", format!("{:?}", e))),
+    }
+}
+
+/// 刷新播放列表失败，附加在错误上，与切片下载的网络错误一样按中断处理
+#[derive(Debug)]
+struct Offline;
+
+impl fmt::Display for Offline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", tr!("刷新播放列表失败"))
+    }
+}
+
+impl Track<'_> {
+    /// 网络中断：保留录制进度，下一轮从下一个序列号接着录制。返回已经中断的时长
+    fn disconnected(&mut self, e: &anyhow::Error) -> Duration {
+        let since = match self.offline {
+            Some(since) => {
+                debug!(
+                    "{}",
+                    tr!("{} 仍未恢复连接: {:#}", format!("{:?}", self.job.output), e)
+                );
+                since
+            }
+            None => {
+                warn!(
+                    "{}",
+                    tr!(
+                        "{} 网络中断，每 {}s 重试一次: {:#}",
+                        format!("{:?}", self.job.output),
+                        RECONNECT_DELAY.as_secs(),
+                        e
+                    )
+                );
+                *self.offline.insert(Instant::now())
+            }
+        };
+        // 增量更新省略的切片可能包含中断期间错过的部分
+        self.skip = false;
+        self.block_at = None;
+        since.elapsed()
+    }
+
+    fn reconnected(&mut self) {
+        if let Some(since) = self.offline.take() {
+            info!(
+                "{}",
+                tr!(
+                    "{} 已重新连接，中断 {:.0}s",
+                    format!("{:?}", self.job.output),
+                    since.elapsed().as_secs_f64()
+                )
+            );
+            self.timeline.push(TimelineEntry {
+                at: self.recorded,
+                sequence: self.next_seq.unwrap_or(0),
+                event: "reconnect".to_string(),
+                detail: tr!("中断 {:.0}s", since.elapsed().as_secs_f64()),
+            });
+        }
+    }
+
+    /// 处理一次刷新得到的播放列表：下载新切片 (LL-HLS 时下载新分片)、检查吞吐并在需要时降档
+    async fn step(
+        &mut self,
+        playlist: MediaPlaylist,
+        low: LowLatency,
+        args: &Args,
+        shared: &Shared,
+        ui: &Ui,
+    ) -> Result<Step> {
+        let (bandwidth, media_url) = self.ladder.variants[self.ladder.current].clone();
+        // 增量更新中 m3u8-rs 给出的第一个切片实际位于 EXT-X-SKIP 省略的切片之后
+        let first_seq = playlist.media_sequence + low.skipped;
+        // 广告按整个切片判断，跳过广告时不按分片录制
+        let use_parts = low.is_enabled() && self.ads.is_none();
+        // 上一轮已写完该切片的全部分片
+        let finished = self.partial.filter(|&(seq, done)| {
+            seq < low.open_msn && low.parts(seq).is_some_and(|parts| parts.len() == done)
+        });
+        if let Some((seq, _)) = finished {
+            self.partial = None;
+            self.next_seq = Some(seq + 1);
+            self.written += 1;
+        }
+        let start = self.next_seq.unwrap_or_else(|| match args.dvr_start {
+            DvrStart::Earliest => {
+                info!(
+                    "{}",
+                    tr!(
+                        "{} 从回看窗口开头录制: {} 个切片, {:.0}s",
+                        format!("{:?}", self.job.output),
+                        playlist.segments.len(),
+                        playlist
+                            .segments
+                            .iter()
+                            .map(|s| f64::from(s.duration))
+                            .sum::<f64>()
+                    )
+                );
+                first_seq
+            }
+            DvrStart::Edge => {
+                first_seq + (playlist.segments.len() as u64).saturating_sub(LIVE_EDGE_SEGMENTS)
+            }
+        });
+        if low.skipped > 0 && start < first_seq {
+            warn!(
+                "{}",
+                tr!("增量更新省略了尚未录制的切片，重新获取完整的播放列表")
+            );
+            self.skip = false;
+            return Ok(Step {
+                new_segments: false,
+                switched: false,
+                target: playlist.target_duration,
+                part_target: None,
+                blocking: false,
+                reload: true,
+            });
+        }
+        let mut fell_behind = false;
+        if start < first_seq {
+            let lost = first_seq - start;
+            warn!(
+                "{}",
+                tr!(
+                    "直播窗口已越过录制位置，丢失 {} 个切片 (序列号 {}..{})",
+                    lost,
+                    start,
+                    first_seq
+                )
+            );
+            // 丢失切片的时长已无从得知，按目标时长估计
+            self.timeline.push(TimelineEntry {
+                at: self.recorded,
+                sequence: start,
+                event: "gap".to_string(),
+                detail: tr!(
+                    "{} 个切片 (序列号 {}..{})，约 {}s",
+                    lost,
+                    start,
+                    first_seq,
+                    lost * playlist.target_duration
+                ),
+            });
+            self.missed += lost;
+            // 中断后重新连接时跟不上是网络原因，不触发降档
+            fell_behind = self.offline.is_none();
+        }
+
+        // 密钥标签对其后的所有切片生效，需要从头跟踪；增量更新从被省略切片上最后生效的密钥开始
+        let mut key: Option<Key> = self
+            .key_changes
+            .iter()
+            .rev()
+            .find(|(seq, _)| *seq < first_seq)
+            .map(|(_, key)| key.clone())
+            .filter(|_| low.skipped > 0);
+        let mut batch: Vec<Piece> = Vec::new();
+        let mut ads_skipped = 0;
+        let mut skipped_to = None;
+        for (i, seg) in playlist.segments.iter().enumerate() {
+            let seq = first_seq + i as u64;
+            if let Some(tag) = &seg.key {
+                key = Some(tag.clone());
+                if self.key_changes.last().is_none_or(|(last, _)| seq > *last) {
+                    self.key_changes.push((seq, tag.clone()));
+                }
+            }
+            // 广告时段可能跨越多次刷新，每个切片只判断一次
+            let ad = match &mut self.ads {
+                Some(ads) if self.observed.is_none_or(|o| seq >= o) => {
+                    self.observed = Some(seq + 1);
+                    ads.observe(seg)
+                }
+                _ => false,
+            };
+            if seq < start {
+                continue;
+            }
+            if ad {
+                ads_skipped += 1;
+                skipped_to = Some(seq + 1);
+                continue;
+            }
+            let Some((_, done)) = self.partial.filter(|(p, _)| *p == seq) else {
+                batch.push(Piece {
+                    seq,
+                    seg: seg.clone(),
+                    key: key.clone(),
+                    part: None,
+                });
+                continue;
+            };
+            // 已经写入了这个切片的前几个分片，只补上剩余的分片
+            match low.parts(seq).filter(|parts| parts.len() > done) {
+                Some(parts) => {
+                    for (j, part) in parts.iter().enumerate().skip(done) {
+                        batch.push(Piece::part(
+                            seq,
+                            part,
+                            key.as_ref(),
+                            j,
+                            j + 1 == parts.len(),
+                        ));
+                    }
+                }
+                None => {
+                    warn!(
+                        "{}",
+                        tr!(
+                            "切片 {} 的分片已不在播放列表中，该切片只录制了前 {} 个分片",
+                            seq,
+                            done
+                        )
+                    );
+                    self.partial = None;
+                    skipped_to = Some(seq + 1);
+                }
+            }
+        }
+        if ads_skipped > 0 {
+            info!(
+                "{}",
+                tr!(
+                    "{} 跳过 {} 个广告切片",
+                    format!("{:?}", self.job.output),
+                    ads_skipped
+                )
+            );
+        }
+        // 尚未完成的切片：已经列出的分片立即下载
+        let open = low
+            .parts(low.open_msn)
+            .filter(|_| use_parts && low.open_msn >= start);
+        if let Some(parts) = open {
+            let done = self
+                .partial
+                .filter(|(p, _)| *p == low.open_msn)
+                .map_or(0, |(_, done)| done);
+            for (j, part) in parts.iter().enumerate().skip(done) {
+                batch.push(Piece::part(low.open_msn, part, key.as_ref(), j, false));
+            }
+        }
+
+        let started = Instant::now();
+        let query = Inherited::new(&args.propagate_query, &self.job.url);
+        let policy = RetryPolicy {
+            scheduler: Some(shared.scheduler.clone()),
+            ..RetryPolicy::from_args(args)
+        };
+        keys::prefetch(
+            shared,
+            Some(&media_url),
+            &query,
+            batch.iter().filter_map(|piece| piece.key.as_ref()),
+            &policy,
+        )
+        .await?;
+        let mut hinted = self.hinted.take();
+        let mut jobs = Vec::with_capacity(batch.len());
+        for piece in &batch {
+            let material = keys::resolve(
+                shared,
+                Some(&media_url),
+                &query,
+                piece.key.as_ref(),
+                piece.seq,
+            )
+            .await?;
+            let gap = args.allow_gaps && is_gap(&piece.seg);
+            let url = query.apply(media_url.join(&piece.seg.uri)?);
+            let prefetched = hinted
+                .take_if(|(hint, _)| *hint == url)
+                .map(|(_, task)| task);
+            jobs.push((piece.seq, url, material, gap, prefetched));
+        }
+        if let Some((_, task)) = hinted {
+            task.abort();
+        }
+
+        let source = HttpSource::new(shared.proxies.clone(), None).with_hooks(shared.hooks.clone());
+        let job = self.job;
+        let mut downloads = std::pin::pin!(
+            stream::iter(jobs)
+                .map(|(seq, seg_url, material, gap, prefetched)| {
+                    let policy = policy.clone();
+                    let source = &source;
+                    async move {
+                        if gap {
+                            return Ok((seq, Vec::new()));
+                        }
+                        let decode = |data: Vec<u8>| -> Result<Vec<u8>> {
+                            let data = decrypt_segment(data, material.as_ref())?;
+                            if !args.no_validate {
+                                check_segment(&data)?;
+                            }
+                            Ok(data)
+                        };
+                        // 预加载的分片请求成功时直接使用，否则照常下载
+                        let early = match prefetched {
+                            Some(task) => task.await.ok().and_then(Result::ok),
+                            None => None,
+                        };
+                        if let Some(data) = early.and_then(|raw| decode(raw).ok()) {
+                            return Ok((seq, data));
+                        }
+                        shared.control.checkpoint(job.id).await?;
+                        let result = fetch_segment_with_retry(
+                            source as &dyn SegmentSource,
+                            seg_url.as_str(),
+                            &shared.mirrors,
+                            &shared.hooks,
+                            &policy,
+                            |fetched| decode(fetched.data),
+                            |attempt, e| {
+                                if let Some(wait) = recovery::retry_after(e) {
+                                    shared.scheduler.throttled(wait);
+                                } else if recovery::is_congested(e) {
+                                    shared.scheduler.congested();
+                                }
+                                shared.events.emit(JobEvent::Retry {
+                                    job: job.id,
+                                    segment: seq as usize,
+                                    attempt,
+                                    error: format!("{:#}", e),
+                                })
+                            },
+                        )
+                        .await;
+                        let data = match result {
+                            Ok(data) => {
+                                shared.scheduler.succeeded();
+                                data
+                            }
+                            Err(e) if args.allow_gaps && recovery::is_missing(&e) => {
+                                warn!("{}", tr!("切片 #{} 已不存在，作为空缺跳过: {:#}", seq, e));
+                                Vec::new()
+                            }
+                            Err(e) => return Err(e),
+                        };
+                        Ok::<_, anyhow::Error>((seq, data))
+                    }
+                    .instrument(info_span!("segment", index = seq))
+                })
+                .buffered(args.concurrency.max(1))
+        );
+
+        let mut batch_secs = 0.0;
+        let mut pieces = batch.iter();
+        while let Some(result) = downloads.next().await {
+            let (seq, data) = result?;
+            self.output.write_all(&data)?;
+            let piece = pieces.next();
+            let secs = piece.map_or(0.0, |p| f64::from(p.seg.duration));
+            if let Some(piece) = piece {
+                self.chapters.push(&piece.seg);
+            }
+            batch_secs += secs;
+            self.recorded += secs;
+            self.bytes += data.len() as u64;
+            match piece.and_then(|p| p.part) {
+                Some((j, false)) => self.partial = Some((seq, j + 1)),
+                _ => {
+                    self.partial = None;
+                    self.written += 1;
+                    self.next_seq = Some(seq + 1);
+                }
+            }
+            shared.events.emit(JobEvent::SegmentDone {
+                job: job.id,
+                segment: seq as usize,
+                bytes: data.len() as u64,
+                completed: self.written,
+                total: 0,
+            });
+            self.pb.set_message(tr!(
+                "{} 录制直播流 {}: {} 个切片, {:.0}s",
+                ui.icon(Icon::Download),
+                format!("{:?}", job.output),
+                self.written,
+                self.recorded
+            ));
+        }
+        if let Some(seq) = skipped_to {
+            self.next_seq = Some(self.next_seq.map_or(seq, |next| next.max(seq)));
+        }
+        if self.next_seq.is_none() {
+            self.next_seq = Some(start);
+        }
+        self.ended = playlist.end_list;
+        self.skip = low.can_skip;
+        // 只需保留仍可能被省略的切片之前最后一个密钥
+        let keep_from = self
+            .key_changes
+            .iter()
+            .rposition(|(seq, _)| *seq < first_seq)
+            .unwrap_or(0);
+        self.key_changes.drain(..keep_from);
+        self.block_at = if use_parts { low.next_part() } else { None };
+        // 预加载提示所指的分片尚未生成，服务器会把请求挂起到分片可用为止，
+        // 与阻塞式刷新同时进行，下一轮即可直接写入
+        let hint = low.preload.as_deref().filter(|_| use_parts && !self.ended);
+        if let Some(uri) = hint {
+            let url = query.apply(media_url.join(uri)?);
+            let request = shared.client.get(url.clone());
+            let task = tokio::spawn(async move {
+                let response = request.send().await?.error_for_status()?;
+                Ok::<_, anyhow::Error>(response.bytes().await?.to_vec())
+            });
+            self.hinted = Some((url, task));
+        }
+
+        // 下载这一批所用的时间超过其媒体时长，说明吞吐跟不上实时
+        if !batch.is_empty() && started.elapsed().as_secs_f64() > batch_secs {
+            fell_behind = true;
+        }
+        self.behind = if fell_behind { self.behind + 1 } else { 0 };
+
+        let mut switched = false;
+        if args.auto_downgrade
+            && !self.ended
+            && self.behind >= args.downgrade_after
+            && self.ladder.current + 1 < self.ladder.variants.len()
+        {
+            self.ladder.current += 1;
+            let lower = self.ladder.variants[self.ladder.current].0;
+            warn!(
+                "{}",
+                tr!(
+                    "{} {} 连续 {} 次跟不上实时，从 {} bps 切换到 {} bps",
+                    ui.icon(Icon::Warn),
+                    format!("{:?}", job.output),
+                    self.behind,
+                    bandwidth,
+                    lower
+                )
+            );
+            // 各变体的媒体序列号是对齐的，切换点落在切片边界上
+            self.timeline.push(TimelineEntry {
+                at: self.recorded,
+                sequence: self.next_seq.unwrap_or(start),
+                event: "variant_switch".to_string(),
+                detail: format!("{} -> {} bps", bandwidth, lower),
+            });
+            self.behind = 0;
+            switched = true;
+            // 各变体的分片不对齐，放弃当前切片剩余的分片，从下一个切片开始录制新变体
+            if let Some((seq, _)) = self.partial.take() {
+                self.next_seq = Some(seq + 1);
+            }
+            self.block_at = None;
+            if let Some((_, task)) = self.hinted.take() {
+                task.abort();
+            }
+        }
+
+        Ok(Step {
+            new_segments: !batch.is_empty() || ads_skipped > 0,
+            switched,
+            target: playlist.target_duration,
+            part_target: low.part_target.filter(|_| use_parts),
+            blocking: self.block_at.is_some(),
+            reload: false,
+        })
+    }
+}
@@ -0,0 +1,188 @@
+use url::Url;
+
+/// LL-HLS 分片 (EXT-X-PART)
+#[derive(Clone, Debug)]
+pub struct Part {
+    pub uri: String,
+    pub duration: f64,
+}
+
+/// 播放列表中与低延迟相关的标签。m3u8-rs 不解析这些标签，
+/// 而且最后一个切片之后的分片与预加载提示会被丢弃，因此直接从原始文本中读取
+#[derive(Clone, Debug, Default)]
+pub struct LowLatency {
+    /// EXT-X-SERVER-CONTROL 的 CAN-BLOCK-RELOAD=YES
+    pub can_block_reload: bool,
+    /// EXT-X-SERVER-CONTROL 的 CAN-SKIP-UNTIL：服务器支持 `_HLS_skip=YES` 增量更新
+    pub can_skip: bool,
+    /// 增量更新中 EXT-X-SKIP 省略的切片数
+    pub skipped: u64,
+    /// EXT-X-PART-INF 的 PART-TARGET (秒)，有此项才算低延迟播放列表
+    pub part_target: Option<f64>,
+    /// 各切片的分片，按媒体序列号排列；最后一项可能属于尚未完成的切片
+    parts: Vec<(u64, Vec<Part>)>,
+    /// 尚未完成 (还没有 EXTINF) 的切片的媒体序列号
+    pub open_msn: u64,
+    /// EXT-X-PRELOAD-HINT TYPE=PART 指向的下一个分片
+    pub preload: Option<String>,
+}
+
+impl LowLatency {
+    pub fn parse(text: &str) -> Self {
+        let mut low = Self::default();
+        let mut msn = 0u64;
+        let mut current: Vec<Part> = Vec::new();
+        for line in text.lines().map(str::trim) {
+            if let Some(rest) = line.strip_prefix("#EXT-X-MEDIA-SEQUENCE:") {
+                msn = rest.trim().parse().unwrap_or(0);
+            } else if let Some(rest) = line.strip_prefix("#EXT-X-SERVER-CONTROL:") {
+                low.can_block_reload =
+                    attribute(rest, "CAN-BLOCK-RELOAD").as_deref() == Some("YES");
+                low.can_skip = attribute(rest, "CAN-SKIP-UNTIL").is_some();
+            } else if let Some(rest) = line.strip_prefix("#EXT-X-SKIP:") {
+                low.skipped = attribute(rest, "SKIPPED-SEGMENTS")
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(0);
+                msn += low.skipped;
+            } else if let Some(rest) = line.strip_prefix("#EXT-X-PART-INF:") {
+                low.part_target = attribute(rest, "PART-TARGET").and_then(|v| v.parse().ok());
+            } else if let Some(rest) = line.strip_prefix("#EXT-X-PART:") {
+                let Some(uri) = attribute(rest, "URI") else {
+                    continue;
+                };
+                let duration = attribute(rest, "DURATION")
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(0.0);
+                current.push(Part { uri, duration });
+            } else if let Some(rest) = line.strip_prefix("#EXT-X-PRELOAD-HINT:") {
+                // 只预加载完整的分片，带 BYTERANGE-START 的提示无法与之后列出的分片对应
+                let whole = attribute(rest, "BYTERANGE-START").is_none();
+                if attribute(rest, "TYPE").as_deref() == Some("PART") && whole {
+                    low.preload = attribute(rest, "URI");
+                }
+            } else if !line.is_empty() && !line.starts_with('#') {
+                if !current.is_empty() {
+                    low.parts.push((msn, std::mem::take(&mut current)));
+                }
+                msn += 1;
+            }
+        }
+        if !current.is_empty() {
+            low.parts.push((msn, current));
+        }
+        low.open_msn = msn;
+        low
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.part_target.is_some()
+    }
+
+    /// 某个切片的分片，播放列表中已不再列出时返回 None
+    pub fn parts(&self, msn: u64) -> Option<&[Part]> {
+        self.parts
+            .iter()
+            .find(|(seq, _)| *seq == msn)
+            .map(|(_, parts)| parts.as_slice())
+    }
+
+    /// 阻塞式刷新时等待的下一个分片 (_HLS_msn, _HLS_part)，即预加载提示所指的分片
+    pub fn next_part(&self) -> Option<(u64, usize)> {
+        if !self.can_block_reload {
+            return None;
+        }
+        let listed = self.parts(self.open_msn).map_or(0, <[Part]>::len);
+        Some((self.open_msn, listed))
+    }
+}
+
+/// 刷新播放列表的地址：阻塞式刷新时服务器在 `_HLS_msn` / `_HLS_part` 指定的分片可用之前
+/// 不返回播放列表；`skip` 时请求省略较早切片的增量更新 (`_HLS_skip=YES`)
+pub fn reload_url(url: &Url, block: Option<(u64, usize)>, skip: bool) -> Url {
+    let mut url = url.clone();
+    if let Some((msn, part)) = block {
+        url.query_pairs_mut()
+            .append_pair("_HLS_msn", &msn.to_string())
+            .append_pair("_HLS_part", &part.to_string());
+    }
+    if skip {
+        url.query_pairs_mut().append_pair("_HLS_skip", "YES");
+    }
+    url
+}
+
+/// 从属性列表 (`NAME=VALUE,NAME="VALUE"`) 中取出某一属性，去掉引号
+pub fn attribute(list: &str, name: &str) -> Option<String> {
+    let mut rest = list;
+    while !rest.is_empty() {
+        let (key, after) = rest.split_once('=')?;
+        let (value, next) = match after.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"')?;
+                let next = quoted[end + 1..].trim_start_matches(',');
+                (&quoted[..end], next)
+            }
+            None => match after.split_once(',') {
+                Some((value, next)) => (value, next),
+                None => (after, ""),
+            },
+        };
+        if key.trim() == name {
+            return Some(value.to_string());
+        }
+        rest = next;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DELTA: &str = "#EXTM3U
+#EXT-X-TARGETDURATION:4
+#EXT-X-SERVER-CONTROL:CAN-BLOCK-RELOAD=YES,CAN-SKIP-UNTIL=24.0
+#EXT-X-PART-INF:PART-TARGET=1.0
+#EXT-X-MEDIA-SEQUENCE:100
+#EXT-X-SKIP:SKIPPED-SEGMENTS=6
+#EXTINF:4.0,
+seg106.ts
+#EXT-X-PART:DURATION=1.0,URI=\"seg107.0.mp4\"
+#EXT-X-PART:DURATION=1.0,URI=\"seg107.1.mp4\"
+#EXTINF:2.0,
+seg107.ts
+#EXT-X-PART:DURATION=1.0,URI=\"seg108.0.mp4\"
+#EXT-X-PRELOAD-HINT:TYPE=PART,URI=\"seg108.1.mp4\"
+";
+
+    #[test]
+    fn skip_offsets_sequence_numbers() {
+        let low = LowLatency::parse(DELTA);
+        assert!(low.can_skip);
+        assert_eq!(low.skipped, 6);
+        // 省略的 6 个切片之后：seg106 为 106，已完成的 seg107 带两个分片，108 尚未完成
+        assert_eq!(low.parts(107).map(<[Part]>::len), Some(2));
+        assert_eq!(low.open_msn, 108);
+        assert_eq!(low.parts(108).map(<[Part]>::len), Some(1));
+        assert_eq!(low.next_part(), Some((108, 1)));
+        assert_eq!(low.preload.as_deref(), Some("seg108.1.mp4"));
+    }
+
+    #[test]
+    fn full_playlist_has_no_skip() {
+        let full = DELTA.replace("#EXT-X-SKIP:SKIPPED-SEGMENTS=6\n", "");
+        let low = LowLatency::parse(&full);
+        assert_eq!(low.skipped, 0);
+        assert_eq!(low.open_msn, 102);
+    }
+
+    #[test]
+    fn reload_url_requests_delta_updates() {
+        let url = Url::parse("https://example.com/live.m3u8?token=1").unwrap();
+        assert_eq!(
+            reload_url(&url, Some((108, 1)), true).as_str(),
+            "https://example.com/live.m3u8?token=1&_HLS_msn=108&_HLS_part=1&_HLS_skip=YES"
+        );
+        assert_eq!(reload_url(&url, None, false), url);
+    }
+}
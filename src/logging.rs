@@ -0,0 +1,74 @@
+use crate::i18n::tr;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::fs::OpenOptions;
+use std::path::Path;
+use std::sync::Mutex;
+use tracing::Level;
+use tracing_subscriber::filter::{EnvFilter, Targets};
+use tracing_subscriber::fmt::{self, MakeWriter, time::ChronoLocal};
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{Layer, Registry};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum LogFormat {
+    /// 单行文本，前面带上所在的任务、切片与重试次数
+    Text,
+    /// 每条日志一行 JSON，任务、切片与重试次数为单独的字段，便于 Loki / ELK 收集
+    Json,
+}
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// 初始化日志：终端默认输出 info 级别 (--quiet 时为 warn)，可用 RUST_LOG 覆盖；
+/// 指定 `file` 时同时追加写入日志文件，文件记录本程序的 debug 级别日志
+/// (每次切片请求、HTTP 状态、FFmpeg 命令等) 与其他库的 info 级别日志，不受 --quiet 与进度条影响。
+/// 依赖库通过 log 输出的日志同样转发到这里
+pub fn init(quiet: bool, colors: bool, format: LogFormat, file: Option<&Path>) -> Result<()> {
+    let level = if quiet { "warn" } else { "info" };
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(level));
+    let mut layers = vec![
+        layer(format, std::io::stderr, colors)
+            .with_filter(filter)
+            .boxed(),
+    ];
+
+    if let Some(path) = file {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| tr!("无法打开日志文件: {}", path.display()))?;
+        let targets = Targets::new()
+            .with_default(Level::INFO)
+            .with_target(env!("CARGO_CRATE_NAME"), Level::DEBUG);
+        layers.push(
+            layer(format, Mutex::new(file), false)
+                .with_filter(targets)
+                .boxed(),
+        );
+    }
+
+    tracing_subscriber::registry()
+        .with(layers)
+        .try_init()
+        .context(tr!("日志器已初始化"))
+}
+
+fn layer<W>(format: LogFormat, writer: W, ansi: bool) -> BoxedLayer
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = fmt::layer()
+        .with_writer(writer)
+        .with_ansi(ansi)
+        .with_timer(ChronoLocal::new("%Y-%m-%d %H:%M:%S%.3f".to_string()));
+    match format {
+        LogFormat::Text => layer.boxed(),
+        LogFormat::Json => layer
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .boxed(),
+    }
+}
@@ -0,0 +1,128 @@
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use std::process::Stdio;
+use tokio::process::Command;
+use tracing::{info, warn};
+
+use crate::i18n::tr;
+use crate::ui::{Icon, Stage, Ui};
+
+/// 目标综合响度 (LUFS)、真峰值 (dBTP) 与响度范围 (LU)
+const TARGET: &str = "I=-16:TP=-1.5:LRA=11";
+
+/// loudnorm 第一遍的测量结果
+#[derive(Clone, Copy, Debug)]
+pub struct Loudness {
+    input_i: f64,
+    input_tp: f64,
+    input_lra: f64,
+    input_thresh: f64,
+    target_offset: f64,
+}
+
+/// loudnorm 以字符串输出各项数值
+#[derive(Deserialize)]
+struct Report {
+    input_i: String,
+    input_tp: String,
+    input_lra: String,
+    input_thresh: String,
+    target_offset: String,
+}
+
+impl Loudness {
+    /// 第二遍的滤镜：按测量结果线性调整增益，loudnorm 输出 192kHz，需要重采样回 48kHz
+    pub fn filter(&self) -> String {
+        format!(
+            "loudnorm={}:measured_I={}:measured_TP={}:measured_LRA={}:measured_thresh={}:offset={}:linear=true,aresample=48000",
+            TARGET,
+            self.input_i,
+            self.input_tp,
+            self.input_lra,
+            self.input_thresh,
+            self.target_offset
+        )
+    }
+}
+
+/// `--normalize-audio`：转码前用 loudnorm 测量每条输出音轨的响度 (EBU R128 双遍处理的第一遍)。
+/// 单个输入时测量其中的音频，多个输入时测量第二个及之后的输入 (独立音轨)，与转码时的映射一致；
+/// 测量失败时输出警告，不做响度标准化
+pub async fn measure_all(inputs: &[&str], ui: &Ui) -> Vec<Loudness> {
+    let sources = if inputs.len() > 1 {
+        &inputs[1..]
+    } else {
+        inputs
+    };
+    let pb = match ui.spinner(Stage::Convert, tr!("分析音频响度...")) {
+        Ok(pb) => pb,
+        Err(e) => {
+            warn!("{:#}", e);
+            return Vec::new();
+        }
+    };
+    let mut measured = Vec::new();
+    for input in sources {
+        match measure(input).await {
+            Ok(loudness) => {
+                info!(
+                    "{}",
+                    tr!("音频响度 {:.1} LUFS，将调整到 -16 LUFS", loudness.input_i)
+                );
+                measured.push(loudness);
+            }
+            Err(e) => {
+                warn!("{}", tr!("测量音频响度失败，不做响度标准化: {:#}", e));
+                pb.finish_and_clear();
+                return Vec::new();
+            }
+        }
+    }
+    pb.finish_with_message(format!("{} {}", ui.icon(Icon::Ok), tr!("音频响度分析完成")));
+    measured
+}
+
+async fn measure(input: &str) -> Result<Loudness> {
+    let mut command = Command::new("ffmpeg");
+    command.args(["-hide_banner", "-nostats"]);
+    if input.ends_with(".txt") {
+        command.args(["-f", "concat", "-safe", "0"]);
+    }
+    let filter = format!("loudnorm={}:print_format=json", TARGET);
+    let output = command
+        .args([
+            "-i", input, "-map", "0:a:0", "-af", &filter, "-f", "null", "-",
+        ])
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .context(tr!("FFmpeg 执行失败"))?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        bail!("{}", stderr.lines().last().unwrap_or_default());
+    }
+    // 测量结果是输出末尾的 JSON 对象
+    let json = stderr
+        .rfind('{')
+        .and_then(|start| Some(&stderr[start..=stderr.rfind('}')?]))
+        .context(tr!("FFmpeg 没有输出响度测量结果"))?;
+    let report: Report = serde_json::from_str(json).context(tr!("无法解析响度测量结果"))?;
+    let parse = |value: &str| -> Result<f64> {
+        value
+            .trim()
+            .parse()
+            .with_context(|| tr!("无效的响度数值: {}", value))
+    };
+    let input_i = parse(&report.input_i)?;
+    if !input_i.is_finite() {
+        bail!(tr!("音频为静音，无法测量响度"));
+    }
+    Ok(Loudness {
+        input_i,
+        input_tp: parse(&report.input_tp)?,
+        input_lra: parse(&report.input_lra)?,
+        input_thresh: parse(&report.input_thresh)?,
+        target_offset: parse(&report.target_offset)?,
+    })
+}
@@ -0,0 +1,306 @@
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::{fs, process::Command};
+use tracing::{error, info, warn};
+
+use crate::concat_entries;
+use crate::i18n::tr;
+use crate::ui::{Icon, Ui};
+
+/// 时长允许的误差 (秒)，容器重写时会有少量取整差异
+const DURATION_TOLERANCE: f64 = 0.5;
+
+/// 与输出文件同目录保存的清单，用于之后的完整性校验
+#[derive(Serialize, Deserialize)]
+pub struct Manifest {
+    pub source: String,
+    pub file: String,
+    pub size: u64,
+    pub sha256: String,
+    pub duration: Option<f64>,
+    pub streams: Vec<StreamInfo>,
+    pub created: u64,
+    /// 录制过程中的关键事件 (如直播变体切换)，按发生顺序排列
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub timeline: Vec<TimelineEntry>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TimelineEntry {
+    /// 事件在输出中的时间位置 (秒)
+    pub at: f64,
+    pub sequence: u64,
+    pub event: String,
+    pub detail: String,
+}
+
+/// 输出中缺失的一个切片，写入 `<输出文件>.gaps.json`
+#[derive(Serialize, Debug)]
+pub struct Gap {
+    pub segment: usize,
+    pub sequence: u64,
+    pub uri: String,
+    /// 空缺在输出中的时间位置 (秒)
+    pub at: f64,
+    pub duration: f64,
+    /// 下载失败的原因；EXT-X-GAP 或 404/410 跳过的切片为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct StreamInfo {
+    pub codec_type: String,
+    pub codec_name: String,
+}
+
+pub struct ProbeInfo {
+    pub duration: Option<f64>,
+    pub streams: Vec<StreamInfo>,
+    pub errors: String,
+}
+
+#[derive(Deserialize)]
+struct FfprobeOutput {
+    #[serde(default)]
+    streams: Vec<FfprobeStream>,
+    format: Option<FfprobeFormat>,
+}
+
+#[derive(Deserialize)]
+struct FfprobeStream {
+    codec_type: Option<String>,
+    codec_name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct FfprobeFormat {
+    duration: Option<String>,
+}
+
+/// 写出空缺切片的报告，返回报告路径
+pub async fn write_gaps(output: &Path, gaps: &[Gap]) -> Result<PathBuf> {
+    let mut name = output.file_name().unwrap_or_default().to_os_string();
+    name.push(".gaps.json");
+    let path = output.with_file_name(name);
+    fs::write(&path, serde_json::to_vec_pretty(gaps)?)
+        .await
+        .with_context(|| tr!("无法写入空缺报告: {}", path.display()))?;
+    Ok(path)
+}
+
+pub fn manifest_path(output: &Path) -> PathBuf {
+    let mut name = output.file_name().unwrap_or_default().to_os_string();
+    name.push(".manifest.json");
+    output.with_file_name(name)
+}
+
+pub async fn sha256_file(path: &Path) -> Result<String> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let mut file =
+            std::fs::File::open(&path).with_context(|| tr!("无法打开文件: {}", path.display()))?;
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut file, &mut hasher)?;
+        Ok::<_, anyhow::Error>(hex::encode(hasher.finalize()))
+    })
+    .await?
+}
+
+pub async fn probe(path: &Path) -> Result<ProbeInfo> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-show_entries"])
+        .arg("format=duration:stream=codec_type,codec_name")
+        .args(["-of", "json"])
+        .arg(path)
+        .output()
+        .await
+        .context(tr!("FFprobe 未找到，请确保已安装 FFmpeg 并添加到 PATH"))?;
+
+    if !output.status.success() {
+        bail!(tr!(
+            "FFprobe 无法解析文件 {}: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let parsed: FfprobeOutput =
+        serde_json::from_slice(&output.stdout).context(tr!("解析 FFprobe 输出失败"))?;
+    Ok(ProbeInfo {
+        duration: parsed
+            .format
+            .and_then(|f| f.duration)
+            .and_then(|d| d.parse().ok()),
+        streams: parsed
+            .streams
+            .into_iter()
+            .map(|s| StreamInfo {
+                codec_type: s.codec_type.unwrap_or_default(),
+                codec_name: s.codec_name.unwrap_or_default(),
+            })
+            .collect(),
+        errors: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+    })
+}
+
+/// 要用 FFprobe 查看的文件：分段合并的 concat 列表查看其中的第一段
+pub async fn probe_target(input: &Path) -> Option<PathBuf> {
+    if input.extension().is_some_and(|e| e == "txt") {
+        let text = fs::read_to_string(input).await.ok()?;
+        concat_entries(input, &text).next()
+    } else {
+        Some(input.to_path_buf())
+    }
+}
+
+/// 在转码完成后为输出文件生成清单
+pub async fn write(output: &Path, source: &str, timeline: &[TimelineEntry]) -> Result<()> {
+    let size = fs::metadata(output).await?.len();
+    let sha256 = sha256_file(output).await?;
+    let info = probe(output).await?;
+    let manifest = Manifest {
+        source: source.to_string(),
+        file: output
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        size,
+        sha256,
+        duration: info.duration,
+        streams: info.streams,
+        created: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        timeline: timeline.to_vec(),
+    };
+
+    let path = manifest_path(output);
+    fs::write(&path, serde_json::to_vec_pretty(&manifest)?)
+        .await
+        .with_context(|| tr!("无法写入清单: {}", path.display()))?;
+    info!("{}", tr!("已生成清单: {}", path.display()));
+    Ok(())
+}
+
+/// 收集待校验的清单：可以是清单本身、媒体文件或包含归档的目录
+fn collect_manifests(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut found = Vec::new();
+    let mut pending: Vec<PathBuf> = paths.to_vec();
+    while let Some(path) = pending.pop() {
+        if path.is_dir() {
+            for entry in
+                std::fs::read_dir(&path).with_context(|| tr!("无法读取目录: {}", path.display()))?
+            {
+                pending.push(entry?.path());
+            }
+        } else if path.to_string_lossy().ends_with(".manifest.json") {
+            found.push(path);
+        } else if paths.contains(&path) {
+            found.push(manifest_path(&path));
+        }
+    }
+    found.sort();
+    Ok(found)
+}
+
+/// 校验单个归档，返回发现的偏差列表
+async fn verify_one(manifest_file: &Path, deep: bool) -> Result<Vec<String>> {
+    let raw = fs::read(manifest_file)
+        .await
+        .with_context(|| tr!("无法读取清单: {}", manifest_file.display()))?;
+    let manifest: Manifest = serde_json::from_slice(&raw)
+        .with_context(|| tr!("清单格式错误: {}", manifest_file.display()))?;
+    let media = manifest_file.with_file_name(&manifest.file);
+
+    let mut drift = Vec::new();
+    let size = match fs::metadata(&media).await {
+        Ok(meta) => meta.len(),
+        Err(_) => {
+            drift.push(tr!("文件缺失: {}", media.display()));
+            return Ok(drift);
+        }
+    };
+    if size != manifest.size {
+        drift.push(tr!("文件大小 {} != 清单记录 {}", size, manifest.size));
+    }
+    let sha256 = sha256_file(&media).await?;
+    if sha256 != manifest.sha256 {
+        drift.push(tr!("SHA-256 {} != 清单记录 {}", sha256, manifest.sha256));
+    }
+
+    if deep {
+        match probe(&media).await {
+            Ok(info) => {
+                if !info.errors.is_empty() {
+                    drift.push(tr!("容器结构异常: {}", info.errors));
+                }
+                match (info.duration, manifest.duration) {
+                    (Some(actual), Some(expected))
+                        if (actual - expected).abs() > DURATION_TOLERANCE =>
+                    {
+                        drift.push(tr!("时长 {:.2}s != 清单记录 {:.2}s", actual, expected));
+                    }
+                    (None, Some(_)) => drift.push(tr!("无法读取时长").to_string()),
+                    _ => {}
+                }
+                if info.streams != manifest.streams {
+                    drift.push(tr!(
+                        "流信息 {} != 清单记录 {}",
+                        format!("{:?}", info.streams),
+                        format!("{:?}", manifest.streams)
+                    ));
+                }
+            }
+            Err(e) => drift.push(format!("{:#}", e)),
+        }
+    }
+
+    Ok(drift)
+}
+
+/// `verify` 子命令：只读地检查归档，发现偏差时返回错误
+pub async fn verify(paths: &[PathBuf], deep: bool, ui: &Ui) -> Result<()> {
+    let manifests = collect_manifests(paths)?;
+    if manifests.is_empty() {
+        bail!(tr!("未找到任何清单文件"));
+    }
+
+    let mut failed = 0;
+    for manifest_file in &manifests {
+        match verify_one(manifest_file, deep).await {
+            Ok(drift) if drift.is_empty() => {
+                info!("{} {}", ui.icon(Icon::Ok), manifest_file.display())
+            }
+            Ok(drift) => {
+                failed += 1;
+                warn!("{} {}", ui.icon(Icon::Fail), manifest_file.display());
+                for d in drift {
+                    warn!("    {}", d);
+                }
+            }
+            Err(e) => {
+                failed += 1;
+                error!(
+                    "{} {}: {:#}",
+                    ui.icon(Icon::Fail),
+                    manifest_file.display(),
+                    e
+                );
+            }
+        }
+    }
+
+    info!(
+        "{}",
+        tr!("校验完成: {} 个归档，{} 个异常", manifests.len(), failed)
+    );
+    if failed > 0 {
+        bail!(tr!("{} 个归档未通过校验", failed));
+    }
+    Ok(())
+}
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+use crate::events::JobEvent;
+use crate::i18n::tr;
+
+/// 转码耗时直方图的桶上限 (秒)
+const TRANSCODE_BUCKETS: &[f64] = &[10.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1800.0, 3600.0];
+
+/// 守护进程的累计指标，由事件总线更新，`GET /metrics` 以 Prometheus 文本格式输出
+pub struct Metrics {
+    segments: AtomicU64,
+    bytes: AtomicU64,
+    retries: AtomicU64,
+    transcode: Mutex<Histogram>,
+    /// 正在转码的任务与收到第一条转码进度的时间
+    transcoding: Mutex<HashMap<usize, Instant>>,
+}
+
+#[derive(Default)]
+struct Histogram {
+    /// 与 [`TRANSCODE_BUCKETS`] 一一对应的非累计计数
+    buckets: [u64; TRANSCODE_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        if let Some(i) = TRANSCODE_BUCKETS.iter().position(|b| value <= *b) {
+            self.buckets[i] += 1;
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            segments: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            retries: AtomicU64::new(0),
+            transcode: Mutex::new(Histogram::default()),
+            transcoding: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl Metrics {
+    /// 转码耗时从第一条转码进度算起，只统计成功完成的任务
+    pub fn observe(&self, event: &JobEvent) {
+        match event {
+            JobEvent::SegmentDone { bytes, .. } => {
+                self.segments.fetch_add(1, Ordering::Relaxed);
+                self.bytes.fetch_add(*bytes, Ordering::Relaxed);
+            }
+            JobEvent::Retry { .. } => {
+                self.retries.fetch_add(1, Ordering::Relaxed);
+            }
+            JobEvent::Transcode { job, .. } => {
+                self.transcoding
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .entry(*job)
+                    .or_insert_with(Instant::now);
+            }
+            JobEvent::Complete { job, .. } => {
+                let started = self
+                    .transcoding
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .remove(job);
+                if let Some(started) = started {
+                    self.transcode
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .observe(started.elapsed().as_secs_f64());
+                }
+            }
+            JobEvent::Failed { job, .. } | JobEvent::Cancelled { job } => {
+                self.transcoding
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .remove(job);
+            }
+            _ => {}
+        }
+    }
+
+    /// 输出 Prometheus 文本格式，`jobs` 为各状态的任务数
+    pub fn render(&self, jobs: &[(&str, u64)]) -> String {
+        let mut out = String::new();
+        counter(
+            &mut out,
+            "m3u8_segments_downloaded_total",
+            tr!("已下载的切片数"),
+            self.segments.load(Ordering::Relaxed),
+        );
+        counter(
+            &mut out,
+            "m3u8_bytes_downloaded_total",
+            tr!("已下载的字节数"),
+            self.bytes.load(Ordering::Relaxed),
+        );
+        counter(
+            &mut out,
+            "m3u8_segment_retries_total",
+            tr!("切片重试次数"),
+            self.retries.load(Ordering::Relaxed),
+        );
+
+        let _ = writeln!(out, "{}", tr!("# HELP m3u8_jobs 各状态的任务数"));
+        let _ = writeln!(out, "# TYPE m3u8_jobs gauge");
+        for (status, count) in jobs {
+            let _ = writeln!(out, "m3u8_jobs{{status=\"{}\"}} {}", status, count);
+        }
+
+        let histogram = self.transcode.lock().unwrap_or_else(|e| e.into_inner());
+        let _ = writeln!(
+            out,
+            "{}",
+            tr!("# HELP m3u8_transcode_duration_seconds 转码耗时 (秒)")
+        );
+        let _ = writeln!(out, "# TYPE m3u8_transcode_duration_seconds histogram");
+        let mut cumulative = 0;
+        for (bound, count) in TRANSCODE_BUCKETS.iter().zip(histogram.buckets) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "m3u8_transcode_duration_seconds_bucket{{le=\"{}\"}} {}",
+                bound, cumulative
+            );
+        }
+        let _ = writeln!(
+            out,
+            "m3u8_transcode_duration_seconds_bucket{{le=\"+Inf\"}} {}",
+            histogram.count
+        );
+        let _ = writeln!(out, "m3u8_transcode_duration_seconds_sum {}", histogram.sum);
+        let _ = writeln!(
+            out,
+            "m3u8_transcode_duration_seconds_count {}",
+            histogram.count
+        );
+        out
+    }
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: u64) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    let _ = writeln!(out, "{} {}", name, value);
+}
@@ -0,0 +1,54 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use url::Url;
+
+/// `--base-url` 给出的镜像主机。同一切片先在首选主机上重试，重试耗尽后换下一个主机；
+/// 某个主机下载成功后成为之后切片的首选，避免每个切片都先在失效的主机上耗尽重试
+pub struct Mirrors {
+    /// 镜像的协议、主机与端口；序号 0 表示切片地址原本的主机
+    origins: Vec<Url>,
+    preferred: AtomicUsize,
+}
+
+impl Mirrors {
+    pub fn new(origins: Vec<Url>) -> Self {
+        Self {
+            origins,
+            preferred: AtomicUsize::new(0),
+        }
+    }
+
+    /// 按尝试顺序列出切片在各主机上的地址 (主机序号, 地址)，首选主机在前。
+    /// 镜像只替换地址的协议、主机与端口，路径与查询参数不变；本地文件只有原地址
+    pub fn locations(&self, location: &str) -> Vec<(usize, String)> {
+        let original = vec![(0, location.to_string())];
+        let Ok(url) = Url::parse(location) else {
+            return original;
+        };
+        if self.origins.is_empty() || !url.scheme().starts_with("http") {
+            return original;
+        }
+        let hosts = self.origins.len() + 1;
+        let preferred = self.preferred.load(Ordering::Relaxed) % hosts;
+        (preferred..hosts)
+            .chain(0..preferred)
+            .filter_map(|i| match i {
+                0 => Some((0, location.to_string())),
+                i => rehost(&url, &self.origins[i - 1]).map(|u| (i, u.to_string())),
+            })
+            .collect()
+    }
+
+    /// 记录下载成功的主机，之后的切片优先使用
+    pub fn prefer(&self, host: usize) {
+        self.preferred.store(host, Ordering::Relaxed);
+    }
+}
+
+/// 把 `url` 的协议、主机与端口换成镜像的
+fn rehost(url: &Url, mirror: &Url) -> Option<Url> {
+    let mut url = url.clone();
+    url.set_scheme(mirror.scheme()).ok()?;
+    url.set_host(mirror.host_str()).ok()?;
+    url.set_port(mirror.port()).ok()?;
+    Some(url)
+}
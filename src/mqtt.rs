@@ -0,0 +1,112 @@
+use anyhow::{Context, Result, bail};
+use rumqttc::{AsyncClient, Event, MqttOptions, Outgoing, QoS};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::{Receiver, error::RecvError};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+use url::Url;
+
+use crate::events::JobEvent;
+use crate::i18n::tr;
+
+/// 同一任务的进度事件最短发布间隔，避免每个切片都刷一条消息
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+fn options(broker: &str) -> Result<MqttOptions> {
+    let url = Url::parse(broker).with_context(|| tr!("无效的 MQTT 地址: {}", broker))?;
+    if url.scheme() != "mqtt" {
+        bail!(tr!("MQTT 地址需以 mqtt:// 开头: {}", broker));
+    }
+    let host = url.host_str().context(tr!("MQTT 地址缺少主机名"))?;
+    let mut opts = MqttOptions::new(
+        format!("m3u8-downloader-{}", std::process::id()),
+        host,
+        url.port().unwrap_or(1883),
+    );
+    opts.set_keep_alive(Duration::from_secs(30));
+    if !url.username().is_empty() {
+        opts.set_credentials(url.username(), url.password().unwrap_or_default());
+    }
+    Ok(opts)
+}
+
+/// 把任务事件发布到 `<prefix>/events`，并以 retained 消息在 `<prefix>/status`
+/// 保留最新状态，方便 Home Assistant 等面板直接订阅。
+/// 事件通道关闭后断开连接，返回的句柄结束即表示消息已尽量发送完毕
+pub fn spawn(broker: &str, prefix: &str, mut rx: Receiver<JobEvent>) -> Result<JoinHandle<()>> {
+    let (client, mut eventloop) = AsyncClient::new(options(broker)?, 64);
+    info!("{}", tr!("任务事件将发布到 MQTT: {} ({})", broker, prefix));
+
+    let pump = tokio::spawn(async move {
+        loop {
+            match eventloop.poll().await {
+                Ok(Event::Outgoing(Outgoing::Disconnect)) => break,
+                Ok(_) => {}
+                Err(e) => {
+                    warn!("{}", tr!("MQTT 连接错误: {}", e));
+                    tokio::time::sleep(Duration::from_secs(2)).await;
+                }
+            }
+        }
+    });
+
+    let events_topic = format!("{}/events", prefix);
+    let status_topic = format!("{}/status", prefix);
+    Ok(tokio::spawn(async move {
+        let mut last_progress: HashMap<usize, Instant> = HashMap::new();
+        loop {
+            let event = match rx.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(n)) => {
+                    warn!("{}", tr!("MQTT 发布跟不上，丢弃了 {} 条事件", n));
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+
+            // 高频进度事件按任务节流，阶段的最后一条总是发送
+            let progress = match &event {
+                JobEvent::SegmentDone {
+                    job,
+                    completed,
+                    total,
+                    ..
+                }
+                | JobEvent::Merge {
+                    job,
+                    completed,
+                    total,
+                } => Some((*job, *total > 0 && completed >= total)),
+                JobEvent::Transcode { job, .. } => Some((*job, false)),
+                _ => None,
+            };
+            if let Some((job, last)) = progress {
+                let now = Instant::now();
+                let due = last_progress
+                    .get(&job)
+                    .is_none_or(|t| now.duration_since(*t) >= PROGRESS_INTERVAL);
+                if !due && !last {
+                    continue;
+                }
+                last_progress.insert(job, now);
+            }
+
+            let payload = match serde_json::to_vec(&event) {
+                Ok(payload) => payload,
+                Err(_) => continue,
+            };
+            // 连接断开时内部队列可能已满，直接丢弃而不是阻塞下载
+            if client
+                .try_publish(&events_topic, QoS::AtLeastOnce, false, payload.clone())
+                .and_then(|_| client.try_publish(&status_topic, QoS::AtLeastOnce, true, payload))
+                .is_err()
+            {
+                warn!("{}", tr!("MQTT 消息队列已满，事件被丢弃"));
+            }
+        }
+
+        let _ = client.disconnect().await;
+        let _ = tokio::time::timeout(Duration::from_secs(5), pump).await;
+    }))
+}
@@ -0,0 +1,251 @@
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use url::Url;
+
+use crate::Args;
+use crate::i18n::tr;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Naming {
+    /// Plex/Jellyfin 剧集格式：`剧名/Season 01/剧名 - S01E02`
+    Plex,
+    /// 按日期归档的新闻格式：`2024-05-01 标题`
+    News,
+    /// 使用 --name-template 自定义
+    Template,
+}
+
+/// 命名所需的元数据，可来自命令行或守护进程的任务请求
+#[derive(Clone, Default, Deserialize)]
+pub struct Metadata {
+    pub title: Option<String>,
+    pub season: Option<u32>,
+    pub episode: Option<u32>,
+    /// `YYYY-MM-DD`，默认当天 (UTC)
+    pub date: Option<String>,
+}
+
+impl Metadata {
+    pub fn from_args(args: &Args) -> Self {
+        Self {
+            title: args.title.clone(),
+            season: args.season,
+            episode: args.episode,
+            date: args.date.clone(),
+        }
+    }
+
+    /// 逐项以 `self` 为准，缺少的项由 `fallback` 补齐
+    pub fn or(self, fallback: &Metadata) -> Self {
+        Self {
+            title: self.title.or_else(|| fallback.title.clone()),
+            season: self.season.or(fallback.season),
+            episode: self.episode.or(fallback.episode),
+            date: self.date.or_else(|| fallback.date.clone()),
+        }
+    }
+}
+
+/// 补齐默认值之后的命名输入
+pub struct NameInputs {
+    pub title: String,
+    pub season: Option<u32>,
+    pub episode: Option<u32>,
+    pub date: String,
+}
+
+/// 输出命名策略：返回相对输出目录的路径，不含扩展名
+pub trait NamingStrategy: Send + Sync {
+    fn name(&self, inputs: &NameInputs) -> Result<PathBuf>;
+}
+
+struct Plex;
+
+impl NamingStrategy for Plex {
+    fn name(&self, inputs: &NameInputs) -> Result<PathBuf> {
+        let (Some(season), Some(episode)) = (inputs.season, inputs.episode) else {
+            bail!(tr!(
+                "Plex 命名需要季/集编号 (--season/--episode，或标题中的 S01E02)"
+            ));
+        };
+        Ok(PathBuf::from(&inputs.title)
+            .join(format!("Season {:02}", season))
+            .join(format!("{} - S{:02}E{:02}", inputs.title, season, episode)))
+    }
+}
+
+struct News;
+
+impl NamingStrategy for News {
+    fn name(&self, inputs: &NameInputs) -> Result<PathBuf> {
+        Ok(PathBuf::from(format!("{} {}", inputs.date, inputs.title)))
+    }
+}
+
+/// 支持 `{title}` `{season}` `{episode}` `{date}` 占位符，`/` 用于分隔目录
+struct Template(String);
+
+impl NamingStrategy for Template {
+    fn name(&self, inputs: &NameInputs) -> Result<PathBuf> {
+        let number = |n: Option<u32>| n.map(|n| format!("{:02}", n)).unwrap_or_default();
+        let name = self
+            .0
+            .replace("{title}", &inputs.title)
+            .replace("{season}", &number(inputs.season))
+            .replace("{episode}", &number(inputs.episode))
+            .replace("{date}", &inputs.date);
+        Ok(name
+            .split('/')
+            .filter(|part| !part.is_empty() && *part != "." && *part != "..")
+            .collect())
+    }
+}
+
+pub fn strategy(args: &Args) -> Result<Option<Box<dyn NamingStrategy>>> {
+    Ok(match args.naming {
+        None => None,
+        Some(Naming::Plex) => Some(Box::new(Plex)),
+        Some(Naming::News) => Some(Box::new(News)),
+        Some(Naming::Template) => {
+            let template = args
+                .name_template
+                .clone()
+                .context(tr!("--naming template 需要同时指定 --name-template"))?;
+            Some(Box::new(Template(template)))
+        }
+    })
+}
+
+/// 按策略生成输出文件路径：`dir/<名称>.<ext>`
+pub fn output_path(
+    strategy: &dyn NamingStrategy,
+    meta: &Metadata,
+    url: &str,
+    dir: &std::path::Path,
+    ext: &str,
+) -> Result<PathBuf> {
+    let inputs = resolve(meta, url);
+    let name = strategy.name(&inputs)?;
+    let mut file = name
+        .file_name()
+        .context(tr!("命名结果为空"))?
+        .to_os_string();
+    file.push(format!(".{}", ext));
+    Ok(dir.join(name.with_file_name(file)))
+}
+
+/// 补齐标题、季/集与日期：标题缺省时取自 URL，季/集可从标题或 URL 中的 `S01E02` 解析
+fn resolve(meta: &Metadata, url: &str) -> NameInputs {
+    let raw_title = meta.title.clone().unwrap_or_else(|| title_from_url(url));
+    let (title, parsed) = match find_episode_tag(&raw_title) {
+        Some((range, se)) => {
+            let mut title = raw_title.clone();
+            title.replace_range(range, "");
+            (title, Some(se))
+        }
+        None => (raw_title, find_episode_tag(url).map(|(_, se)| se)),
+    };
+    let title = sanitize(title.trim_matches(|c: char| c.is_whitespace() || c == '-' || c == '.'));
+
+    NameInputs {
+        title: if title.is_empty() {
+            "video".to_string()
+        } else {
+            title
+        },
+        season: meta.season.or(parsed.map(|(s, _)| s)),
+        episode: meta.episode.or(parsed.map(|(_, e)| e)),
+        date: meta.date.clone().unwrap_or_else(today),
+    }
+}
+
+/// 未指定 --output 时的输出文件名 (不含扩展名)，取自 URL 路径
+pub fn stem_from_url(url: &str) -> String {
+    let stem = sanitize(title_from_url(url).trim());
+    if stem.is_empty() {
+        "video".to_string()
+    } else {
+        stem
+    }
+}
+
+/// 取 URL 路径中最后一个有意义的部分，跳过 index/master/playlist 这类通用文件名
+fn title_from_url(url: &str) -> String {
+    let path = Url::parse(url)
+        .map(|u| u.path().to_string())
+        .unwrap_or_else(|_| url.to_string());
+    path.rsplit(['/', '\\'])
+        .map(|part| part.split('.').next().unwrap_or_default())
+        .find(|stem| {
+            !stem.is_empty()
+                && !matches!(
+                    stem.to_lowercase().as_str(),
+                    "index" | "master" | "playlist" | "prog_index" | "hls" | "video"
+                )
+        })
+        .unwrap_or("video")
+        .to_string()
+}
+
+/// 查找形如 `S01E02` (不区分大小写) 的标记，返回其位置与 (季, 集)
+fn find_episode_tag(text: &str) -> Option<(std::ops::Range<usize>, (u32, u32))> {
+    let bytes = text.as_bytes();
+    let digits = |from: usize| {
+        bytes[from..]
+            .iter()
+            .take_while(|b| b.is_ascii_digit())
+            .count()
+    };
+    for start in 0..bytes.len() {
+        if !bytes[start].eq_ignore_ascii_case(&b's') {
+            continue;
+        }
+        let s_len = digits(start + 1);
+        let e_pos = start + 1 + s_len;
+        if s_len == 0 || e_pos >= bytes.len() || !bytes[e_pos].eq_ignore_ascii_case(&b'e') {
+            continue;
+        }
+        let e_len = digits(e_pos + 1);
+        if e_len == 0 {
+            continue;
+        }
+        let end = e_pos + 1 + e_len;
+        let season = text[start + 1..e_pos].parse().ok()?;
+        let episode = text[e_pos + 1..end].parse().ok()?;
+        return Some((start..end, (season, episode)));
+    }
+    None
+}
+
+/// 去掉文件系统不允许的字符
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// 当天日期 (UTC)，`YYYY-MM-DD`
+fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() / 86_400)
+        .unwrap_or(0) as i64;
+    // 公历换算，见 Howard Hinnant 的 civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
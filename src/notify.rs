@@ -0,0 +1,127 @@
+use reqwest::Client;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::{Receiver, error::RecvError};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::events::JobEvent;
+use crate::i18n::tr;
+
+/// 单次通知的最大尝试次数
+const ATTEMPTS: u32 = 3;
+
+/// 任务结束时 POST 到 `--notify-url` 的内容
+#[derive(Serialize)]
+struct Notification<'a> {
+    job: usize,
+    status: &'static str,
+    url: &'a str,
+    output: &'a str,
+    /// 媒体时长 (秒)
+    duration: f64,
+    /// 下载的切片字节数
+    bytes: u64,
+    /// 任务耗时 (秒)
+    elapsed: f64,
+    error: Option<&'a str>,
+}
+
+#[derive(Default)]
+struct JobState {
+    url: String,
+    output: String,
+    duration: f64,
+    bytes: u64,
+    started: Option<Instant>,
+}
+
+/// POST JSON 到 webhook，失败时短暂等待后重试，最终失败只记录警告
+pub async fn post(client: &Client, url: &str, body: &impl Serialize) {
+    for attempt in 1..=ATTEMPTS {
+        match client
+            .post(url)
+            .json(body)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+        {
+            Ok(_) => return,
+            Err(e) if attempt < ATTEMPTS => {
+                warn!(
+                    "{}",
+                    tr!("Webhook 通知失败 ({}/{}): {}", attempt, ATTEMPTS, e)
+                );
+                tokio::time::sleep(Duration::from_secs(2)).await;
+            }
+            Err(e) => warn!("{}", tr!("Webhook 通知失败，放弃: {}", e)),
+        }
+    }
+}
+
+/// 订阅任务事件，在每个任务完成、失败或取消时发送一次通知
+/// `client` 与下载使用相同的代理、TLS 设置与请求头
+pub fn spawn(notify_url: String, client: Client, mut rx: Receiver<JobEvent>) -> JoinHandle<()> {
+    info!("{}", tr!("任务结束时将通知: {}", notify_url));
+
+    tokio::spawn(async move {
+        let mut jobs: HashMap<usize, JobState> = HashMap::new();
+        loop {
+            let event = match rx.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(n)) => {
+                    warn!("{}", tr!("Webhook 通知跟不上，丢弃了 {} 条事件", n));
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+
+            let (id, status, error) = match &event {
+                JobEvent::Queued { job, url, output } => {
+                    let state = jobs.entry(*job).or_default();
+                    state.url = url.clone();
+                    state.output = output.clone();
+                    continue;
+                }
+                JobEvent::Started { job, .. } => {
+                    jobs.entry(*job).or_default().started = Some(Instant::now());
+                    continue;
+                }
+                JobEvent::PlaylistParsed { job, duration, .. } => {
+                    jobs.entry(*job).or_default().duration = *duration;
+                    continue;
+                }
+                JobEvent::SegmentDone { job, bytes, .. } => {
+                    jobs.entry(*job).or_default().bytes += bytes;
+                    continue;
+                }
+                // 直播录制的实际时长在转码时才确定
+                JobEvent::Transcode { job, duration, .. } if *duration > 0.0 => {
+                    jobs.entry(*job).or_default().duration = *duration;
+                    continue;
+                }
+                JobEvent::Complete { job, .. } => (*job, "complete", None),
+                JobEvent::Failed { job, error } => (*job, "failed", Some(error.as_str())),
+                JobEvent::Cancelled { job } => (*job, "cancelled", None),
+                _ => continue,
+            };
+
+            let state = jobs.remove(&id).unwrap_or_default();
+            let body = Notification {
+                job: id,
+                status,
+                url: &state.url,
+                output: &state.output,
+                duration: state.duration,
+                bytes: state.bytes,
+                elapsed: state
+                    .started
+                    .map(|t| t.elapsed().as_secs_f64())
+                    .unwrap_or(0.0),
+                error,
+            };
+            post(&client, &notify_url, &body).await;
+        }
+    })
+}
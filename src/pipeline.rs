@@ -0,0 +1,58 @@
+use m3u8_rs::MediaSegment;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tokio::process::ChildStdin;
+
+use crate::i18n::tr;
+use crate::{Args, Job};
+
+/// 流水线转码时 FFmpeg 的输入
+pub const INPUT: &str = "pipe:0";
+
+/// `--pipeline`：点播任务的主输出在下载开始时就启动 FFmpeg，按顺序合并的切片同时写入
+/// FFmpeg 的标准输入，下载结束时转码也基本完成。合并文件照常写出，流水线转码失败时改为从合并文件转码。
+/// 这里保存各输出等待合并时取用的 FFmpeg 标准输入
+#[derive(Default)]
+pub struct Feeds {
+    pipes: Mutex<HashMap<PathBuf, ChildStdin>>,
+}
+
+impl Feeds {
+    pub fn attach(&self, job: &Job, stdin: ChildStdin) {
+        self.lock().insert(job.output.clone(), stdin);
+    }
+
+    /// 取出 `job` 的 FFmpeg 标准输入；丢弃后 FFmpeg 读到输入结束
+    pub fn take(&self, job: &Job) -> Option<ChildStdin> {
+        self.lock().remove(&job.output)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<PathBuf, ChildStdin>> {
+        self.pipes.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// 不能流水线转码的原因；`separate` 表示还有第二路变体流、独立音轨或要下载的字幕，
+/// 它们要在主输出下载完之后才能一起交给 FFmpeg
+pub fn blocker(segments: &[MediaSegment], args: &Args, separate: bool) -> Option<&'static str> {
+    if !args.cenc_key.is_empty() {
+        Some(tr!("CENC 加密的切片要合并后才能解密"))
+    } else if separate {
+        Some(tr!("有第二路变体流、独立音轨或播放列表中的字幕"))
+    } else if segments
+        .iter()
+        .skip(1)
+        .any(|s| s.discontinuity || s.map.is_some())
+    {
+        Some(tr!("播放列表有不连续点或多个初始化段，需要分段合并"))
+    } else if args.tonemap.is_some() {
+        Some(tr!("--tonemap 要在转码前查看源画面的 HDR 参数"))
+    } else if args.normalize_audio {
+        Some(tr!("--normalize-audio 要在转码前测量完整音频的响度"))
+    } else if args.refetch_mismatched {
+        Some(tr!("--refetch-mismatched 要在全部下载完成后才能合并"))
+    } else {
+        None
+    }
+}
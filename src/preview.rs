@@ -0,0 +1,326 @@
+use anyhow::{Context, Result};
+use axum::{
+    Router,
+    body::{Body, Bytes},
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use std::fmt::Write;
+use std::io::SeekFrom;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tracing::info;
+
+use crate::Job;
+use crate::i18n::tr;
+
+/// 渐进式输出追上下载进度后，等待新数据的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// 渐进式输出每次读取的最大字节数
+const CHUNK: u64 = 1 << 20;
+
+/// 已合并的一个切片在合并文件中的位置
+struct Piece {
+    group: usize,
+    offset: u64,
+    len: u64,
+    duration: f32,
+}
+
+/// 合并文件的一段 (按不连续点分段合并时有多段)
+struct Group {
+    path: PathBuf,
+    /// 段开头的初始化段 (fMP4) 长度
+    init: u64,
+    /// 已写入的字节数
+    written: u64,
+}
+
+#[derive(Default)]
+struct Progress {
+    /// 正在预览的任务
+    job: Option<usize>,
+    output: PathBuf,
+    groups: Vec<Group>,
+    pieces: Vec<Piece>,
+    /// 空缺切片之后的第一个切片，播放列表中在此处标记不连续
+    breaks: Vec<usize>,
+    finished: bool,
+}
+
+/// 渐进式输出下一次可以读取的内容
+enum Tail {
+    /// 读取 `path` 中 `end` 之前的内容，`complete` 表示这一段已写完
+    Ready {
+        path: PathBuf,
+        end: u64,
+        complete: bool,
+    },
+    Wait,
+    End,
+}
+
+/// `--serve`：在本地以 HLS 播放列表 (`/index.m3u8`，切片为合并文件中的字节范围) 与渐进式 TS
+/// (`/stream.ts`) 提供已下载的部分，下载期间即可用播放器观看。同一时间只预览一个任务，
+/// 切片按顺序下载并边下载边合并 (相当于 --ordered)
+pub struct Preview {
+    progress: Mutex<Progress>,
+}
+
+impl Preview {
+    /// 开始预览 `job` 的主输出；已有其他任务在预览时不预览这个任务
+    pub fn claim(&self, job: &Job) {
+        let mut progress = self.lock();
+        if progress.job.is_some_and(|id| id != job.id) {
+            return;
+        }
+        *progress = Progress {
+            job: Some(job.id),
+            output: job.output.clone(),
+            ..Progress::default()
+        };
+        info!("{}", tr!("可以边下载边播放: {}", job.output.display()));
+    }
+
+    /// `job` 是否为正在预览的输出 (第二路变体流与音轨不预览)
+    pub fn watches(&self, job: &Job) -> bool {
+        let progress = self.lock();
+        progress.job == Some(job.id) && progress.output == job.output && !progress.finished
+    }
+
+    /// 合并时开始新的一段
+    pub fn group(&self, path: PathBuf, init: u64) {
+        self.lock().groups.push(Group {
+            path,
+            init,
+            written: init,
+        });
+    }
+
+    /// 当前段追加了一个切片；`len` 为 None 时是空缺切片
+    pub fn piece(&self, duration: f32, len: Option<u64>) {
+        let mut progress = self.lock();
+        let Some(len) = len else {
+            let next = progress.pieces.len();
+            progress.breaks.push(next);
+            return;
+        };
+        let group = progress.groups.len().saturating_sub(1);
+        let Some(current) = progress.groups.last_mut() else {
+            return;
+        };
+        let offset = current.written;
+        current.written += len;
+        progress.pieces.push(Piece {
+            group,
+            offset,
+            len,
+            duration,
+        });
+    }
+
+    /// 合并结束或任务结束：播放列表加上 EXT-X-ENDLIST，并允许其他任务预览
+    pub fn finish(&self, job: usize) {
+        let mut progress = self.lock();
+        if progress.job == Some(job) {
+            progress.finished = true;
+            progress.job = None;
+        }
+    }
+
+    fn playlist(&self) -> String {
+        let progress = self.lock();
+        let target = progress
+            .pieces
+            .iter()
+            .map(|p| p.duration.ceil() as u64)
+            .max()
+            .unwrap_or(1);
+        let mut out = String::new();
+        let _ = writeln!(out, "#EXTM3U");
+        let _ = writeln!(out, "#EXT-X-VERSION:6");
+        let _ = writeln!(out, "#EXT-X-TARGETDURATION:{}", target);
+        let _ = writeln!(out, "#EXT-X-MEDIA-SEQUENCE:0");
+        let _ = writeln!(out, "#EXT-X-PLAYLIST-TYPE:EVENT");
+        let mut group = None;
+        for (i, piece) in progress.pieces.iter().enumerate() {
+            if group != Some(piece.group) {
+                if group.is_some() {
+                    let _ = writeln!(out, "#EXT-X-DISCONTINUITY");
+                }
+                let init = progress.groups[piece.group].init;
+                if init > 0 {
+                    let _ = writeln!(
+                        out,
+                        "#EXT-X-MAP:URI=\"group/{}\",BYTERANGE=\"{}@0\"",
+                        piece.group, init
+                    );
+                }
+                group = Some(piece.group);
+            } else if progress.breaks.contains(&i) {
+                let _ = writeln!(out, "#EXT-X-DISCONTINUITY");
+            }
+            let _ = writeln!(out, "#EXTINF:{:.3},", piece.duration);
+            let _ = writeln!(out, "#EXT-X-BYTERANGE:{}@{}", piece.len, piece.offset);
+            let _ = writeln!(out, "group/{}", piece.group);
+        }
+        if progress.finished {
+            let _ = writeln!(out, "#EXT-X-ENDLIST");
+        }
+        out
+    }
+
+    fn tail(&self, group: usize) -> Tail {
+        let progress = self.lock();
+        match progress.groups.get(group) {
+            Some(g) => Tail::Ready {
+                path: g.path.clone(),
+                end: g.written,
+                complete: progress.finished || group + 1 < progress.groups.len(),
+            },
+            None if progress.finished => Tail::End,
+            None => Tail::Wait,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Progress> {
+        self.progress.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// 在 `listen` 上启动预览服务
+pub async fn spawn(listen: &str) -> Result<Arc<Preview>> {
+    let preview = Arc::new(Preview {
+        progress: Mutex::new(Progress::default()),
+    });
+    let app = Router::new()
+        .route("/index.m3u8", get(playlist))
+        .route("/group/{n}", get(group))
+        .route("/stream.ts", get(stream))
+        .with_state(preview.clone());
+    let listener = tokio::net::TcpListener::bind(listen)
+        .await
+        .with_context(|| tr!("无法监听地址: {}", listen))?;
+    let addr = listener.local_addr()?;
+    info!(
+        "{}",
+        tr!(
+            "预览服务已启动: http://{}/index.m3u8 (渐进式: http://{}/stream.ts)",
+            addr,
+            addr
+        )
+    );
+    tokio::spawn(async move {
+        let _ = axum::serve(listener, app).await;
+    });
+    Ok(preview)
+}
+
+async fn playlist(State(preview): State<Arc<Preview>>) -> impl IntoResponse {
+    (
+        [
+            (header::CONTENT_TYPE, "application/vnd.apple.mpegurl"),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        preview.playlist(),
+    )
+}
+
+/// 按 Range 请求返回合并文件中已写入的部分
+async fn group(
+    State(preview): State<Arc<Preview>>,
+    Path(n): Path<usize>,
+    headers: HeaderMap,
+) -> Response {
+    let Tail::Ready { path, end, .. } = preview.tail(n) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let range = headers
+        .get(header::RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_range);
+    let (start, last) = match range {
+        Some((start, last)) => (start, last.unwrap_or(end.saturating_sub(1))),
+        None => (0, end.saturating_sub(1)),
+    };
+    if start >= end || last < start {
+        return StatusCode::RANGE_NOT_SATISFIABLE.into_response();
+    }
+    let last = last.min(end - 1);
+    let data = match read_at(&path, start, last - start + 1).await {
+        Ok(data) => data,
+        Err(_) => return StatusCode::NOT_FOUND.into_response(),
+    };
+    let headers = [
+        (header::CONTENT_TYPE, "video/mp2t"),
+        (header::ACCEPT_RANGES, "bytes"),
+    ];
+    if range.is_none() {
+        return (headers, data).into_response();
+    }
+    let mut response = (StatusCode::PARTIAL_CONTENT, headers, data).into_response();
+    if let Ok(value) = format!("bytes {}-{}/*", start, last).parse() {
+        response.headers_mut().insert(header::CONTENT_RANGE, value);
+    }
+    response
+}
+
+/// 依次输出各段合并文件，追上下载进度后等待新数据，直到合并结束
+async fn stream(State(preview): State<Arc<Preview>>) -> Response {
+    let body = futures::stream::unfold(
+        (preview, 0, 0),
+        |(preview, mut group, mut offset)| async move {
+            loop {
+                match preview.tail(group) {
+                    Tail::End => return None,
+                    Tail::Wait => tokio::time::sleep(POLL_INTERVAL).await,
+                    Tail::Ready {
+                        path,
+                        end,
+                        complete,
+                    } => {
+                        if offset < end {
+                            let len = (end - offset).min(CHUNK);
+                            let chunk = read_at(&path, offset, len).await.map(Bytes::from);
+                            return Some((chunk, (preview, group, offset + len)));
+                        }
+                        if complete {
+                            group += 1;
+                            offset = 0;
+                        } else {
+                            tokio::time::sleep(POLL_INTERVAL).await;
+                        }
+                    }
+                }
+            }
+        },
+    );
+    (
+        [(header::CONTENT_TYPE, "video/mp2t")],
+        Body::from_stream(body),
+    )
+        .into_response()
+}
+
+async fn read_at(path: &std::path::Path, offset: u64, len: u64) -> std::io::Result<Vec<u8>> {
+    let mut file = tokio::fs::File::open(path).await?;
+    file.seek(SeekFrom::Start(offset)).await?;
+    let mut data = vec![0; len as usize];
+    file.read_exact(&mut data).await?;
+    Ok(data)
+}
+
+/// 解析 `bytes=起点-[终点]`
+fn parse_range(value: &str) -> Option<(u64, Option<u64>)> {
+    let (start, last) = value.strip_prefix("bytes=")?.split_once('-')?;
+    let last = match last.trim() {
+        "" => None,
+        last => Some(last.parse().ok()?),
+    };
+    Some((start.trim().parse().ok()?, last))
+}
@@ -0,0 +1,90 @@
+use crate::i18n::tr;
+use clap::ValueEnum;
+use reqwest::{Client, StatusCode};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tracing::warn;
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum ProxyRotation {
+    /// 每个切片请求轮流使用下一个代理
+    RoundRobin,
+    /// 一直使用同一个代理，请求失败或被限流时换下一个
+    OnFailure,
+}
+
+/// `--proxies` 给出的代理，每个代理一个客户端；没有指定时只有直连 (或 `--proxy`) 的客户端
+pub struct ProxyPool {
+    /// (代理地址, 客户端)
+    clients: Vec<(Option<String>, Client)>,
+    rotation: ProxyRotation,
+    next: AtomicUsize,
+}
+
+impl ProxyPool {
+    pub fn direct(client: Client) -> Self {
+        Self {
+            clients: vec![(None, client)],
+            rotation: ProxyRotation::RoundRobin,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn new(clients: Vec<(String, Client)>, rotation: ProxyRotation) -> Self {
+        Self {
+            clients: clients
+                .into_iter()
+                .map(|(proxy, client)| (Some(proxy), client))
+                .collect(),
+            rotation,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// 本次切片请求使用的客户端及其序号
+    pub fn pick(&self) -> (usize, &Client) {
+        let index = match self.rotation {
+            ProxyRotation::RoundRobin => self.next.fetch_add(1, Ordering::Relaxed),
+            ProxyRotation::OnFailure => self.next.load(Ordering::Relaxed),
+        } % self.clients.len();
+        (index, &self.clients[index].1)
+    }
+
+    /// 通过第 `index` 个代理的请求连接失败或被拒绝 (403/429)。`--proxy-rotation on-failure` 时
+    /// 换下一个代理；并发请求同时失败时只换一次
+    pub fn failed(&self, index: usize) {
+        if self.rotation != ProxyRotation::OnFailure || self.clients.len() < 2 {
+            return;
+        }
+        let current = self.next.load(Ordering::Relaxed);
+        if current % self.clients.len() != index {
+            return;
+        }
+        if self
+            .next
+            .compare_exchange(current, current + 1, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+        {
+            let next = (index + 1) % self.clients.len();
+            warn!(
+                "{}",
+                tr!(
+                    "代理 {} 请求失败，换用 {}",
+                    self.name(index),
+                    self.name(next)
+                )
+            );
+        }
+    }
+
+    /// 响应状态说明这个出口 IP 被拒绝或限流，换代理可能有用
+    pub fn is_blocked(status: StatusCode) -> bool {
+        matches!(
+            status,
+            StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS
+        )
+    }
+
+    fn name(&self, index: usize) -> &str {
+        self.clients[index].0.as_deref().unwrap_or(tr!("直连"))
+    }
+}
@@ -0,0 +1,96 @@
+use anyhow::{Context, Result, bail};
+use futures::future;
+use m3u8_rs::VariantStream;
+use std::time::Instant;
+use tracing::{info, warn};
+use url::Url;
+
+use crate::i18n::tr;
+use crate::query::Inherited;
+use crate::{Args, Shared, directory_url, fetch_media_playlist};
+
+/// 测速时下载的切片数，同时下载以接近正式下载时的并发情况
+const PROBE_SEGMENTS: usize = 2;
+
+/// 只选择带宽不超过实测速度这一比例的变体流，为网络波动留出余量
+const SAFETY_FACTOR: f64 = 0.75;
+
+/// `--auto-quality`：从 `best` 下载几个切片测量实际下载速度，选择带宽在实测速度范围内的最高变体流；
+/// 都超出时选择带宽最低的一路。测速失败时沿用 `best`
+pub async fn choose<'a>(
+    variants: &'a [VariantStream],
+    best: &'a VariantStream,
+    base: &Url,
+    query: &Inherited,
+    args: &Args,
+    shared: &Shared,
+) -> &'a VariantStream {
+    let url = match base.join(&best.uri) {
+        Ok(url) => query.apply(url),
+        Err(e) => {
+            warn!("{}", tr!("无法测速，沿用最佳流: {}", e));
+            return best;
+        }
+    };
+    let bps = match probe(&url, query, args, shared).await {
+        Ok(bps) => bps,
+        Err(e) => {
+            warn!("{}", tr!("测速失败，沿用最佳流: {:#}", e));
+            return best;
+        }
+    };
+    let budget = bps * SAFETY_FACTOR;
+    let candidates = variants.iter().filter(|v| !v.is_i_frame);
+    let chosen = candidates
+        .clone()
+        .filter(|v| v.bandwidth as f64 <= budget)
+        .max_by_key(|v| {
+            (
+                v.bandwidth,
+                v.resolution.as_ref().map(|r| r.width * r.height),
+            )
+        })
+        .or_else(|| candidates.min_by_key(|v| v.bandwidth))
+        .unwrap_or(best);
+    info!(
+        "{}",
+        tr!(
+            "实测下载速度 {:.0} kbps，选择带宽 {} kbps 的变体流",
+            bps / 1000.0,
+            chosen.bandwidth / 1000
+        )
+    );
+    chosen
+}
+
+/// 同时下载媒体播放列表开头的几个切片，返回总体下载速度 (bit/s)
+async fn probe(url: &Url, query: &Inherited, args: &Args, shared: &Shared) -> Result<f64> {
+    let media = fetch_media_playlist(url.as_str(), args).await?;
+    let base = directory_url(url);
+    let urls = media
+        .segments
+        .iter()
+        .take(PROBE_SEGMENTS)
+        .map(|s| base.join(&s.uri).map(|u| query.apply(u)))
+        .collect::<Result<Vec<_>, _>>()?;
+    if urls.is_empty() {
+        bail!(tr!("播放列表中没有切片"));
+    }
+
+    let started = Instant::now();
+    let sizes = future::try_join_all(urls.iter().map(|url| async move {
+        let body = shared
+            .client
+            .get(url.clone())
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .with_context(|| tr!("下载测速切片失败: {}", url))?
+            .bytes()
+            .await?;
+        Ok::<_, anyhow::Error>(body.len())
+    }))
+    .await?;
+    let elapsed = started.elapsed().as_secs_f64().max(0.001);
+    Ok(sizes.iter().sum::<usize>() as f64 * 8.0 / elapsed)
+}
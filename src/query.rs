@@ -0,0 +1,105 @@
+use crate::i18n::tr;
+use std::str::FromStr;
+use url::Url;
+
+/// 自动模式下视为鉴权参数的名称片段 (不区分大小写)
+const AUTH_HINTS: &[&str] = &[
+    "token",
+    "auth",
+    "sig",
+    "expire",
+    "policy",
+    "key-pair-id",
+    "hdnts",
+    "hdnea",
+    "hmac",
+    "acl",
+];
+
+/// 播放列表地址中的查询参数如何带到变体流、密钥与切片请求上
+#[derive(Clone, Debug, Default)]
+pub enum Propagation {
+    /// 只继承名称像鉴权参数的项 (token、sig、expires 等)，且只发往同一主机
+    #[default]
+    Auto,
+    /// 继承全部参数
+    All,
+    /// 不继承
+    Off,
+    /// 只继承指定名称的参数
+    Names(Vec<String>),
+}
+
+impl FromStr for Propagation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "auto" => Ok(Self::Auto),
+            "all" => Ok(Self::All),
+            "off" | "none" => Ok(Self::Off),
+            "" => Err(tr!("参数名不能为空 (可选 auto/all/off 或逗号分隔的参数名)").to_string()),
+            names => Ok(Self::Names(
+                names
+                    .split(',')
+                    .map(|n| n.trim().to_string())
+                    .filter(|n| !n.is_empty())
+                    .collect(),
+            )),
+        }
+    }
+}
+
+/// 从播放列表地址中选出的、需要附加到派生地址上的查询参数
+#[derive(Clone, Debug, Default)]
+pub struct Inherited {
+    params: Vec<(String, String)>,
+    /// 自动模式下只附加到与播放列表同一主机的地址，避免把凭证发给第三方
+    host: Option<String>,
+}
+
+impl Inherited {
+    pub fn new(mode: &Propagation, playlist: &str) -> Self {
+        let Ok(url) = Url::parse(playlist) else {
+            return Self::default();
+        };
+        let params = url
+            .query_pairs()
+            .filter(|(name, _)| match mode {
+                Propagation::Auto => {
+                    let name = name.to_ascii_lowercase();
+                    AUTH_HINTS.iter().any(|hint| name.contains(hint))
+                }
+                Propagation::All => true,
+                Propagation::Off => false,
+                Propagation::Names(names) => names.iter().any(|n| n == name),
+            })
+            .map(|(name, value)| (name.into_owned(), value.into_owned()))
+            .collect();
+        let host = match mode {
+            Propagation::Auto => url.host_str().map(str::to_string),
+            _ => None,
+        };
+        Self { params, host }
+    }
+
+    /// 为地址补上缺少的参数，已有的同名参数保持不变
+    pub fn apply(&self, mut url: Url) -> Url {
+        if self.params.is_empty() || !url.scheme().starts_with("http") {
+            return url;
+        }
+        if self.host.is_some() && url.host_str() != self.host.as_deref() {
+            return url;
+        }
+        let existing: Vec<String> = url.query_pairs().map(|(n, _)| n.into_owned()).collect();
+        let missing: Vec<&(String, String)> = self
+            .params
+            .iter()
+            .filter(|(name, _)| !existing.contains(name))
+            .collect();
+        if !missing.is_empty() {
+            url.query_pairs_mut().extend_pairs(missing);
+        }
+        url
+    }
+}
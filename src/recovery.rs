@@ -0,0 +1,213 @@
+use crate::i18n::tr;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use reqwest::StatusCode;
+
+/// 失败类别，以 context 的形式附加在错误上，供任务级恢复策略识别
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Failure {
+    /// 所有切片都返回 403，通常是播放列表中的签名已过期
+    Forbidden,
+    /// 硬件编码器转码失败
+    Encoder,
+    /// 解密失败的切片数
+    Decrypt(usize),
+    /// 看门狗发现任务超过指定分钟数没有进展
+    Stalled(u64),
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Forbidden => write!(f, "{}", tr!("所有切片均返回 403")),
+            Failure::Encoder => write!(f, "{}", tr!("硬件编码器转码失败")),
+            Failure::Decrypt(n) => write!(f, "{}", tr!("{} 个切片解密失败", n)),
+            Failure::Stalled(n) => {
+                write!(f, "{}", tr!("任务超过 {} 分钟没有进展，已被看门狗中止", n))
+            }
+        }
+    }
+}
+
+/// 服务器随 429/503 返回的 Retry-After，以 context 的形式附加在错误上
+#[derive(Clone, Copy, Debug)]
+pub struct RetryAfter(pub Duration);
+
+impl fmt::Display for RetryAfter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", tr!("服务器要求 {} 秒后重试", self.0.as_secs()))
+    }
+}
+
+/// 服务器要求的重试等待时间
+pub fn retry_after(e: &anyhow::Error) -> Option<Duration> {
+    e.downcast_ref::<RetryAfter>().map(|r| r.0)
+}
+
+/// 错误链中是否包含 HTTP 403
+pub fn is_forbidden(e: &anyhow::Error) -> bool {
+    e.chain()
+        .filter_map(|c| c.downcast_ref::<reqwest::Error>())
+        .any(|e| e.status() == Some(StatusCode::FORBIDDEN))
+}
+
+/// 错误链中是否包含 HTTP 404 或 410，即切片已不存在，重试也无济于事
+pub fn is_missing(e: &anyhow::Error) -> bool {
+    e.chain()
+        .filter_map(|c| c.downcast_ref::<reqwest::Error>())
+        .any(|e| matches!(e.status(), Some(StatusCode::NOT_FOUND | StatusCode::GONE)))
+}
+
+/// 错误链中是否包含限流 (429/503) 或超时，说明请求发得太快，供 `--adaptive-concurrency` 降低并发
+pub fn is_congested(e: &anyhow::Error) -> bool {
+    e.chain().any(|c| {
+        c.is::<tokio::time::error::Elapsed>()
+            || c.downcast_ref::<reqwest::Error>().is_some_and(|e| {
+                e.is_timeout()
+                    || matches!(
+                        e.status(),
+                        Some(StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE)
+                    )
+            })
+    })
+}
+
+/// 错误链中是否包含网络中断 (连接失败、超时、传输中断或服务器 5xx)，网络恢复后重试即可
+pub fn is_network(e: &anyhow::Error) -> bool {
+    e.chain().any(|c| {
+        c.is::<tokio::time::error::Elapsed>()
+            || c.downcast_ref::<reqwest::Error>().is_some_and(|e| {
+                e.is_connect()
+                    || e.is_timeout()
+                    || e.is_request()
+                    || e.is_body()
+                    || e.status().is_some_and(|s| s.is_server_error())
+            })
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Class {
+    Forbidden,
+    Encoder,
+    Decrypt,
+    Stalled,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Action {
+    /// 重新下载播放列表 (其中的签名与切片地址) 后重跑任务
+    RefreshPlaylist,
+    /// 改用 CPU 编码，复用已合并的 TS 只重做转码
+    Cpu,
+    /// 重新获取密钥后重跑任务
+    RefetchKeys,
+}
+
+/// 一条恢复规则：`类别[:阈值]=动作`，例如 `decrypt:5=refetch-keys`
+#[derive(Clone, Debug)]
+pub struct RecoveryRule {
+    class: Class,
+    threshold: usize,
+    action: Action,
+}
+
+impl FromStr for RecoveryRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (class, action) = s
+            .split_once('=')
+            .ok_or_else(|| tr!("恢复规则格式应为 类别[:阈值]=动作: {}", s))?;
+        let (class, threshold) = match class.split_once(':') {
+            Some((class, n)) => (class, n.parse().map_err(|_| tr!("无效的阈值: {}", n))?),
+            None => (class, 1),
+        };
+        let class = match class.trim() {
+            "forbidden" | "403" => Class::Forbidden,
+            "encoder" => Class::Encoder,
+            "decrypt" => Class::Decrypt,
+            "stalled" => Class::Stalled,
+            other => {
+                return Err(tr!(
+                    "未知的失败类别: {} (可选 forbidden/encoder/decrypt/stalled)",
+                    other
+                ));
+            }
+        };
+        let action = match action.trim() {
+            "refresh-playlist" => Action::RefreshPlaylist,
+            "cpu" => Action::Cpu,
+            "refetch-keys" => Action::RefetchKeys,
+            other => {
+                return Err(tr!(
+                    "未知的恢复动作: {} (可选 refresh-playlist/cpu/refetch-keys)",
+                    other
+                ));
+            }
+        };
+        Ok(Self {
+            class,
+            threshold,
+            action,
+        })
+    }
+}
+
+impl fmt::Display for RecoveryRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let class = match self.class {
+            Class::Forbidden => "forbidden",
+            Class::Encoder => "encoder",
+            Class::Decrypt => "decrypt",
+            Class::Stalled => "stalled",
+        };
+        let action = match self.action {
+            Action::RefreshPlaylist => "refresh-playlist",
+            Action::Cpu => "cpu",
+            Action::RefetchKeys => "refetch-keys",
+        };
+        write!(f, "{}:{}={}", class, self.threshold, action)
+    }
+}
+
+/// 重跑任务时调整的参数
+#[derive(Default)]
+pub struct Overrides {
+    pub force_cpu: bool,
+}
+
+/// 按失败类别查找尚未用过的规则；每条规则在同一任务中只触发一次
+pub fn plan<'a>(
+    rules: &'a [RecoveryRule],
+    error: &anyhow::Error,
+    used: &mut Vec<usize>,
+) -> Option<&'a RecoveryRule> {
+    let (class, count) = match error.downcast_ref::<Failure>()? {
+        Failure::Forbidden => (Class::Forbidden, 1),
+        Failure::Encoder => (Class::Encoder, 1),
+        Failure::Decrypt(n) => (Class::Decrypt, *n),
+        Failure::Stalled(_) => (Class::Stalled, 1),
+    };
+    let (i, rule) = rules
+        .iter()
+        .enumerate()
+        .find(|(i, r)| r.class == class && count >= r.threshold && !used.contains(i))?;
+    used.push(i);
+    Some(rule)
+}
+
+impl RecoveryRule {
+    pub fn apply(&self, overrides: &mut Overrides) {
+        if self.action == Action::Cpu {
+            overrides.force_cpu = true;
+        }
+    }
+
+    /// 重跑前是否需要丢弃已缓存的密钥
+    pub fn refetches_keys(&self) -> bool {
+        self.action == Action::RefetchKeys
+    }
+}
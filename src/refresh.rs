@@ -0,0 +1,178 @@
+use anyhow::{Context, Result, bail};
+use m3u8_rs::{MediaPlaylist, Playlist, parse_playlist};
+use reqwest::header::HeaderMap;
+use std::fmt;
+use std::time::Duration;
+use tracing::info;
+
+use crate::hooks;
+use crate::i18n::tr;
+use crate::{Args, download_playlist_with, parse_headers};
+
+/// `--refresh-cmd` 的最长运行时间
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// 每个任务最多重新获取播放列表的次数
+pub const MAX_REFRESHES: u32 = 3;
+
+/// 切片地址已过期 (其他切片返回了 403)，暂不下载，等重新获取播放列表后换用新地址
+#[derive(Debug)]
+pub struct Deferred;
+
+impl fmt::Display for Deferred {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", tr!("切片地址已过期，等待重新获取播放列表"))
+    }
+}
+
+/// 重新下载媒体播放列表，为 `indices` 中的切片换上新的地址 (通常是新的签名参数)，返回地址变化的切片数。
+/// `sequences` 为各切片的媒体序列号
+pub async fn remap(
+    url: &str,
+    args: &Args,
+    headers: &HeaderMap,
+    sequences: &[u64],
+    indices: &[usize],
+    uris: &mut [String],
+) -> Result<usize> {
+    let content = download_playlist_with(url, args, headers).await?;
+    let fresh = match parse_playlist(&content) {
+        Ok((_, Playlist::MediaPlaylist(media))) => media,
+        _ => bail!(tr!("重新获取的播放列表不是有效的 Media Playlist")),
+    };
+    Ok(rematch(&fresh, sequences, indices, uris))
+}
+
+/// 按去掉查询参数后的地址匹配新旧切片；签名在路径中时按媒体序列号匹配
+fn rematch(
+    fresh: &MediaPlaylist,
+    sequences: &[u64],
+    indices: &[usize],
+    uris: &mut [String],
+) -> usize {
+    let mut changed = 0;
+    for &idx in indices {
+        let path = strip_query(&uris[idx]);
+        let by_sequence = || {
+            let offset = sequences[idx].checked_sub(fresh.media_sequence)?;
+            fresh.segments.get(usize::try_from(offset).ok()?)
+        };
+        let found = fresh
+            .segments
+            .iter()
+            .find(|s| strip_query(&s.uri) == path)
+            .or_else(by_sequence);
+        if let Some(seg) = found.filter(|s| s.uri != uris[idx]) {
+            uris[idx] = seg.uri.clone();
+            changed += 1;
+        }
+    }
+    changed
+}
+
+fn strip_query(uri: &str) -> &str {
+    uri.split(['?', '#']).next().unwrap_or(uri)
+}
+
+/// `--refresh-cmd` 给出的新凭据
+#[derive(Default)]
+pub struct Renewed {
+    /// 新的播放列表地址
+    pub url: Option<String>,
+    /// 新的请求头，附加在之后的播放列表与切片请求上
+    pub headers: HeaderMap,
+}
+
+/// 通过 shell 运行 `--refresh-cmd` (见 [`hooks::shell`])，环境变量 `M3U8_URL` 为当前的播放列表地址。
+/// stdout 中以 http(s):// 开头的行是新的播放列表地址，`名称: 值` 形式的行是请求头，其余行忽略
+pub async fn run_command(command: &str, url: &str) -> Result<Renewed> {
+    let output = tokio::time::timeout(
+        COMMAND_TIMEOUT,
+        hooks::shell(command).env("M3U8_URL", url).output(),
+    )
+    .await
+    .with_context(|| tr!("--refresh-cmd 超过 {}s 未结束", COMMAND_TIMEOUT.as_secs()))?
+    .with_context(|| tr!("无法运行 --refresh-cmd: {}", command))?;
+    if !output.status.success() {
+        bail!(tr!(
+            "--refresh-cmd 执行失败 ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut renewed = Renewed::default();
+    let mut raw_headers = Vec::new();
+    for line in stdout.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if line.starts_with("http://") || line.starts_with("https://") {
+            renewed.url = Some(line.to_string());
+        } else if line.contains(':') {
+            raw_headers.push(line.to_string());
+        }
+    }
+    renewed.headers = parse_headers(&raw_headers)?;
+    info!(
+        "{}",
+        tr!(
+            "--refresh-cmd 返回了 {} 个请求头{}",
+            renewed.headers.len(),
+            if renewed.url.is_some() {
+                tr!("与新的播放列表地址")
+            } else {
+                ""
+            }
+        )
+    );
+    Ok(renewed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use m3u8_rs::MediaSegment;
+
+    fn playlist(media_sequence: u64, uris: &[&str]) -> MediaPlaylist {
+        MediaPlaylist {
+            media_sequence,
+            segments: uris
+                .iter()
+                .map(|uri| MediaSegment {
+                    uri: uri.to_string(),
+                    duration: 10.0,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn uris(list: &[&str]) -> Vec<String> {
+        list.iter().map(|u| u.to_string()).collect()
+    }
+
+    #[test]
+    fn rematch_by_path_replaces_query() {
+        let fresh = playlist(7, &["a.ts?sig=new", "b.ts?sig=new", "c.ts?sig=new"]);
+        let mut old = uris(&["b.ts?sig=old", "c.ts?sig=new"]);
+        assert_eq!(rematch(&fresh, &[8, 9], &[0, 1], &mut old), 1);
+        assert_eq!(old, ["b.ts?sig=new", "c.ts?sig=new"]);
+    }
+
+    #[test]
+    fn rematch_by_sequence_when_signature_is_in_path() {
+        // 去除广告后保留的切片序列号不连续，新播放列表的窗口也已经前移
+        let fresh = playlist(11, &["new/11.ts", "new/12.ts", "new/13.ts", "new/14.ts"]);
+        let mut old = uris(&["old/10.ts", "old/13.ts", "old/14.ts"]);
+        assert_eq!(rematch(&fresh, &[10, 13, 14], &[0, 1, 2], &mut old), 2);
+        assert_eq!(old, ["old/10.ts", "new/13.ts", "new/14.ts"]);
+    }
+
+    #[test]
+    fn rematch_only_touches_requested_indices() {
+        let fresh = playlist(0, &["new/0.ts", "new/1.ts"]);
+        let mut old = uris(&["old/0.ts", "old/1.ts"]);
+        assert_eq!(rematch(&fresh, &[0, 1], &[1], &mut old), 1);
+        assert_eq!(old, ["old/0.ts", "new/1.ts"]);
+    }
+}
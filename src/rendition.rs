@@ -0,0 +1,138 @@
+use crate::i18n::tr;
+use m3u8_rs::{AlternativeMedia, AlternativeMediaType, MasterPlaylist, VariantStream};
+use tracing::warn;
+
+/// 常见语言的 ISO 639-1 与 ISO 639-2 代码，用于匹配 `--audio-lang` 与写入输出的语言标签
+const LANGUAGES: &[(&str, &[&str])] = &[
+    ("en", &["eng"]),
+    ("ja", &["jpn"]),
+    ("zh", &["zho", "chi"]),
+    ("ko", &["kor"]),
+    ("fr", &["fra", "fre"]),
+    ("de", &["deu", "ger"]),
+    ("es", &["spa"]),
+    ("it", &["ita"]),
+    ("pt", &["por"]),
+    ("ru", &["rus"]),
+    ("ar", &["ara"]),
+    ("hi", &["hin"]),
+    ("th", &["tha"]),
+    ("vi", &["vie"]),
+    ("id", &["ind"]),
+    ("nl", &["nld", "dut"]),
+    ("sv", &["swe"]),
+    ("pl", &["pol"]),
+    ("tr", &["tur"]),
+];
+
+/// 写入输出的音轨信息
+#[derive(Clone, Debug)]
+pub struct AudioTrack {
+    /// ISO 639-2 语言代码 (无法识别时为播放列表中的原值)
+    pub language: Option<String>,
+    pub name: String,
+}
+
+impl AudioTrack {
+    pub fn of(media: &AlternativeMedia) -> Self {
+        Self {
+            language: media.language.as_deref().map(iso639_2),
+            name: media.name.clone(),
+        }
+    }
+}
+
+/// 变体流通过 AUDIO 属性引用的独立音频播放列表 (EXT-X-MEDIA TYPE=AUDIO)。
+/// 默认只选一个音轨，同组有多个时优先 DEFAULT=YES，其次 AUTOSELECT=YES；
+/// `all` 为同组全部音轨，`langs` 按给出的顺序逐个选择对应语言的音轨。
+/// 没有 URI 的音轨表示音频已经包含在变体流中，不需要单独下载
+pub fn audio<'a>(
+    master: &'a MasterPlaylist,
+    variant: &VariantStream,
+    langs: &[String],
+    all: bool,
+) -> Vec<&'a AlternativeMedia> {
+    let Some(group) = variant.audio.as_deref() else {
+        return Vec::new();
+    };
+    let mut candidates: Vec<&AlternativeMedia> = master
+        .alternatives
+        .iter()
+        .filter(|m| matches!(m.media_type, AlternativeMediaType::Audio))
+        .filter(|m| m.group_id == group && m.uri.is_some())
+        .collect();
+    candidates.dedup_by(|a, b| a.uri == b.uri);
+    if all {
+        return candidates;
+    }
+
+    let mut selected: Vec<&AlternativeMedia> = Vec::new();
+    for lang in langs {
+        let found = candidates
+            .iter()
+            .filter(|m| !selected.iter().any(|s| s.uri == m.uri))
+            .find(|m| {
+                m.language
+                    .as_deref()
+                    .is_some_and(|l| same_language(l, lang))
+            })
+            .copied();
+        match found {
+            Some(media) => selected.push(media),
+            None => warn!("{}", tr!("未找到语言为 {} 的音轨", lang)),
+        }
+    }
+    if selected.is_empty() {
+        selected.extend(
+            candidates
+                .into_iter()
+                .min_by_key(|m| (!m.default, !m.autoselect)),
+        );
+    }
+    selected
+}
+
+/// 主语言子标签，ISO 639-2 代码换成对应的 ISO 639-1 代码，例如 `eng`、`en-US` 都为 `en`
+fn primary(tag: &str) -> String {
+    let primary = tag.split(['-', '_']).next().unwrap_or(tag).to_lowercase();
+    LANGUAGES
+        .iter()
+        .find(|(_, three)| three.contains(&primary.as_str()))
+        .map_or(primary, |(two, _)| two.to_string())
+}
+
+fn same_language(a: &str, b: &str) -> bool {
+    primary(a) == primary(b)
+}
+
+/// MP4 的语言标签需要 ISO 639-2 代码
+fn iso639_2(tag: &str) -> String {
+    let primary = primary(tag);
+    LANGUAGES
+        .iter()
+        .find(|(two, _)| *two == primary)
+        .map_or(primary, |(_, three)| three[0].to_string())
+}
+
+/// 变体流通过 SUBTITLES 属性引用的 WebVTT 字幕 (EXT-X-MEDIA TYPE=SUBTITLES)，
+/// 给出语言时按语言选择，否则优先 DEFAULT=YES
+pub fn subtitles<'a>(
+    master: &'a MasterPlaylist,
+    variant: &VariantStream,
+    lang: Option<&str>,
+) -> Option<&'a AlternativeMedia> {
+    let group = variant.subtitles.as_deref()?;
+    let mut candidates = master
+        .alternatives
+        .iter()
+        .filter(|m| matches!(m.media_type, AlternativeMediaType::Subtitles))
+        .filter(|m| m.group_id == group && m.uri.is_some());
+    match lang {
+        Some(lang) => candidates.find(|m| {
+            m.language
+                .as_deref()
+                .is_some_and(|l| same_language(l, lang))
+        }),
+        None => candidates.min_by_key(|m| (!m.default, !m.autoselect)),
+    }
+}
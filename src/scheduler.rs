@@ -0,0 +1,279 @@
+use crate::i18n::tr;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::{info, warn};
+use url::Url;
+
+/// 两次降低并发之间的最短间隔，同一波限流只降一次
+const COOLDOWN: Duration = Duration::from_secs(5);
+
+/// 所有任务 (批量任务与守护进程模式提交的任务) 共用的切片下载额度：
+/// 全局最多 `--concurrency` 个切片请求，同一主机最多 `--per-host` 个
+pub struct Scheduler {
+    global: Arc<Semaphore>,
+    per_host: usize,
+    hosts: Mutex<HashMap<String, Arc<Semaphore>>>,
+    /// `--adaptive-concurrency`：超时与没有 Retry-After 的限流也降低并发
+    adaptive: bool,
+    /// 当前全局并发，降低后随成功的切片逐步恢复
+    state: Mutex<Adaptive>,
+    /// 服务器要求的 Retry-After 结束前不发出新请求
+    paused_until: Mutex<Option<Instant>>,
+}
+
+struct Adaptive {
+    max: usize,
+    limit: usize,
+    /// 上次调整以来连续成功的切片数
+    successes: usize,
+    last_cut: Option<Instant>,
+}
+
+/// 持有期间占用一个全局额度与一个主机额度
+pub struct Permit {
+    _host: Option<OwnedSemaphorePermit>,
+    _global: OwnedSemaphorePermit,
+}
+
+impl Scheduler {
+    /// `per_host` 为 0 时不限制单个主机；`adaptive` 时全局并发从 `concurrency` 开始按服务器的反馈调整
+    pub fn new(concurrency: usize, per_host: usize, adaptive: bool) -> Self {
+        let concurrency = concurrency.max(1);
+        Self {
+            global: Arc::new(Semaphore::new(concurrency)),
+            per_host,
+            hosts: Mutex::new(HashMap::new()),
+            adaptive,
+            state: Mutex::new(Adaptive {
+                max: concurrency,
+                limit: concurrency,
+                successes: 0,
+                last_cut: None,
+            }),
+            paused_until: Mutex::new(None),
+        }
+    }
+
+    /// 等待下载 `location` 的额度，服务器要求的 Retry-After 结束前不发放。先取主机额度再取全局额度，
+    /// 避免排队等某个主机的请求占着全局额度，让其他主机的切片也等着
+    pub async fn acquire(&self, location: &str) -> Result<Permit> {
+        while let Some(wait) = self.paused() {
+            tokio::time::sleep(wait).await;
+        }
+        let host = match self.host(location) {
+            Some(host) => Some(host.acquire_owned().await?),
+            None => None,
+        };
+        Ok(Permit {
+            _host: host,
+            _global: self.global.clone().acquire_owned().await?,
+        })
+    }
+
+    /// 切片请求遇到限流 (429/503) 或超时：`--adaptive-concurrency` 时降低并发
+    pub fn congested(&self) {
+        if self.adaptive {
+            self.cut();
+        }
+    }
+
+    /// 服务器返回 429/503 并带有 Retry-After：所有任务暂停发出新请求，直到等待时间结束，
+    /// 并且不论是否指定 `--adaptive-concurrency` 都暂时降低并发
+    pub fn throttled(&self, wait: Duration) {
+        let until = Instant::now() + wait;
+        {
+            let mut paused = self.paused_until.lock().unwrap_or_else(|e| e.into_inner());
+            if paused.is_some_and(|t| t >= until) {
+                return;
+            }
+            *paused = Some(until);
+        }
+        warn!(
+            "{}",
+            tr!("服务器限流，暂停 {} 秒后继续下载", wait.as_secs())
+        );
+        self.cut();
+    }
+
+    /// 暂停剩余的时间
+    fn paused(&self) -> Option<Duration> {
+        let paused = self.paused_until.lock().unwrap_or_else(|e| e.into_inner());
+        paused
+            .map(|t| t.saturating_duration_since(Instant::now()))
+            .filter(|wait| !wait.is_zero())
+    }
+
+    /// 并发减半，至少保留 1 个。多出的额度等正在进行的请求归还后收回，期间不再发出新请求
+    fn cut(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.limit == 1 || state.last_cut.is_some_and(|t| t.elapsed() < COOLDOWN) {
+            return;
+        }
+        let cut = state.limit / 2;
+        state.limit -= cut;
+        state.successes = 0;
+        state.last_cut = Some(Instant::now());
+        warn!("{}", tr!("服务器限流或请求超时，并发降至 {}", state.limit));
+        let global = self.global.clone();
+        tokio::spawn(async move {
+            if let Ok(permits) = global.acquire_many_owned(cut as u32).await {
+                permits.forget();
+            }
+        });
+    }
+
+    /// 切片下载成功：连续成功两倍于当前并发数的切片后并发加 1，直到 `--concurrency`
+    pub fn succeeded(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.limit >= state.max {
+            return;
+        }
+        state.successes += 1;
+        if state.successes < state.limit * 2
+            || state.last_cut.is_some_and(|t| t.elapsed() < COOLDOWN)
+        {
+            return;
+        }
+        state.limit += 1;
+        state.successes = 0;
+        self.global.add_permits(1);
+        info!("{}", tr!("服务器恢复正常，并发升至 {}", state.limit));
+    }
+
+    fn host(&self, location: &str) -> Option<Arc<Semaphore>> {
+        if self.per_host == 0 {
+            return None;
+        }
+        let url = Url::parse(location).ok()?;
+        let key = format!("{}:{}", url.host_str()?, url.port_or_known_default()?);
+        let mut hosts = self.hosts.lock().unwrap_or_else(|e| e.into_inner());
+        let host = hosts
+            .entry(key)
+            .or_insert_with(|| Arc::new(Semaphore::new(self.per_host)));
+        Some(host.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 让 `cut` 派生的回收额度任务先执行完
+    async fn settle() {
+        for _ in 0..4 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    fn limit(scheduler: &Scheduler) -> usize {
+        scheduler.state.lock().unwrap().limit
+    }
+
+    /// 把上次降低并发的时间移到冷却期之前
+    fn cool_down(scheduler: &Scheduler) {
+        scheduler.state.lock().unwrap().last_cut = Instant::now().checked_sub(COOLDOWN);
+    }
+
+    #[tokio::test]
+    async fn cut_halves_once_per_cooldown() {
+        let scheduler = Scheduler::new(8, 0, true);
+        scheduler.congested();
+        settle().await;
+        assert_eq!(limit(&scheduler), 4);
+        assert_eq!(scheduler.global.available_permits(), 4);
+
+        // 同一波限流只降一次
+        scheduler.congested();
+        settle().await;
+        assert_eq!(limit(&scheduler), 4);
+
+        cool_down(&scheduler);
+        scheduler.congested();
+        settle().await;
+        assert_eq!(limit(&scheduler), 2);
+        assert_eq!(scheduler.global.available_permits(), 2);
+    }
+
+    #[tokio::test]
+    async fn cut_keeps_at_least_one() {
+        let scheduler = Scheduler::new(1, 0, true);
+        scheduler.congested();
+        settle().await;
+        assert_eq!(limit(&scheduler), 1);
+        assert_eq!(scheduler.global.available_permits(), 1);
+    }
+
+    #[tokio::test]
+    async fn congested_needs_adaptive() {
+        let scheduler = Scheduler::new(8, 0, false);
+        scheduler.congested();
+        settle().await;
+        assert_eq!(limit(&scheduler), 8);
+        assert_eq!(scheduler.global.available_permits(), 8);
+    }
+
+    #[tokio::test]
+    async fn succeeded_restores_one_step_at_a_time() {
+        let scheduler = Scheduler::new(4, 0, true);
+        scheduler.congested();
+        settle().await;
+        assert_eq!(limit(&scheduler), 2);
+
+        for _ in 0..3 {
+            scheduler.succeeded();
+        }
+        assert_eq!(limit(&scheduler), 2);
+        // 冷却期内成功的切片照常计数，但要等冷却期过后才恢复
+        scheduler.succeeded();
+        assert_eq!(limit(&scheduler), 2);
+        cool_down(&scheduler);
+        scheduler.succeeded();
+        assert_eq!(limit(&scheduler), 3);
+        assert_eq!(scheduler.global.available_permits(), 3);
+
+        for _ in 0..5 {
+            scheduler.succeeded();
+        }
+        assert_eq!(limit(&scheduler), 3);
+        scheduler.succeeded();
+        assert_eq!(limit(&scheduler), 4);
+        // 已回到 --concurrency，不再增加
+        for _ in 0..20 {
+            scheduler.succeeded();
+        }
+        assert_eq!(limit(&scheduler), 4);
+        assert_eq!(scheduler.global.available_permits(), 4);
+    }
+
+    #[tokio::test]
+    async fn throttled_pauses_and_cuts_without_adaptive() {
+        let scheduler = Scheduler::new(8, 0, false);
+        scheduler.throttled(Duration::from_secs(30));
+        settle().await;
+        assert_eq!(limit(&scheduler), 4);
+        assert!(
+            scheduler
+                .paused()
+                .is_some_and(|wait| wait > Duration::from_secs(25))
+        );
+
+        // 更短的 Retry-After 不会提前结束暂停
+        scheduler.throttled(Duration::from_secs(1));
+        assert!(
+            scheduler
+                .paused()
+                .is_some_and(|wait| wait > Duration::from_secs(25))
+        );
+    }
+
+    #[test]
+    fn pause_ends_after_retry_after() {
+        let scheduler = Scheduler::new(2, 0, false);
+        assert!(scheduler.paused().is_none());
+        *scheduler.paused_until.lock().unwrap() = Instant::now().checked_sub(COOLDOWN);
+        assert!(scheduler.paused().is_none());
+    }
+}
@@ -0,0 +1,82 @@
+use anyhow::{Context, Result, bail};
+use futures::future::BoxFuture;
+use reqwest::header::{HeaderName, HeaderValue};
+use rhai::{AST, Dynamic, Engine, Map, Scope};
+use std::path::Path;
+
+use crate::hooks::{Hook, HookRequest};
+use crate::i18n::tr;
+
+/// 脚本中处理切片请求的函数名
+const ON_REQUEST: &str = "on_request";
+
+/// `--script` 指定的 Rhai 脚本，用于签名、轮换主机、计算请求头等站点特定的请求改写。
+/// 脚本定义 `fn on_request(req)`，`req` 为 `#{url: "...", headers: #{...}}`，
+/// 返回改写后的对象 (只返回字符串时视为新的地址)
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+}
+
+impl Script {
+    pub fn load(path: &Path) -> Result<Self> {
+        let engine = Engine::new();
+        let ast = engine
+            .compile_file(path.to_path_buf())
+            .map_err(|e| anyhow::anyhow!("{}", e))
+            .with_context(|| tr!("无法加载脚本: {}", path.display()))?;
+        if !ast.iter_functions().any(|f| f.name == ON_REQUEST) {
+            bail!(tr!(
+                "脚本 {} 中没有定义 {} 函数",
+                path.display(),
+                ON_REQUEST
+            ));
+        }
+        Ok(Self { engine, ast })
+    }
+
+    fn call(&self, request: &mut HookRequest) -> Result<()> {
+        let mut headers = Map::new();
+        for (name, value) in &request.headers {
+            if let Ok(value) = value.to_str() {
+                headers.insert(name.as_str().into(), value.to_string().into());
+            }
+        }
+        let mut req = Map::new();
+        req.insert("url".into(), request.url.clone().into());
+        req.insert("headers".into(), headers.into());
+
+        let result: Dynamic = self
+            .engine
+            .call_fn(&mut Scope::new(), &self.ast, ON_REQUEST, (req,))
+            .map_err(|e| anyhow::anyhow!(tr!("脚本 {} 执行失败: {}", ON_REQUEST, e)))?;
+        if result.is_string() {
+            request.url = result.to_string();
+            return Ok(());
+        }
+        let Some(mut req) = result.try_cast::<Map>() else {
+            bail!(tr!("{} 应返回请求对象或地址字符串", ON_REQUEST));
+        };
+        if let Some(url) = req.remove("url") {
+            request.url = url.to_string();
+        }
+        let Some(headers) = req.remove("headers").and_then(|h| h.try_cast::<Map>()) else {
+            return Ok(());
+        };
+        request.headers.clear();
+        for (name, value) in headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .with_context(|| tr!("脚本返回了无效的请求头名称: {}", name))?;
+            let value = HeaderValue::from_str(&value.to_string())
+                .with_context(|| tr!("脚本返回了无效的请求头值: {}", name))?;
+            request.headers.insert(name, value);
+        }
+        Ok(())
+    }
+}
+
+impl Hook for Script {
+    fn before_request<'a>(&'a self, request: &'a mut HookRequest) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move { self.call(request) })
+    }
+}
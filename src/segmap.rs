@@ -0,0 +1,109 @@
+use crate::i18n::tr;
+use indicatif::ProgressBar;
+use std::sync::Mutex;
+
+/// 切片地图最多占用的行数
+const MAX_ROWS: usize = 4;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SegmentState {
+    Pending,
+    Downloading,
+    Done,
+    Failed,
+    Skipped,
+}
+
+/// 实时渲染每个切片的状态，失败集中在某一段或某个 CDN 分片时一眼可见。
+/// 切片数超过终端宽度时，每个格子代表连续的若干切片，显示其中最需要关注的状态
+pub struct SegmentMap {
+    states: Mutex<Vec<SegmentState>>,
+    pb: ProgressBar,
+    ascii: bool,
+}
+
+impl SegmentMap {
+    pub fn new(total: usize, pb: ProgressBar, ascii: bool) -> Self {
+        let map = Self {
+            states: Mutex::new(vec![SegmentState::Pending; total]),
+            pb,
+            ascii,
+        };
+        map.render(&map.states.lock().unwrap());
+        map
+    }
+
+    pub fn set(&self, index: usize, state: SegmentState) {
+        let mut states = self.states.lock().unwrap();
+        if let Some(s) = states.get_mut(index) {
+            *s = state;
+        }
+        self.render(&states);
+    }
+
+    pub fn finish(&self) {
+        self.pb.finish();
+    }
+
+    fn symbol(&self, state: SegmentState) -> char {
+        match (state, self.ascii) {
+            (SegmentState::Pending, false) => '·',
+            (SegmentState::Downloading, false) => '▒',
+            (SegmentState::Done, false) => '█',
+            (SegmentState::Pending, true) => '.',
+            (SegmentState::Downloading, true) => '>',
+            (SegmentState::Done, true) => '#',
+            (SegmentState::Failed, _) => 'X',
+            (SegmentState::Skipped, _) => '-',
+        }
+    }
+
+    fn render(&self, states: &[SegmentState]) {
+        if states.is_empty() {
+            return;
+        }
+        let width = (console::Term::stderr().size().1 as usize)
+            .saturating_sub(2)
+            .max(20);
+        let cells = states.len().min(width * MAX_ROWS);
+        let per_cell = states.len().div_ceil(cells);
+
+        let mut map = String::new();
+        for (i, chunk) in states.chunks(per_cell).enumerate() {
+            if i > 0 && i % width == 0 {
+                map.push('\n');
+            }
+            map.push(self.symbol(summarize(chunk)));
+        }
+
+        let count = |s: SegmentState| states.iter().filter(|x| **x == s).count();
+        map.push_str(&tr!(
+            "\n{} 完成 {}  {} 下载中 {}  {} 等待 {}  {} 失败 {}  {} 跳过 {}  (每格 {} 个切片)",
+            self.symbol(SegmentState::Done),
+            count(SegmentState::Done),
+            self.symbol(SegmentState::Downloading),
+            count(SegmentState::Downloading),
+            self.symbol(SegmentState::Pending),
+            count(SegmentState::Pending),
+            self.symbol(SegmentState::Failed),
+            count(SegmentState::Failed),
+            self.symbol(SegmentState::Skipped),
+            count(SegmentState::Skipped),
+            per_cell
+        ));
+        self.pb.set_message(map);
+    }
+}
+
+/// 一个格子内按 失败 > 下载中 > 等待 > 跳过 > 完成 的优先级取状态
+fn summarize(chunk: &[SegmentState]) -> SegmentState {
+    [
+        SegmentState::Failed,
+        SegmentState::Downloading,
+        SegmentState::Pending,
+        SegmentState::Skipped,
+    ]
+    .into_iter()
+    .find(|s| chunk.contains(s))
+    .unwrap_or(SegmentState::Done)
+}
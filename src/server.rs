@@ -0,0 +1,596 @@
+use anyhow::{Context, Result};
+use axum::{
+    Json, Router,
+    extract::{
+        Path, Query, State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast::{Receiver, error::RecvError};
+use tokio::sync::{Semaphore, watch};
+use tokio::task::AbortHandle;
+use tracing::{info, warn};
+
+use crate::control;
+use crate::events::JobEvent;
+use crate::i18n::tr;
+use crate::metrics::Metrics;
+use crate::naming::{self, Metadata, NamingStrategy};
+use crate::ui::Ui;
+use crate::{Args, Job, Shared, default_extension, run_job};
+
+/// 计算下载速度的最短采样间隔
+const SPEED_WINDOW: Duration = Duration::from_secs(1);
+
+/// WebSocket 推送进度的间隔，期间有变化的任务合并为一条消息
+const PUSH_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum JobStatus {
+    Queued,
+    Running,
+    Paused,
+    Complete,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    const ALL: [JobStatus; 6] = [
+        JobStatus::Queued,
+        JobStatus::Running,
+        JobStatus::Paused,
+        JobStatus::Complete,
+        JobStatus::Failed,
+        JobStatus::Cancelled,
+    ];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Running => "running",
+            JobStatus::Paused => "paused",
+            JobStatus::Complete => "complete",
+            JobStatus::Failed => "failed",
+            JobStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+/// 任务记录，`GET /jobs` 与 `GET /jobs/{id}` 直接返回它的 JSON
+#[derive(Clone, Serialize)]
+struct JobRecord {
+    id: usize,
+    url: String,
+    output: String,
+    status: JobStatus,
+    /// 已完成 / 总切片数，直播录制时总数为 0
+    completed: u64,
+    total: u64,
+    bytes: u64,
+    retries: u64,
+    /// 转码进度 (0-100)
+    transcode: Option<f64>,
+    /// 当前阶段：playlist / download / merge / transcode
+    stage: Option<&'static str>,
+    /// 最近一段时间的下载速度 (字节/秒)
+    speed: u64,
+    error: Option<String>,
+    created: u64,
+    finished: Option<u64>,
+    /// 计算下载速度的上一个采样点 (时间, 已下载字节数)
+    #[serde(skip)]
+    sample: Option<(Instant, u64)>,
+}
+
+impl JobRecord {
+    fn is_finished(&self) -> bool {
+        matches!(
+            self.status,
+            JobStatus::Complete | JobStatus::Failed | JobStatus::Cancelled
+        )
+    }
+
+    /// 按最近一次采样以来的字节数更新下载速度，采样间隔至少 1 秒
+    fn update_speed(&mut self) {
+        let now = Instant::now();
+        match self.sample {
+            Some((at, bytes)) if now - at >= SPEED_WINDOW => {
+                let elapsed = (now - at).as_secs_f64();
+                self.speed = ((self.bytes - bytes) as f64 / elapsed) as u64;
+                self.sample = Some((now, self.bytes));
+            }
+            Some(_) => {}
+            None => self.sample = Some((now, self.bytes)),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SubmitRequest {
+    url: String,
+    /// 输出文件名，只取文件名部分，保存在 `--output-dir` 下；
+    /// 省略时按 `--naming` 策略或 `job_<id>.mp4` 命名
+    output: Option<String>,
+    /// 命名用的标题、季/集与日期，缺省项沿用启动参数
+    #[serde(flatten)]
+    meta: Metadata,
+}
+
+type ApiError = (StatusCode, Json<serde_json::Value>);
+
+fn api_error(status: StatusCode, message: impl Into<String>) -> ApiError {
+    (status, Json(json!({ "error": message.into() })))
+}
+
+/// 守护进程状态：所有任务共用启动时的参数、连接池与并发额度
+struct Daemon {
+    args: Arc<Args>,
+    shared: Arc<Shared>,
+    ui: Ui,
+    output_dir: PathBuf,
+    namer: Option<Box<dyn NamingStrategy>>,
+    meta: Metadata,
+    /// 同时执行的任务数，对应 `--jobs`
+    slots: Arc<Semaphore>,
+    next_id: AtomicUsize,
+    jobs: Mutex<BTreeMap<usize, JobRecord>>,
+    tasks: Mutex<HashMap<usize, AbortHandle>>,
+    /// 停止守护进程时通知 WebSocket 连接断开，否则平滑关闭会一直等待这些连接
+    stopping: watch::Sender<bool>,
+    metrics: Metrics,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl Daemon {
+    fn update(&self, id: usize, f: impl FnOnce(&mut JobRecord)) {
+        if let Some(record) = self.jobs.lock().unwrap().get_mut(&id) {
+            f(record);
+        }
+    }
+
+    fn submit(self: &Arc<Self>, req: SubmitRequest) -> Result<JobRecord, ApiError> {
+        let explicit = req
+            .output
+            .as_deref()
+            .map(std::path::Path::new)
+            .and_then(|p| p.file_name())
+            .map(|name| self.output_dir.join(name));
+        let output = match (explicit, &self.namer) {
+            (Some(output), _) => Some(output),
+            (None, Some(namer)) => Some(
+                naming::output_path(
+                    namer.as_ref(),
+                    &req.meta.or(&self.meta),
+                    &req.url,
+                    &self.output_dir,
+                    default_extension(&self.args),
+                )
+                .map_err(|e| api_error(StatusCode::BAD_REQUEST, format!("{:#}", e)))?,
+            ),
+            (None, None) => None,
+        };
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let job = Job {
+            id,
+            url: req.url,
+            output: output.unwrap_or_else(|| {
+                let ext = default_extension(&self.args);
+                self.output_dir.join(format!("job_{}.{}", id, ext))
+            }),
+        };
+
+        let record = JobRecord {
+            id,
+            url: job.url.clone(),
+            output: job.output.display().to_string(),
+            status: JobStatus::Queued,
+            completed: 0,
+            total: 0,
+            bytes: 0,
+            retries: 0,
+            transcode: None,
+            stage: None,
+            speed: 0,
+            error: None,
+            created: now(),
+            finished: None,
+            sample: None,
+        };
+        self.jobs.lock().unwrap().insert(id, record.clone());
+        self.shared.events.emit(JobEvent::Queued {
+            job: id,
+            url: record.url.clone(),
+            output: record.output.clone(),
+        });
+        info!(
+            "{}",
+            tr!("收到任务 #{}: {} -> {}", id, record.url, record.output)
+        );
+
+        // 持有锁期间启动任务，保证任务结束时一定能找到并移除自己的句柄
+        let mut tasks = self.tasks.lock().unwrap();
+        let daemon = self.clone();
+        let handle = tokio::spawn(async move {
+            let _slot = daemon.slots.clone().acquire_owned().await;
+            let result = run_job(&job, &daemon.args, &daemon.shared, &daemon.ui).await;
+            daemon.update(id, |r| {
+                match &result {
+                    Ok(()) => r.status = JobStatus::Complete,
+                    Err(e) => {
+                        r.status = JobStatus::Failed;
+                        r.error = Some(format!("{:#}", e));
+                    }
+                }
+                r.finished = Some(now());
+            });
+            daemon.tasks.lock().unwrap().remove(&id);
+            daemon.shared.control.forget(id);
+        });
+        tasks.insert(id, handle.abort_handle());
+        Ok(record)
+    }
+
+    fn cancel(&self, id: usize) -> Result<JobRecord, ApiError> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let record = jobs
+            .get_mut(&id)
+            .ok_or_else(|| api_error(StatusCode::NOT_FOUND, tr!("任务不存在")))?;
+        if record.is_finished() {
+            return Err(api_error(StatusCode::CONFLICT, tr!("任务已结束")));
+        }
+        self.shared.control.set(Some(id), control::State::Cancelled);
+        if let Some(handle) = self.tasks.lock().unwrap().remove(&id) {
+            handle.abort();
+        }
+        self.shared.control.forget(id);
+        record.status = JobStatus::Cancelled;
+        record.finished = Some(now());
+        self.shared.events.emit(JobEvent::Cancelled { job: id });
+        info!("{}", tr!("任务 #{} 已取消", id));
+        Ok(record.clone())
+    }
+
+    /// 暂停或恢复任务：正在进行的切片请求完成后停止，恢复后从下一个切片继续
+    fn pause(&self, id: usize, paused: bool) -> Result<JobRecord, ApiError> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let record = jobs
+            .get_mut(&id)
+            .ok_or_else(|| api_error(StatusCode::NOT_FOUND, tr!("任务不存在")))?;
+        if record.is_finished() {
+            return Err(api_error(StatusCode::CONFLICT, tr!("任务已结束")));
+        }
+        let (state, status) = if paused {
+            (control::State::Paused, JobStatus::Paused)
+        } else {
+            (control::State::Running, JobStatus::Running)
+        };
+        self.shared.control.set(Some(id), state);
+        record.status = status;
+        info!(
+            "{}",
+            tr!(
+                "任务 #{} 已{}",
+                id,
+                if paused { tr!("暂停") } else { tr!("恢复") }
+            )
+        );
+        Ok(record.clone())
+    }
+
+    fn abort_all(&self) {
+        for (_, handle) in self.tasks.lock().unwrap().drain() {
+            handle.abort();
+        }
+    }
+}
+
+/// 订阅事件总线，把进度写回任务记录；结束状态由任务本身写入
+async fn track(daemon: Arc<Daemon>, mut rx: Receiver<JobEvent>) {
+    loop {
+        let event = match rx.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(n)) => {
+                warn!("{}", tr!("任务进度更新跟不上，丢弃了 {} 条事件", n));
+                continue;
+            }
+            Err(RecvError::Closed) => break,
+        };
+        daemon.metrics.observe(&event);
+        let id = match &event {
+            JobEvent::Started { job, .. }
+            | JobEvent::PlaylistParsed { job, .. }
+            | JobEvent::SegmentDone { job, .. }
+            | JobEvent::Retry { job, .. }
+            | JobEvent::Merge { job, .. }
+            | JobEvent::Transcode { job, .. } => *job,
+            _ => continue,
+        };
+        daemon.update(id, |r| {
+            if r.is_finished() {
+                return;
+            }
+            match event {
+                JobEvent::Started { .. } => {
+                    r.stage = Some("playlist");
+                    if r.status != JobStatus::Paused {
+                        r.status = JobStatus::Running;
+                    }
+                }
+                JobEvent::PlaylistParsed { segments, .. } => {
+                    r.stage = Some("download");
+                    r.total = segments as u64;
+                }
+                JobEvent::SegmentDone {
+                    bytes,
+                    completed,
+                    total,
+                    ..
+                } => {
+                    r.completed = completed;
+                    r.total = total;
+                    r.bytes += bytes;
+                    r.update_speed();
+                }
+                JobEvent::Retry { .. } => r.retries += 1,
+                JobEvent::Merge { .. } => {
+                    r.stage = Some("merge");
+                    r.speed = 0;
+                }
+                JobEvent::Transcode {
+                    out_time, duration, ..
+                } => {
+                    r.stage = Some("transcode");
+                    r.speed = 0;
+                    if duration > 0.0 {
+                        r.transcode = Some((out_time / duration * 100.0).min(100.0));
+                    }
+                }
+                _ => {}
+            }
+        });
+    }
+}
+
+async fn list_jobs(State(daemon): State<Arc<Daemon>>) -> Json<Vec<JobRecord>> {
+    Json(daemon.jobs.lock().unwrap().values().cloned().collect())
+}
+
+async fn submit_job(
+    State(daemon): State<Arc<Daemon>>,
+    Json(req): Json<SubmitRequest>,
+) -> Result<(StatusCode, Json<JobRecord>), ApiError> {
+    if req.url.trim().is_empty() {
+        return Err(api_error(StatusCode::BAD_REQUEST, tr!("缺少 url")));
+    }
+    Ok((StatusCode::CREATED, Json(daemon.submit(req)?)))
+}
+
+async fn get_job(
+    State(daemon): State<Arc<Daemon>>,
+    Path(id): Path<usize>,
+) -> Result<Json<JobRecord>, ApiError> {
+    daemon
+        .jobs
+        .lock()
+        .unwrap()
+        .get(&id)
+        .cloned()
+        .map(Json)
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, tr!("任务不存在")))
+}
+
+async fn pause_job(
+    State(daemon): State<Arc<Daemon>>,
+    Path(id): Path<usize>,
+) -> Result<Json<JobRecord>, ApiError> {
+    daemon.pause(id, true).map(Json)
+}
+
+async fn resume_job(
+    State(daemon): State<Arc<Daemon>>,
+    Path(id): Path<usize>,
+) -> Result<Json<JobRecord>, ApiError> {
+    daemon.pause(id, false).map(Json)
+}
+
+async fn cancel_job(
+    State(daemon): State<Arc<Daemon>>,
+    Path(id): Path<usize>,
+) -> Result<Json<JobRecord>, ApiError> {
+    daemon.cancel(id).map(Json)
+}
+
+/// `GET /metrics`：Prometheus 文本格式的累计指标与各状态的任务数
+async fn metrics(State(daemon): State<Arc<Daemon>>) -> impl IntoResponse {
+    let jobs: Vec<(&str, u64)> = {
+        let records = daemon.jobs.lock().unwrap();
+        JobStatus::ALL
+            .iter()
+            .map(|status| {
+                let count = records.values().filter(|r| r.status == *status).count();
+                (status.as_str(), count as u64)
+            })
+            .collect()
+    };
+    (
+        [(
+            header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )],
+        daemon.metrics.render(&jobs),
+    )
+}
+
+#[derive(Deserialize)]
+struct ProgressFilter {
+    /// 只推送某个任务的进度
+    job: Option<usize>,
+}
+
+async fn progress_ws(
+    State(daemon): State<Arc<Daemon>>,
+    Query(filter): Query<ProgressFilter>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    ws.on_upgrade(move |socket| push_progress(socket, daemon, filter.job))
+}
+
+/// `GET /ws`：连接后先推送现有任务的记录，之后每隔 [`PUSH_INTERVAL`] 推送有变化的任务，
+/// 每个任务一条 JSON 消息，格式与 `GET /jobs/{id}` 相同
+async fn push_progress(mut socket: WebSocket, daemon: Arc<Daemon>, job: Option<usize>) {
+    let mut rx = daemon.shared.events.subscribe();
+    let mut stopping = daemon.stopping.subscribe();
+    let mut tick = tokio::time::interval(PUSH_INTERVAL);
+    let wanted = |id: usize| job.is_none_or(|job| job == id);
+    let mut changed: BTreeSet<usize> = daemon
+        .jobs
+        .lock()
+        .unwrap()
+        .keys()
+        .copied()
+        .filter(|id| wanted(*id))
+        .collect();
+    loop {
+        tokio::select! {
+            event = rx.recv() => match event {
+                Ok(event) if wanted(event.job()) => {
+                    changed.insert(event.job());
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => break,
+            },
+            _ = tick.tick() => {
+                let records: Vec<JobRecord> = {
+                    let jobs = daemon.jobs.lock().unwrap();
+                    changed.iter().filter_map(|id| jobs.get(id).cloned()).collect()
+                };
+                changed.clear();
+                for record in records {
+                    let Ok(text) = serde_json::to_string(&record) else {
+                        continue;
+                    };
+                    if socket.send(Message::Text(text.into())).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+            _ = stopping.changed() => break,
+        }
+    }
+    let _ = socket.send(Message::Close(None)).await;
+}
+
+/// 输出目录中已有的 `job_<id>.*` 文件与临时目录的最大编号。重启后从其后编号，
+/// 默认文件名不会与之前的任务冲突
+fn last_job_id(dir: &std::path::Path) -> usize {
+    std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name();
+            let rest = name.to_str()?.strip_prefix("job_")?;
+            rest.split('.').next()?.parse().ok()
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// `serve` 子命令：常驻运行并通过 HTTP 接口接收下载任务，直到收到 Ctrl-C
+pub async fn run(
+    listen: &str,
+    output_dir: PathBuf,
+    args: Arc<Args>,
+    shared: Arc<Shared>,
+    ui: Ui,
+) -> Result<()> {
+    tokio::fs::create_dir_all(&output_dir)
+        .await
+        .with_context(|| tr!("无法创建输出目录: {}", output_dir.display()))?;
+
+    let next_id = last_job_id(&output_dir) + 1;
+    let daemon = Arc::new(Daemon {
+        namer: naming::strategy(&args)?,
+        meta: Metadata::from_args(&args),
+        slots: Arc::new(Semaphore::new(args.jobs.max(1))),
+        args,
+        shared,
+        ui,
+        output_dir,
+        next_id: AtomicUsize::new(next_id),
+        jobs: Mutex::new(BTreeMap::new()),
+        tasks: Mutex::new(HashMap::new()),
+        stopping: watch::channel(false).0,
+        metrics: Metrics::default(),
+    });
+    let tracker = tokio::spawn(track(daemon.clone(), daemon.shared.events.subscribe()));
+
+    let app = Router::new()
+        .route("/jobs", get(list_jobs).post(submit_job))
+        .route("/jobs/{id}", get(get_job).delete(cancel_job))
+        .route("/jobs/{id}/pause", post(pause_job))
+        .route("/jobs/{id}/resume", post(resume_job))
+        .route("/ws", get(progress_ws))
+        .route("/metrics", get(metrics))
+        .with_state(daemon.clone());
+    let listener = tokio::net::TcpListener::bind(listen)
+        .await
+        .with_context(|| tr!("无法监听地址: {}", listen))?;
+    info!(
+        "{}",
+        tr!("守护进程已启动: http://{}", listener.local_addr()?)
+    );
+
+    let stopping = daemon.clone();
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async move {
+            let _ = tokio::signal::ctrl_c().await;
+            info!("{}", tr!("收到中断信号，停止守护进程"));
+            stopping.stopping.send_replace(true);
+        })
+        .await?;
+
+    daemon.abort_all();
+    tracker.abort();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn job_ids_continue_after_existing_outputs() {
+        let dir = std::env::temp_dir().join(format!("m3u8-serve-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        assert_eq!(last_job_id(&dir), 0);
+        for name in ["job_2.mp4", "job_12.parts", "job_x.mp4", "other_30.mp4"] {
+            std::fs::write(dir.join(name), b"").unwrap();
+        }
+        let found = last_job_id(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(found, 12);
+        assert_eq!(last_job_id(&dir), 0);
+    }
+}
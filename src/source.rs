@@ -0,0 +1,447 @@
+use anyhow::{Context, Result, bail};
+use futures::future::BoxFuture;
+use reqwest::StatusCode;
+use reqwest::header::{
+    ACCEPT_ENCODING, CONTENT_RANGE, ETAG, HeaderMap, IF_RANGE, LAST_MODIFIED, RANGE, RETRY_AFTER,
+};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::debug;
+use url::Url;
+
+use crate::hooks::{HookRequest, Hooks};
+use crate::i18n::tr;
+use crate::proxy::ProxyPool;
+use crate::query::Inherited;
+use crate::recovery::RetryAfter;
+use crate::spool::{Spool, SpoolTarget};
+
+/// `--refresh-cmd` 更新的请求头 (例如新的鉴权令牌)，附加在之后的切片请求上
+pub type SharedHeaders = Arc<RwLock<HeaderMap>>;
+
+/// 读取到的切片内容
+pub struct Fetched {
+    pub data: Vec<u8>,
+    /// 响应头 Content-Length 声明的字节数，本地文件、分块传输或压缩传输 (已自动解压) 时为 None
+    pub expected: Option<u64>,
+    /// 超过缓冲上限、已边下载边解密写入磁盘的切片，此时 `data` 为空
+    pub spool: Option<Spool>,
+}
+
+impl Fetched {
+    /// 收到的字节数 (解密前)
+    pub fn received(&self) -> u64 {
+        match &self.spool {
+            Some(spool) => spool.received(),
+            None => self.data.len() as u64,
+        }
+    }
+}
+
+/// 读取中断的切片已收到的部分，重试时用 Range 请求从断点续传。
+/// 解密在整个切片收齐之后进行，续传的密文与一次下载的完全相同，不受 CBC 分块影响
+#[derive(Default)]
+pub struct Partial {
+    data: Vec<u8>,
+    /// 首次响应的 ETag 或 Last-Modified，续传时作为 If-Range，服务器上的内容变化时会返回完整响应
+    validator: Option<String>,
+    /// 超过 `target` 的缓冲上限后改为写入磁盘
+    spool: Option<Spool>,
+    target: Option<SpoolTarget>,
+}
+
+impl Partial {
+    /// `target` 为 None 时整个切片都缓冲在内存中
+    pub fn new(target: Option<SpoolTarget>) -> Self {
+        Self {
+            target,
+            ..Self::default()
+        }
+    }
+
+    /// 保留一次不完整的响应，供下次续传
+    pub fn keep(&mut self, fetched: Fetched) {
+        self.data = fetched.data;
+        self.spool = fetched.spool;
+    }
+
+    fn received(&self) -> u64 {
+        match &self.spool {
+            Some(spool) => spool.received(),
+            None => self.data.len() as u64,
+        }
+    }
+
+    /// 丢弃已收到的部分，从头接收
+    fn restart(&mut self, validator: Option<String>) {
+        self.data.clear();
+        self.spool = None;
+        self.validator = validator;
+    }
+
+    fn push(&mut self, chunk: &[u8]) -> Result<()> {
+        if let Some(spool) = &mut self.spool {
+            return spool.write(chunk);
+        }
+        self.data.extend_from_slice(chunk);
+        if let Some(target) = self.target.as_ref().filter(|t| self.data.len() > t.cap) {
+            let mut spool = Spool::create(target)?;
+            spool.write(&std::mem::take(&mut self.data))?;
+            self.spool = Some(spool);
+        }
+        Ok(())
+    }
+}
+
+/// 切片来源：把播放列表中的切片 URI 定位并读取为字节，
+/// 之后的解密、合并与转码流程与来源无关
+pub trait SegmentSource: Send + Sync {
+    /// 把切片 URI 解析为完整位置 (URL 或文件路径)，用于读取与日志
+    fn locate(&self, uri: &str) -> Result<String>;
+
+    /// 读取切片；`partial` 中有上次中断时收到的字节时尽量从断点续传，
+    /// 本次读取中断时已收到的字节留在 `partial` 中
+    fn fetch<'a>(
+        &'a self,
+        location: &'a str,
+        partial: &'a mut Partial,
+    ) -> BoxFuture<'a, Result<Fetched>>;
+}
+
+/// 通过 HTTP(S) 下载，相对 URI 基于 `base` 解析
+pub struct HttpSource {
+    /// 按 `--proxies` 轮换的客户端
+    clients: Arc<ProxyPool>,
+    base: Option<Url>,
+    query: Inherited,
+    headers: SharedHeaders,
+    hooks: Arc<Hooks>,
+}
+
+impl HttpSource {
+    pub fn new(clients: Arc<ProxyPool>, base: Option<Url>) -> Self {
+        Self {
+            clients,
+            base,
+            query: Inherited::default(),
+            headers: SharedHeaders::default(),
+            hooks: Arc::default(),
+        }
+    }
+
+    /// 每次请求时附加 `headers` 中当前的请求头
+    pub fn with_headers(mut self, headers: SharedHeaders) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// 发出请求前由 `hooks` 改写地址与请求头
+    pub fn with_hooks(mut self, hooks: Arc<Hooks>) -> Self {
+        self.hooks = hooks;
+        self
+    }
+
+    /// 为每个切片地址补上从播放列表地址继承的查询参数
+    pub fn with_query(mut self, query: Inherited) -> Self {
+        self.query = query;
+        self
+    }
+}
+
+impl SegmentSource for HttpSource {
+    fn locate(&self, uri: &str) -> Result<String> {
+        let url = match &self.base {
+            Some(base) => base.join(uri)?,
+            None => Url::parse(uri).with_context(|| tr!("切片地址不是完整 URL: {}", uri))?,
+        };
+        Ok(self.query.apply(url).to_string())
+    }
+
+    fn fetch<'a>(
+        &'a self,
+        location: &'a str,
+        partial: &'a mut Partial,
+    ) -> BoxFuture<'a, Result<Fetched>> {
+        Box::pin(async move {
+            let offset = partial.received();
+            let mut target = HookRequest {
+                url: location.to_string(),
+                headers: self.headers.read().map(|h| h.clone()).unwrap_or_default(),
+            };
+            self.hooks.before_request(&mut target).await?;
+            let (proxy, client) = self.clients.pick();
+            let request = client.get(&target.url).headers(target.headers);
+            let request = resume(request, offset, partial.validator.as_deref());
+            let resp = match request.send().await {
+                Ok(resp) => resp,
+                Err(e) => {
+                    self.clients.failed(proxy);
+                    return Err(e.into());
+                }
+            };
+            debug!("HTTP {} {}", resp.status(), location);
+            if ProxyPool::is_blocked(resp.status()) {
+                self.clients.failed(proxy);
+            }
+            if resp.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+                // 断点已不在内容范围内，下次从头下载
+                partial.restart(None);
+            }
+            let retry_after = retry_after(&resp);
+            let mut resp = match resp.error_for_status() {
+                Ok(resp) => resp,
+                Err(e) => {
+                    return Err(match retry_after {
+                        Some(wait) => anyhow::Error::new(e).context(RetryAfter(wait)),
+                        None => e.into(),
+                    });
+                }
+            };
+            let mut expected = resp.content_length();
+            if offset > 0 && resp.status() == StatusCode::PARTIAL_CONTENT {
+                let start = resp
+                    .headers()
+                    .get(CONTENT_RANGE)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(range_start);
+                if start != Some(offset) {
+                    partial.restart(None);
+                    bail!(tr!(
+                        "续传响应的 Content-Range 与断点 ({} 字节) 不符",
+                        offset
+                    ));
+                }
+                debug!("{}", tr!("从第 {} 字节续传: {}", offset, location));
+                expected = expected.map(|n| n + offset);
+            } else {
+                // 首次请求，或服务器不支持 Range / 内容已变化而返回了完整响应
+                partial.restart(validator(resp.headers()));
+            }
+            while let Some(chunk) = resp.chunk().await? {
+                partial.push(&chunk)?;
+            }
+            Ok(Fetched {
+                data: std::mem::take(&mut partial.data),
+                expected,
+                spool: partial.spool.take(),
+            })
+        })
+    }
+}
+
+/// 从本地目录读取切片，例如从对象存储同步下来的录制文件；
+/// 切片 URI 为 http(s) 地址时仍通过网络下载
+pub struct LocalSource {
+    dir: PathBuf,
+    http: HttpSource,
+}
+
+impl LocalSource {
+    pub fn new(dir: PathBuf, http: HttpSource) -> Self {
+        Self { dir, http }
+    }
+}
+
+impl SegmentSource for LocalSource {
+    fn locate(&self, uri: &str) -> Result<String> {
+        match Url::parse(uri) {
+            Ok(url) if url.scheme() == "file" => Ok(url
+                .to_file_path()
+                .map_err(|_| anyhow::anyhow!(tr!("无效的文件地址: {}", uri)))?
+                .display()
+                .to_string()),
+            Ok(url) if url.scheme().starts_with("http") => Ok(url.to_string()),
+            _ => Ok(self.dir.join(uri).display().to_string()),
+        }
+    }
+
+    fn fetch<'a>(
+        &'a self,
+        location: &'a str,
+        partial: &'a mut Partial,
+    ) -> BoxFuture<'a, Result<Fetched>> {
+        if location.starts_with("http://") || location.starts_with("https://") {
+            return self.http.fetch(location, partial);
+        }
+        Box::pin(async move {
+            let data = tokio::fs::read(location)
+                .await
+                .with_context(|| tr!("无法读取切片: {}", location))?;
+            Ok(Fetched {
+                data,
+                expected: None,
+                spool: None,
+            })
+        })
+    }
+}
+
+/// 按切片基址选择来源：`--segment-base` 优先，其次是播放列表所在的网络目录或本地目录
+pub fn open(
+    segment_base: Option<&str>,
+    playlist_base: Option<Url>,
+    playlist: &str,
+    query: Inherited,
+    clients: Arc<ProxyPool>,
+    headers: SharedHeaders,
+    hooks: Arc<Hooks>,
+) -> Result<Arc<dyn SegmentSource>> {
+    let http = |base| {
+        HttpSource::new(clients.clone(), base)
+            .with_headers(headers.clone())
+            .with_hooks(hooks.clone())
+    };
+    let source: Arc<dyn SegmentSource> = match (segment_base, playlist_base) {
+        (Some(base), _) => match Url::parse(base) {
+            Ok(mut url) if url.scheme().starts_with("http") => {
+                // 保证基址以 / 结尾，否则 join 会替换掉最后一级目录
+                if !url.path().ends_with('/') {
+                    url.set_path(&format!("{}/", url.path()));
+                }
+                Arc::new(http(Some(url)).with_query(query))
+            }
+            _ => Arc::new(LocalSource::new(PathBuf::from(base), http(None))),
+        },
+        (None, Some(base)) => Arc::new(http(Some(base)).with_query(query)),
+        (None, None) => {
+            let dir = std::path::Path::new(playlist)
+                .parent()
+                .map(PathBuf::from)
+                .unwrap_or_default();
+            Arc::new(LocalSource::new(dir, http(None)))
+        }
+    };
+    Ok(source)
+}
+
+/// 服务器要求的最长等待时间，超过时按这个时间处理
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(600);
+
+/// 429/503 响应的 `Retry-After`：秒数或 HTTP 日期
+fn retry_after(resp: &reqwest::Response) -> Option<Duration> {
+    if !matches!(
+        resp.status(),
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+    ) {
+        return None;
+    }
+    let value = resp.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    let wait = match value.parse::<u64>() {
+        Ok(secs) => Duration::from_secs(secs),
+        Err(_) => {
+            let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+            (at.to_utc() - chrono::Utc::now())
+                .to_std()
+                .unwrap_or_default()
+        }
+    };
+    Some(wait.min(MAX_RETRY_AFTER))
+}
+
+/// 从 `offset` 字节续传时附加 Range 与 If-Range，`offset` 为 0 时原样返回
+fn resume(
+    request: reqwest::RequestBuilder,
+    offset: u64,
+    validator: Option<&str>,
+) -> reqwest::RequestBuilder {
+    if offset == 0 {
+        return request;
+    }
+    // 断点按解压后的字节数计算，续传时要求不压缩，Content-Range 才能与断点对应
+    let request = request
+        .header(RANGE, format!("bytes={}-", offset))
+        .header(ACCEPT_ENCODING, "identity");
+    match validator {
+        Some(validator) => request.header(IF_RANGE, validator),
+        None => request,
+    }
+}
+
+/// 续传时用作 If-Range 的 ETag，没有时用 Last-Modified
+fn validator(headers: &HeaderMap) -> Option<String> {
+    [ETAG, LAST_MODIFIED]
+        .iter()
+        .find_map(|h| headers.get(h))
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+/// 解析 `Content-Range: bytes 100-199/200` 的起始位置
+fn range_start(value: &str) -> Option<u64> {
+    value
+        .strip_prefix("bytes ")?
+        .split('-')
+        .next()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(request: reqwest::RequestBuilder) -> HeaderMap {
+        request.build().unwrap().headers().clone()
+    }
+
+    #[test]
+    fn range_start_reads_content_range() {
+        assert_eq!(range_start("bytes 100-199/200"), Some(100));
+        assert_eq!(range_start("bytes 0-99/*"), Some(0));
+        assert_eq!(range_start("bytes */200"), None);
+        assert_eq!(range_start("items 100-199/200"), None);
+    }
+
+    #[test]
+    fn resume_sends_range_and_if_range() {
+        let client = reqwest::Client::new();
+        let url = "https://example.com/seg.ts";
+
+        let fresh = headers(resume(client.get(url), 0, Some("\"v1\"")));
+        assert!(fresh.get(RANGE).is_none());
+        assert!(fresh.get(IF_RANGE).is_none());
+
+        let resumed = headers(resume(client.get(url), 1024, Some("\"v1\"")));
+        assert_eq!(resumed[RANGE], "bytes=1024-");
+        assert_eq!(resumed[ACCEPT_ENCODING], "identity");
+        assert_eq!(resumed[IF_RANGE], "\"v1\"");
+
+        // 首次响应没有 ETag / Last-Modified 时只发 Range
+        let blind = headers(resume(client.get(url), 1024, None));
+        assert_eq!(blind[RANGE], "bytes=1024-");
+        assert!(blind.get(IF_RANGE).is_none());
+    }
+
+    #[test]
+    fn validator_prefers_etag() {
+        let mut map = HeaderMap::new();
+        assert_eq!(validator(&map), None);
+        map.insert(
+            LAST_MODIFIED,
+            "Wed, 21 Oct 2026 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(
+            validator(&map).as_deref(),
+            Some("Wed, 21 Oct 2026 07:28:00 GMT")
+        );
+        map.insert(ETAG, "\"v1\"".parse().unwrap());
+        assert_eq!(validator(&map).as_deref(), Some("\"v1\""));
+    }
+
+    #[test]
+    fn partial_keeps_bytes_until_restart() {
+        let mut partial = Partial::new(None);
+        partial.restart(Some("\"v1\"".to_string()));
+        partial.push(b"abc").unwrap();
+        partial.push(b"de").unwrap();
+        assert_eq!(partial.received(), 5);
+        assert_eq!(partial.validator.as_deref(), Some("\"v1\""));
+
+        // 服务器返回完整响应 (内容已变化) 时从头接收
+        partial.restart(Some("\"v2\"".to_string()));
+        assert_eq!(partial.received(), 0);
+        assert_eq!(partial.validator.as_deref(), Some("\"v2\""));
+    }
+}
@@ -0,0 +1,91 @@
+use anyhow::{Context, Result, bail};
+use indicatif::HumanBytes;
+use m3u8_rs::MediaSegment;
+use reqwest::Client;
+use std::path::Path;
+use tracing::info;
+
+use crate::i18n::tr;
+use crate::source::SegmentSource;
+
+/// 抽样估算大小的切片数
+const SAMPLES: usize = 3;
+
+/// 估算误差的余量
+const MARGIN: f64 = 1.1;
+
+/// 开始下载前检查磁盘空间：抽样几个切片的 Content-Length 按时长折算整个播放列表。
+/// 切片在合并时逐个删除，但合并后的 TS 与转码输出会同时存在，因此按两倍估算；
+/// 已下载的切片 (`downloaded` 字节) 不再重复计算
+pub async fn preflight(
+    client: &Client,
+    source: &dyn SegmentSource,
+    segments: &[MediaSegment],
+    dir: &Path,
+    downloaded: u64,
+) -> Result<()> {
+    let Some(estimate) = estimate(client, source, segments).await else {
+        return Ok(());
+    };
+    let required = ((estimate * 2) as f64 * MARGIN) as u64;
+    let required = required.saturating_sub(downloaded);
+    let available = fs2::available_space(dir)
+        .with_context(|| tr!("无法获取磁盘可用空间: {}", dir.display()))?;
+    if available < required {
+        bail!(tr!(
+            "磁盘空间不足: 预计需要约 {}，{} 所在磁盘只有 {} 可用 (可用 --no-space-check 跳过检查)",
+            HumanBytes(required),
+            dir.display(),
+            HumanBytes(available)
+        ));
+    }
+    info!(
+        "{}",
+        tr!(
+            "预计需要约 {} 磁盘空间，可用 {}",
+            HumanBytes(required),
+            HumanBytes(available)
+        )
+    );
+    Ok(())
+}
+
+/// 对开头、中间、结尾的切片发送 HEAD 请求，按字节/秒折算总大小；
+/// 本地切片或服务器不返回 Content-Length 时返回 None
+pub async fn estimate(
+    client: &Client,
+    source: &dyn SegmentSource,
+    segments: &[MediaSegment],
+) -> Option<u64> {
+    if segments.is_empty() {
+        return None;
+    }
+    let mut picks: Vec<usize> = (0..SAMPLES)
+        .map(|i| i * (segments.len() - 1) / (SAMPLES - 1))
+        .collect();
+    picks.dedup();
+
+    let mut bytes = 0u64;
+    let mut secs = 0.0;
+    for idx in picks {
+        let Ok(location) = source.locate(&segments[idx].uri) else {
+            continue;
+        };
+        if !location.starts_with("http") {
+            continue;
+        }
+        let length = match client.head(&location).send().await {
+            Ok(resp) if resp.status().is_success() => resp.content_length(),
+            _ => None,
+        };
+        if let Some(length) = length.filter(|&l| l > 0) {
+            bytes += length;
+            secs += f64::from(segments[idx].duration);
+        }
+    }
+    if bytes == 0 || secs <= 0.0 {
+        return None;
+    }
+    let total_secs: f64 = segments.iter().map(|s| f64::from(s.duration)).sum();
+    Some((bytes as f64 / secs * total_secs) as u64)
+}
@@ -0,0 +1,102 @@
+use aes::{Aes128, Block};
+use anyhow::{Context, Result};
+use block_modes::block_padding::{Padding, Pkcs7};
+use block_modes::{BlockMode, Cbc};
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::i18n::tr;
+use crate::recovery::Failure;
+
+/// 保留解密后开头的字节数，用于检查是否为媒体数据
+const HEAD_BYTES: usize = 1024;
+
+/// 切片超过内存缓冲上限时写到哪里、如何解密
+#[derive(Clone)]
+pub struct SpoolTarget {
+    /// 内存中最多缓冲的字节数，对应 `--segment-buffer`
+    pub cap: usize,
+    pub path: PathBuf,
+    /// AES-128 的 (密钥, IV)，未加密时为 None
+    pub key: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+/// 边下载边解密写入磁盘的切片。密文按 16 字节分组逐块解密，最后一组留到下载结束时
+/// 去掉填充，内存中只保留不超过一组的密文；断点续传时接着写入，解密状态保持不变
+pub struct Spool {
+    file: File,
+    cipher: Option<Cbc<Aes128, Pkcs7>>,
+    /// 尚未解密的密文 (1 ~ 16 字节)
+    pending: Vec<u8>,
+    /// 已收到的密文字节数
+    received: u64,
+    head: Vec<u8>,
+}
+
+impl Spool {
+    pub fn create(target: &SpoolTarget) -> Result<Self> {
+        let file = File::create(&target.path)
+            .with_context(|| tr!("无法创建切片文件: {}", target.path.display()))?;
+        let cipher = match &target.key {
+            Some((key, iv)) => Some(Cbc::new_from_slices(key, iv)?),
+            None => None,
+        };
+        Ok(Self {
+            file,
+            cipher,
+            pending: Vec::new(),
+            received: 0,
+            head: Vec::new(),
+        })
+    }
+
+    pub fn received(&self) -> u64 {
+        self.received
+    }
+
+    pub fn write(&mut self, chunk: &[u8]) -> Result<()> {
+        self.received += chunk.len() as u64;
+        let Some(cipher) = &mut self.cipher else {
+            return self.emit(chunk);
+        };
+        self.pending.extend_from_slice(chunk);
+        // 至少留下一组，下载结束时才知道哪一组带填充
+        let ready = self.pending.len().saturating_sub(1) / 16 * 16;
+        let rest = self.pending.split_off(ready);
+        let data = std::mem::replace(&mut self.pending, rest);
+        let plain = decrypt_blocks(cipher, &data);
+        self.emit(&plain)
+    }
+
+    /// 下载完成：解密最后一组并去掉 PKCS#7 填充，返回解密后的开头部分供校验
+    pub fn finish(mut self) -> Result<Vec<u8>> {
+        if let Some(cipher) = &mut self.cipher {
+            let last = std::mem::take(&mut self.pending);
+            if last.len() != 16 {
+                let error = anyhow::anyhow!(tr!("密文长度不是 16 字节的整数倍"));
+                return Err(error.context(Failure::Decrypt(1)));
+            }
+            let block = decrypt_blocks(cipher, &last);
+            let plain = Pkcs7::unpad(&block)
+                .map_err(|_| anyhow::anyhow!(tr!("解密后的填充无效")))
+                .context(Failure::Decrypt(1))?;
+            self.emit(plain)?;
+        }
+        self.file.flush()?;
+        Ok(self.head)
+    }
+
+    fn emit(&mut self, plain: &[u8]) -> Result<()> {
+        let n = HEAD_BYTES.saturating_sub(self.head.len()).min(plain.len());
+        self.head.extend_from_slice(&plain[..n]);
+        self.file.write_all(plain)?;
+        Ok(())
+    }
+}
+
+fn decrypt_blocks(cipher: &mut Cbc<Aes128, Pkcs7>, data: &[u8]) -> Vec<u8> {
+    let mut blocks: Vec<Block> = data.chunks_exact(16).map(Block::clone_from_slice).collect();
+    cipher.decrypt_blocks(&mut blocks);
+    blocks.iter().flatten().copied().collect()
+}
@@ -0,0 +1,105 @@
+use reqwest::Client;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::{Receiver, error::RecvError};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::events::JobEvent;
+use crate::i18n::tr;
+use crate::notify;
+
+/// 检查是否停滞的间隔
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Serialize)]
+struct StallNotification<'a> {
+    job: usize,
+    status: &'static str,
+    url: &'a str,
+    /// 已经多少秒没有进展
+    stalled_for: u64,
+}
+
+struct Watch {
+    url: String,
+    last_progress: Instant,
+    alerted: bool,
+}
+
+/// 监视进行中的任务：超过 `timeout` 没有任何切片、合并或转码进展时输出警告，
+/// 指定了 `notify_url` 时用 `client` 同时发送一次 Webhook；任务恢复进展后重新计时
+pub fn spawn(
+    timeout: Duration,
+    notify_url: Option<String>,
+    client: Client,
+    mut rx: Receiver<JobEvent>,
+) -> JoinHandle<()> {
+    info!(
+        "{}",
+        tr!("任务停滞超过 {} 秒时将发出警告", timeout.as_secs())
+    );
+
+    tokio::spawn(async move {
+        let mut running: HashMap<usize, Watch> = HashMap::new();
+        let mut ticker = tokio::time::interval(CHECK_INTERVAL.min(timeout));
+        loop {
+            tokio::select! {
+                event = rx.recv() => match event {
+                    Ok(JobEvent::Started { job, url }) => {
+                        running.insert(job, Watch {
+                            url,
+                            last_progress: Instant::now(),
+                            alerted: false,
+                        });
+                    }
+                    Ok(
+                        JobEvent::SegmentDone { job, .. }
+                        | JobEvent::Merge { job, .. }
+                        | JobEvent::Transcode { job, .. },
+                    ) => {
+                        if let Some(watch) = running.get_mut(&job) {
+                            if watch.alerted {
+                                info!("{}", tr!("任务 #{} 恢复进展", job));
+                            }
+                            watch.last_progress = Instant::now();
+                            watch.alerted = false;
+                        }
+                    }
+                    Ok(
+                        JobEvent::Complete { job, .. }
+                        | JobEvent::Failed { job, .. }
+                        | JobEvent::Cancelled { job },
+                    ) => {
+                        running.remove(&job);
+                    }
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => break,
+                },
+                _ = ticker.tick() => {
+                    for (job, watch) in running.iter_mut() {
+                        let idle = watch.last_progress.elapsed();
+                        if watch.alerted || idle < timeout {
+                            continue;
+                        }
+                        watch.alerted = true;
+                        warn!(
+                            "{}",
+                            tr!("任务 #{} 已 {} 秒没有进展: {}", job, idle.as_secs(), watch.url)
+                        );
+                        if let Some(url) = &notify_url {
+                            let body = StallNotification {
+                                job: *job,
+                                status: "stalled",
+                                url: &watch.url,
+                                stalled_for: idle.as_secs(),
+                            };
+                            notify::post(&client, url, &body).await;
+                        }
+                    }
+                }
+            }
+        }
+    })
+}
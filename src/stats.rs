@@ -0,0 +1,186 @@
+use indicatif::HumanBytes;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::{Receiver, error::RecvError};
+use tokio::task::JoinHandle;
+use tracing::info;
+
+use crate::events::JobEvent;
+use crate::i18n::tr;
+
+/// 计算峰值速度的采样间隔
+const SPEED_WINDOW: Duration = Duration::from_secs(1);
+
+/// 单个任务的统计，各阶段以第一次进入该阶段的时间为准 (按 --recover 重跑时不重新计时)
+struct JobStats {
+    started: Instant,
+    download: Option<Instant>,
+    merge: Option<Instant>,
+    transcode: Option<Instant>,
+    bytes: u64,
+    retries: u64,
+    /// 重试过的切片，之后下载成功的计为恢复
+    retried: HashSet<usize>,
+    recovered: u64,
+    /// 峰值速度的采样起点 (时间, 已下载字节数)
+    sample: (Instant, u64),
+    peak: f64,
+}
+
+impl JobStats {
+    fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            started: now,
+            download: None,
+            merge: None,
+            transcode: None,
+            bytes: 0,
+            retries: 0,
+            retried: HashSet::new(),
+            recovered: 0,
+            sample: (now, 0),
+            peak: 0.0,
+        }
+    }
+
+    fn segment_done(&mut self, segment: usize, bytes: u64) {
+        self.bytes += bytes;
+        if self.retried.remove(&segment) {
+            self.recovered += 1;
+        }
+        let (at, before) = self.sample;
+        let elapsed = at.elapsed();
+        if elapsed >= SPEED_WINDOW {
+            let speed = (self.bytes - before) as f64 / elapsed.as_secs_f64();
+            self.peak = self.peak.max(speed);
+            self.sample = (Instant::now(), self.bytes);
+        }
+    }
+
+    /// 各阶段的耗时：下载到合并 (或转码、结束) 为止，合并到转码为止，转码到结束为止
+    fn stages(&self, end: Instant) -> Vec<(&'static str, Duration)> {
+        let stages = [
+            (tr!("下载"), self.download, [self.merge, self.transcode]),
+            (tr!("合并"), self.merge, [self.transcode, None]),
+            (tr!("转码"), self.transcode, [None, None]),
+        ];
+        stages
+            .into_iter()
+            .filter_map(|(name, start, next)| {
+                let start = start?;
+                let stop = next.into_iter().flatten().next().unwrap_or(end);
+                Some((name, stop.saturating_duration_since(start)))
+            })
+            .collect()
+    }
+
+    /// 没有解析到播放列表 (例如输出已存在而跳过) 的任务不输出统计
+    fn report(&self, job: usize, output: Option<&str>) {
+        if self.download.is_none() {
+            return;
+        }
+        let end = Instant::now();
+        let download = self
+            .download
+            .map(|start| {
+                let stop = self.merge.or(self.transcode).unwrap_or(end);
+                stop.saturating_duration_since(start)
+            })
+            .unwrap_or_default();
+        // 下载不到一个采样间隔时没有峰值，以平均速度代替
+        let average = match download.as_secs_f64() {
+            secs if secs > 0.0 => self.bytes as f64 / secs,
+            _ => 0.0,
+        };
+        let peak = self.peak.max(average);
+
+        info!("{}", tr!("任务 #{} 统计:", job));
+        info!(
+            "{}",
+            tr!(
+                "  下载 {}，平均 {}/s，峰值 {}/s",
+                HumanBytes(self.bytes),
+                HumanBytes(average as u64),
+                HumanBytes(peak as u64)
+            )
+        );
+        info!(
+            "{}",
+            tr!(
+                "  重试 {} 次，{} 个切片重试后下载成功",
+                self.retries,
+                self.recovered
+            )
+        );
+        let stages = self
+            .stages(end)
+            .iter()
+            .map(|(name, d)| format!("{} {:.1}s", name, d.as_secs_f64()))
+            .collect::<Vec<_>>()
+            .join(" / ");
+        info!(
+            "{}",
+            tr!(
+                "  耗时 {}s ({})",
+                format!("{:.1}", (end - self.started).as_secs_f64()),
+                stages
+            )
+        );
+        if let Some(size) = output.and_then(|path| std::fs::metadata(path).ok()) {
+            info!("{}", tr!("  输出文件 {}", HumanBytes(size.len())));
+        }
+    }
+}
+
+/// 收集每个任务的下载量、速度、重试与各阶段耗时，任务结束 (成功或失败) 时输出统计，
+/// 便于调整 --concurrency 与 --retries
+pub fn spawn(mut rx: Receiver<JobEvent>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut jobs: HashMap<usize, JobStats> = HashMap::new();
+        loop {
+            let event = match rx.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            };
+            if let JobEvent::Started { job, .. } = event {
+                jobs.insert(job, JobStats::new());
+                continue;
+            }
+            let Some(stats) = jobs.get_mut(&event.job()) else {
+                continue;
+            };
+            let now = Instant::now();
+            match event {
+                JobEvent::PlaylistParsed { .. } => {
+                    stats.download.get_or_insert(now);
+                    stats.sample = (now, stats.bytes);
+                }
+                JobEvent::SegmentDone { segment, bytes, .. } => stats.segment_done(segment, bytes),
+                JobEvent::Retry { segment, .. } => {
+                    stats.retries += 1;
+                    stats.retried.insert(segment);
+                }
+                JobEvent::Merge { .. } => {
+                    stats.merge.get_or_insert(now);
+                }
+                JobEvent::Transcode { .. } => {
+                    stats.transcode.get_or_insert(now);
+                }
+                JobEvent::Complete { job, output } => {
+                    stats.report(job, Some(&output));
+                    jobs.remove(&job);
+                }
+                JobEvent::Failed { job, .. } => {
+                    stats.report(job, None);
+                    jobs.remove(&job);
+                }
+                JobEvent::Cancelled { job } => {
+                    jobs.remove(&job);
+                }
+                _ => {}
+            }
+        }
+    })
+}
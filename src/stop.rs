@@ -0,0 +1,127 @@
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta};
+use indicatif::HumanBytes;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::Args;
+use crate::i18n::tr;
+
+/// `--stop-at` 的停止时间：某一时刻 (今天已过则为明天)，或完整的日期时间
+#[derive(Clone, Debug)]
+pub enum StopAt {
+    Daily(NaiveTime),
+    At(DateTime<Local>),
+}
+
+impl FromStr for StopAt {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        for format in ["%H:%M", "%H:%M:%S"] {
+            if let Ok(time) = NaiveTime::parse_from_str(s, format) {
+                return Ok(Self::Daily(time));
+            }
+        }
+        if let Ok(at) = DateTime::parse_from_rfc3339(s) {
+            return Ok(Self::At(at.with_timezone(&Local)));
+        }
+        for format in ["%Y-%m-%d %H:%M", "%Y-%m-%d %H:%M:%S"] {
+            if let Some(at) = NaiveDateTime::parse_from_str(s, format)
+                .ok()
+                .and_then(|at| at.and_local_timezone(Local).earliest())
+            {
+                return Ok(Self::At(at));
+            }
+        }
+        if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+            return Err(tr!("请同时给出时刻，例如 \"{} 22:30\"", date));
+        }
+        Err(tr!("无效的停止时间: {} (例如 22:30、2025-01-01 22:30)", s))
+    }
+}
+
+impl StopAt {
+    /// 换算为从现在起的具体时间
+    fn deadline(&self) -> DateTime<Local> {
+        match self {
+            Self::At(at) => *at,
+            Self::Daily(time) => {
+                let now = Local::now();
+                let today = now
+                    .date_naive()
+                    .and_time(*time)
+                    .and_local_timezone(Local)
+                    .earliest()
+                    .unwrap_or(now);
+                if today > now {
+                    today
+                } else {
+                    today + TimeDelta::days(1)
+                }
+            }
+        }
+    }
+}
+
+/// 解析文件大小，例如 `500M`、`2G`、`1.5GB`，纯数字按字节计，单位按 1024 换算
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| tr!("无效的大小: {} (例如 500M、2G)", text))?;
+    let unit = unit.trim().to_ascii_uppercase();
+    let multiplier: u64 = match unit.trim_end_matches("IB").trim_end_matches('B') {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return Err(tr!("无效的大小单位: {}", text)),
+    };
+    Ok((number * multiplier as f64) as u64)
+}
+
+/// 直播录制的停止条件：录制时长、停止时间与文件大小，任一满足即结束录制并照常合并转码
+pub struct Limits {
+    duration: Option<Duration>,
+    deadline: Option<DateTime<Local>>,
+    max_bytes: Option<u64>,
+}
+
+impl Limits {
+    pub fn new(args: &Args) -> Self {
+        Self {
+            duration: args.record_duration,
+            deadline: args.stop_at.as_ref().map(StopAt::deadline),
+            max_bytes: args.max_filesize,
+        }
+    }
+
+    /// 按已录制的媒体时长与写入的字节数检查是否该停止，返回停止原因
+    pub fn reached(&self, recorded: f64, bytes: u64) -> Option<String> {
+        if let Some(limit) = self.duration.filter(|d| recorded >= d.as_secs_f64()) {
+            return Some(tr!("已录制 {}s (--record-duration)", limit.as_secs()));
+        }
+        if let Some(deadline) = self.deadline.filter(|d| Local::now() >= *d) {
+            return Some(tr!(
+                "已到停止时间 {} (--stop-at)",
+                deadline.format("%Y-%m-%d %H:%M:%S")
+            ));
+        }
+        if let Some(limit) = self.max_bytes.filter(|&l| bytes >= l) {
+            return Some(tr!("录制文件已达到 {} (--max-filesize)", HumanBytes(limit)));
+        }
+        None
+    }
+
+    /// 距停止时间还有多久，用于缩短刷新间隔的等待
+    pub fn until_deadline(&self) -> Option<Duration> {
+        self.deadline
+            .map(|d| (d - Local::now()).to_std().unwrap_or_default())
+    }
+}
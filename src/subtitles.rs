@@ -0,0 +1,106 @@
+use anyhow::{Context, Result, bail};
+use futures::stream::{self, StreamExt, TryStreamExt};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tracing::info;
+use url::Url;
+
+use crate::i18n::tr;
+use crate::{Args, Shared, directory_url, fetch_media_playlist};
+
+/// `--burn-subs` 的字幕来源
+pub enum BurnSubs {
+    /// 播放列表中的 WebVTT 字幕，可以指定语言
+    Rendition(Option<String>),
+    /// 本地字幕文件 (.srt、.vtt、.ass)
+    File(PathBuf),
+}
+
+impl BurnSubs {
+    /// 空值为默认字幕，存在的文件或字幕扩展名为本地文件，其余按语言代码处理
+    pub fn parse(value: &str) -> Self {
+        let path = Path::new(value);
+        let is_file = path.exists()
+            || path.extension().is_some_and(|e| {
+                ["srt", "vtt", "ass", "ssa"]
+                    .iter()
+                    .any(|s| e.eq_ignore_ascii_case(s))
+            });
+        match value.trim() {
+            "" => BurnSubs::Rendition(None),
+            _ if is_file => BurnSubs::File(path.to_path_buf()),
+            lang => BurnSubs::Rendition(Some(lang.to_string())),
+        }
+    }
+}
+
+/// 下载分段的 WebVTT 字幕并合并为一个文件：只保留第一个 WEBVTT 头，
+/// 跨切片重复出现的字幕块只写一次。字幕时间按节目开头计算，忽略 X-TIMESTAMP-MAP
+pub async fn download(url: &Url, output: &Path, args: &Args, shared: &Shared) -> Result<()> {
+    let playlist = fetch_media_playlist(url.as_str(), args).await?;
+    let base = directory_url(url);
+    let urls = playlist
+        .segments
+        .iter()
+        .map(|s| base.join(&s.uri))
+        .collect::<Result<Vec<_>, _>>()?;
+    info!("{}", tr!("下载 WebVTT 字幕，共 {} 个切片", urls.len()));
+
+    let texts: Vec<String> = stream::iter(urls)
+        .map(|url| async move {
+            let resp = shared
+                .client
+                .get(url.clone())
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .with_context(|| tr!("下载字幕失败: {}", url))?;
+            Ok::<_, anyhow::Error>(resp.text().await?)
+        })
+        .buffered(args.concurrency.max(1))
+        .try_collect()
+        .await?;
+
+    let mut merged = String::from("WEBVTT\n\n");
+    let mut seen = HashSet::new();
+    for text in &texts {
+        let text = text.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+        // 第一个空行之前是 WEBVTT 头 (含 X-TIMESTAMP-MAP)
+        let body = text.split_once("\n\n").map_or("", |(_, body)| body);
+        for block in body.split("\n\n").map(str::trim).filter(|b| !b.is_empty()) {
+            if seen.insert(block.to_string()) {
+                merged.push_str(block);
+                merged.push_str("\n\n");
+            }
+        }
+    }
+    if seen.is_empty() {
+        bail!(tr!("字幕播放列表中没有字幕"));
+    }
+    fs::write(output, merged)
+        .await
+        .with_context(|| tr!("无法写入字幕文件: {}", output.display()))?;
+    Ok(())
+}
+
+/// 作为 subtitles 滤镜参数的路径：先按滤镜选项转义，再按滤镜图转义
+pub fn filter_path(path: &Path) -> Result<String> {
+    let path = path
+        .to_str()
+        .ok_or_else(|| anyhow::anyhow!(tr!("字幕路径包含无效字符")))?
+        .replace('\\', "/");
+    let mut escaped = String::with_capacity(path.len());
+    for c in path.chars() {
+        match c {
+            ':' => escaped.push_str("\\\\:"),
+            '\'' => escaped.push_str("\\\\\\'"),
+            '[' | ']' | ',' | ';' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c => escaped.push(c),
+        }
+    }
+    Ok(escaped)
+}
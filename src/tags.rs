@@ -0,0 +1,52 @@
+use chrono::{SecondsFormat, Utc};
+use url::Url;
+
+use crate::{Args, Job};
+
+/// 写入输出文件的元数据：`--title` / `--artist` / `--comment`，以及来源地址、下载时间与源分辨率，
+/// 以 FFmpeg `-metadata` 参数的形式返回
+pub fn ffmpeg_args(job: &Job, resolution: Option<&str>, args: &Args) -> Vec<String> {
+    let mut tags = vec![
+        ("source_url", source_url(&job.url)),
+        (
+            "creation_time",
+            Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        ),
+    ];
+    if let Some(resolution) = resolution {
+        tags.push(("original_resolution", resolution.to_string()));
+    }
+    for (key, value) in [
+        ("title", &args.title),
+        ("artist", &args.artist),
+        ("comment", &args.comment),
+    ] {
+        if let Some(value) = value {
+            tags.push((key, value.clone()));
+        }
+    }
+
+    let mut ffmpeg_args = Vec::new();
+    for (key, value) in tags {
+        ffmpeg_args.push("-metadata".to_string());
+        ffmpeg_args.push(format!("{}={}", key, value));
+    }
+    // MP4 默认只写入 iTunes 标准标签，自定义键需要 use_metadata_tags
+    ffmpeg_args.push("-movflags".to_string());
+    ffmpeg_args.push("+use_metadata_tags".to_string());
+    ffmpeg_args
+}
+
+/// 去掉用户名密码与查询参数，签名地址中的令牌不应留在归档文件里
+fn source_url(url: &str) -> String {
+    match Url::parse(url) {
+        Ok(mut url) => {
+            let _ = url.set_username("");
+            let _ = url.set_password(None);
+            url.set_query(None);
+            url.set_fragment(None);
+            url.to_string()
+        }
+        Err(_) => url.to_string(),
+    }
+}
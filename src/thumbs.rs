@@ -0,0 +1,218 @@
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use m3u8_rs::{ByteRange, KeyMethod, Playlist, parse_playlist};
+use reqwest::header::RANGE;
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Stdio;
+use tokio::fs;
+use tokio::process::Command;
+use tracing::{info, warn};
+use url::Url;
+
+use crate::i18n::tr;
+use crate::{Args, Job, Shared, download_playlist};
+
+/// 拼图每行的缩略图数
+const SHEET_COLUMNS: usize = 10;
+
+/// TS 切片开头通常是 PAT 与 PMT 两个包，I 帧的字节范围不一定包含它们
+const TS_HEADER_BYTES: u64 = 2 * 188;
+
+/// 缩略图的输出形式
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Thumbnails {
+    /// 所有缩略图拼成一张图片 `<输出名>.thumbs.jpg`
+    Sheet,
+    /// 每张缩略图一个文件，放在 `<输出名>.thumbs/` 目录中
+    Jpeg,
+}
+
+/// 每隔 `--thumbnail-interval` 秒取一帧生成缩略图。有 I 帧播放列表时只下载所需的 I 帧，
+/// 否则从转码后的输出中抽帧
+pub async fn generate(
+    job: &Job,
+    iframes: Option<&Url>,
+    mode: Thumbnails,
+    duration: f64,
+    args: &Args,
+    shared: &Shared,
+) -> Result<()> {
+    let interval = args.thumbnail_interval.max(1) as f64;
+    let frames_path = job.output.with_extension("iframes");
+    let collected = match iframes {
+        Some(url) => match collect(url, interval, &frames_path, args, shared).await {
+            Ok(count) => Some(count),
+            Err(e) => {
+                warn!(
+                    "{}",
+                    tr!(
+                        "无法使用 I 帧播放列表生成缩略图，改为从输出文件抽帧: {:#}",
+                        e
+                    )
+                );
+                None
+            }
+        },
+        None => None,
+    };
+
+    let scale = format!("scale={}:-2", args.thumbnail_width);
+    let (input, mut filter, count) = match collected {
+        Some(count) => (frames_path.clone(), scale, count),
+        None => (
+            job.output.clone(),
+            format!("fps=1/{},{}", interval, scale),
+            (duration / interval).ceil().max(1.0) as usize,
+        ),
+    };
+    let mut ffmpeg_args: Vec<String> = vec![
+        "-hide_banner".into(),
+        "-loglevel".into(),
+        "error".into(),
+        "-y".into(),
+        "-i".into(),
+        path_str(&input)?.into(),
+        "-vsync".into(),
+        "passthrough".into(),
+    ];
+    let output = match mode {
+        Thumbnails::Sheet => {
+            let columns = count.clamp(1, SHEET_COLUMNS);
+            let rows = count.div_ceil(columns);
+            filter.push_str(&format!(",tile={}x{}", columns, rows));
+            ffmpeg_args.extend(["-vf".into(), filter, "-frames:v".into(), "1".into()]);
+            let sheet = job.output.with_extension("thumbs.jpg");
+            ffmpeg_args.push(path_str(&sheet)?.into());
+            sheet
+        }
+        Thumbnails::Jpeg => {
+            let dir = job.output.with_extension("thumbs");
+            fs::create_dir_all(&dir)
+                .await
+                .with_context(|| tr!("无法创建缩略图目录: {}", dir.display()))?;
+            ffmpeg_args.extend(["-vf".into(), filter]);
+            ffmpeg_args.push(path_str(&dir.join("%04d.jpg"))?.into());
+            dir
+        }
+    };
+
+    let result = Command::new("ffmpeg")
+        .args(&ffmpeg_args)
+        .stdout(Stdio::null())
+        .output()
+        .await
+        .context(tr!("FFmpeg 生成缩略图失败"));
+    let _ = fs::remove_file(&frames_path).await;
+    let result = result?;
+    if !result.status.success() {
+        bail!(tr!(
+            "FFmpeg 生成缩略图失败: {}",
+            String::from_utf8_lossy(&result.stderr).trim()
+        ));
+    }
+    info!("{}", tr!("已生成 {} 张缩略图: {}", count, output.display()));
+    Ok(())
+}
+
+/// 下载 I 帧播放列表中每隔 `interval` 秒的一帧，拼成一个可直接交给 FFmpeg 的文件，返回帧数
+async fn collect(
+    url: &Url,
+    interval: f64,
+    path: &Path,
+    args: &Args,
+    shared: &Shared,
+) -> Result<usize> {
+    let content = download_playlist(url.as_str(), args).await?;
+    let playlist = match parse_playlist(&content) {
+        Ok((_, Playlist::MediaPlaylist(mp))) => mp,
+        _ => bail!(tr!("解析 I 帧播放列表失败: {}", url)),
+    };
+    // 加密切片的字节范围无法单独解密
+    if playlist
+        .segments
+        .iter()
+        .filter_map(|s| s.key.as_ref())
+        .any(|k| !matches!(k.method, KeyMethod::None))
+    {
+        bail!(tr!("I 帧播放列表已加密"));
+    }
+
+    let mut data = Vec::new();
+    let mut headers: HashMap<Url, Vec<u8>> = HashMap::new();
+    let mut ends: HashMap<Url, u64> = HashMap::new();
+    let mut init = None;
+    let mut elapsed = 0.0;
+    let mut next_mark = 0.0;
+    let mut count = 0;
+    for seg in &playlist.segments {
+        let uri = url.join(&seg.uri)?;
+        let range = seg
+            .byte_range
+            .as_ref()
+            .map(|r| resolve(r, ends.get(&uri).copied()));
+        if let Some((offset, length)) = range {
+            ends.insert(uri.clone(), offset + length);
+        }
+        if let Some(map) = &seg.map {
+            init = Some((url.join(&map.uri)?, map.byte_range.clone()));
+        }
+        let at = elapsed;
+        elapsed += f64::from(seg.duration);
+        if at < next_mark {
+            continue;
+        }
+        next_mark += interval;
+
+        match &init {
+            // fMP4：初始化段只需出现一次，之后依次拼接各帧的 moof/mdat
+            Some((map_url, map_range)) if data.is_empty() => {
+                let range = map_range.as_ref().map(|r| resolve(r, None));
+                data.extend(fetch(shared, map_url, range).await?);
+            }
+            // TS：补上切片开头的 PAT/PMT，FFmpeg 才能识别每一帧
+            None if range.is_some_and(|(offset, _)| offset > 0) => {
+                if !headers.contains_key(&uri) {
+                    let header = fetch(shared, &uri, Some((0, TS_HEADER_BYTES))).await?;
+                    headers.insert(uri.clone(), header);
+                }
+                data.extend_from_slice(&headers[&uri]);
+            }
+            _ => {}
+        }
+        data.extend(fetch(shared, &uri, range).await?);
+        count += 1;
+    }
+    if count == 0 {
+        bail!(tr!("I 帧播放列表中没有可用的帧"));
+    }
+    fs::write(path, &data).await?;
+    info!(
+        "{}",
+        tr!("从 I 帧播放列表下载了 {} 帧用于生成缩略图", count)
+    );
+    Ok(count)
+}
+
+/// 字节范围的 (起点, 长度)；没有起点时紧接同一资源的上一个范围
+fn resolve(range: &ByteRange, previous_end: Option<u64>) -> (u64, u64) {
+    (range.offset.or(previous_end).unwrap_or(0), range.length)
+}
+
+async fn fetch(shared: &Shared, url: &Url, range: Option<(u64, u64)>) -> Result<Vec<u8>> {
+    let mut request = shared.client.get(url.clone());
+    if let Some((offset, length)) = range {
+        request = request.header(RANGE, format!("bytes={}-{}", offset, offset + length - 1));
+    }
+    let response = request
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .with_context(|| tr!("下载 I 帧失败: {}", url))?;
+    Ok(response.bytes().await?.to_vec())
+}
+
+fn path_str(path: &Path) -> Result<&str> {
+    path.to_str()
+        .ok_or_else(|| anyhow::anyhow!(tr!("路径包含无效字符: {}", path.display())))
+}
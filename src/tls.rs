@@ -0,0 +1,76 @@
+use crate::i18n::tr;
+use anyhow::{Result, bail};
+use clap::ValueEnum;
+use reqwest::{ClientBuilder, Identity};
+
+#[cfg(not(any(feature = "native-tls", feature = "rustls")))]
+compile_error!("至少需要启用 native-tls 或 rustls 特性之一");
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum TlsBackend {
+    /// 系统 TLS 库 (Linux 上为 OpenSSL)，适合需要自定义信任库的企业代理
+    Native,
+    /// 纯 Rust 实现，不依赖 OpenSSL，根证书取自系统证书库
+    Rustls,
+}
+
+impl TlsBackend {
+    /// 未指定 `--tls-backend` 时优先使用系统 TLS 库；指定了编译时未启用的实现时报错
+    pub fn resolve(choice: Option<TlsBackend>) -> Result<Self> {
+        let backend = choice.unwrap_or(if cfg!(feature = "native-tls") {
+            TlsBackend::Native
+        } else {
+            TlsBackend::Rustls
+        });
+        match backend {
+            TlsBackend::Native if !cfg!(feature = "native-tls") => {
+                bail!(tr!(
+                    "编译时未启用 native-tls 特性，不能使用 --tls-backend native"
+                ))
+            }
+            TlsBackend::Rustls if !cfg!(feature = "rustls") => {
+                bail!(tr!(
+                    "编译时未启用 rustls 特性，不能使用 --tls-backend rustls"
+                ))
+            }
+            _ => Ok(backend),
+        }
+    }
+
+    pub fn apply(self, builder: ClientBuilder) -> ClientBuilder {
+        match self {
+            #[cfg(feature = "native-tls")]
+            TlsBackend::Native => builder.use_native_tls(),
+            #[cfg(feature = "rustls")]
+            TlsBackend::Rustls => builder.use_rustls_tls(),
+            #[allow(unreachable_patterns)]
+            _ => builder,
+        }
+    }
+
+    /// 客户端证书：native-tls 支持 PEM 证书 + PKCS#8 私钥与 PKCS#12，rustls 只支持 PEM
+    #[cfg_attr(not(feature = "native-tls"), allow(unused_variables))]
+    pub fn identity(self, cert: &[u8], key: Option<&[u8]>, password: &str) -> Result<Identity> {
+        match self {
+            #[cfg(feature = "native-tls")]
+            TlsBackend::Native => Ok(match key {
+                Some(key) => Identity::from_pkcs8_pem(cert, key)?,
+                None => Identity::from_pkcs12_der(cert, password)?,
+            }),
+            #[cfg(feature = "rustls")]
+            TlsBackend::Rustls => {
+                let Some(key) = key else {
+                    bail!(tr!(
+                        "rustls 不支持 PKCS#12 客户端证书，请用 --client-key 指定 PEM 私钥，或改用 --tls-backend native"
+                    ));
+                };
+                let mut pem = cert.to_vec();
+                pem.push(b'\n');
+                pem.extend_from_slice(key);
+                Ok(Identity::from_pem(&pem)?)
+            }
+            #[allow(unreachable_patterns)]
+            _ => bail!(tr!("编译时未启用所选的 TLS 实现")),
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use clap::ValueEnum;
+use tracing::warn;
+
+use crate::i18n::tr;
+use crate::{AccelType, Args};
+
+/// 编码速度与画质的取舍，沿用 libx264 的名称，硬件编码器换算为各自最接近的档位
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum Preset {
+    Ultrafast,
+    Superfast,
+    Veryfast,
+    Faster,
+    Fast,
+    Medium,
+    Slow,
+    Slower,
+    Veryslow,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Profile {
+    Baseline,
+    Main,
+    High,
+}
+
+/// libx264 的 tune；硬件编码器只支持其中的一部分
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Tune {
+    Film,
+    Animation,
+    Grain,
+    Stillimage,
+    Fastdecode,
+    Zerolatency,
+}
+
+impl Preset {
+    fn x264(self) -> &'static str {
+        match self {
+            Preset::Ultrafast => "ultrafast",
+            Preset::Superfast => "superfast",
+            Preset::Veryfast => "veryfast",
+            Preset::Faster => "faster",
+            Preset::Fast => "fast",
+            Preset::Medium => "medium",
+            Preset::Slow => "slow",
+            Preset::Slower => "slower",
+            Preset::Veryslow => "veryslow",
+        }
+    }
+}
+
+/// `--preset` / `--video-profile` / `--tune` / `--level` 换算为当前编码器的 FFmpeg 参数。
+/// 未指定 `--preset` 时保持各编码器原来的默认档位
+pub fn ffmpeg_args(accel: AccelType, args: &Args) -> Vec<String> {
+    let mut out: Vec<String> = Vec::new();
+    let mut push = |key: &str, value: &str| {
+        out.push(key.to_string());
+        out.push(value.to_string());
+    };
+
+    match (accel, args.preset) {
+        (AccelType::Nvidia, preset) => {
+            // NVENC 的 p1 (最快) ~ p7 (画质最好)
+            let level = match preset {
+                None => "p3",
+                Some(Preset::Ultrafast | Preset::Superfast) => "p1",
+                Some(Preset::Veryfast) => "p2",
+                Some(Preset::Faster) => "p3",
+                Some(Preset::Fast) => "p4",
+                Some(Preset::Medium) => "p5",
+                Some(Preset::Slow) => "p6",
+                Some(Preset::Slower | Preset::Veryslow) => "p7",
+            };
+            push("-preset", level);
+        }
+        (AccelType::AMD, Some(preset)) => {
+            let quality = if preset <= Preset::Faster {
+                "speed"
+            } else if preset <= Preset::Medium {
+                "balanced"
+            } else {
+                "quality"
+            };
+            push("-quality", quality);
+        }
+        (AccelType::AMD, None) => {}
+        // Quick Sync 没有 veryfast 以下的档位
+        (AccelType::Intel, preset) => {
+            let preset = preset.unwrap_or(Preset::Medium).max(Preset::Veryfast);
+            push("-preset", preset.x264());
+        }
+        (AccelType::CPU, preset) => push("-preset", preset.unwrap_or(Preset::Medium).x264()),
+    }
+
+    if let Some(profile) = args.video_profile {
+        let name = match (accel, profile) {
+            (AccelType::AMD, Profile::Baseline) => "constrained_baseline",
+            (_, Profile::Baseline) => "baseline",
+            (_, Profile::Main) => "main",
+            (_, Profile::High) => "high",
+        };
+        push("-profile:v", name);
+    }
+
+    if let Some(tune) = args.tune {
+        let mapped = match (accel, tune) {
+            (AccelType::CPU, tune) => Some(("-tune", tune_name(tune))),
+            (AccelType::Nvidia, Tune::Zerolatency) => Some(("-tune", "ull")),
+            (AccelType::Nvidia, Tune::Film | Tune::Animation | Tune::Grain) => {
+                Some(("-tune", "hq"))
+            }
+            (AccelType::AMD, Tune::Zerolatency) => Some(("-usage", "ultralowlatency")),
+            _ => None,
+        };
+        match mapped {
+            Some((key, value)) => push(key, value),
+            None => warn!(
+                "{}",
+                tr!(
+                    "{} 不支持 --tune {}，已忽略",
+                    accel.encoder(),
+                    tune_name(tune)
+                )
+            ),
+        }
+    }
+
+    if let Some(level) = &args.level {
+        push("-level:v", level);
+    }
+    out
+}
+
+fn tune_name(tune: Tune) -> &'static str {
+    match tune {
+        Tune::Film => "film",
+        Tune::Animation => "animation",
+        Tune::Grain => "grain",
+        Tune::Stillimage => "stillimage",
+        Tune::Fastdecode => "fastdecode",
+        Tune::Zerolatency => "zerolatency",
+    }
+}
@@ -0,0 +1,341 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use indicatif::style::ProgressTracker;
+use indicatif::{
+    HumanDuration, MultiProgress, ProgressBar, ProgressDrawTarget, ProgressState, ProgressStyle,
+};
+use std::borrow::Cow;
+use std::fmt;
+use std::io::IsTerminal;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::info;
+
+const UNICODE_TICKS: &[&str] = &["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+const ASCII_TICKS: &[&str] = &["-", "\\", "|", "/", "*"];
+
+/// ETA 吞吐量的 EWMA 半衰期：越长越平滑，对速度变化的反应越慢
+const ETA_HALF_LIFE: Duration = Duration::from_secs(20);
+/// 两次采样的最短间隔，避免突发的一批切片让速度瞬间飙高
+const ETA_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ColorChoice {
+    /// 终端支持时启用颜色，并遵循 NO_COLOR 环境变量
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ProgressMode {
+    /// 终端进度条，stderr 不是终端时自动改用 plain
+    Bar,
+    /// 不绘制进度条，定期输出一行纯文本日志
+    Plain,
+    /// 向 stdout 输出逐行 JSON 事件，不绘制进度条
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Theme {
+    /// 适合深色背景的默认配色
+    Default,
+    /// 适合浅色背景，避免黄色/青色等低对比度颜色
+    Light,
+    /// 不使用任何颜色，适合日志文件与读屏软件
+    Mono,
+}
+
+/// 各处理阶段的进度条，配色由主题决定
+#[derive(Clone, Copy)]
+pub enum Stage {
+    Check,
+    Playlist,
+    Download,
+    Merge,
+    Convert,
+}
+
+impl Theme {
+    fn color(self, stage: Stage) -> &'static str {
+        match (self, stage) {
+            (Theme::Default, Stage::Check) => "green",
+            (Theme::Default, Stage::Playlist) => "blue",
+            (Theme::Default, Stage::Download) => "cyan/blue",
+            (Theme::Default, Stage::Merge) => "green",
+            (Theme::Default, Stage::Convert) => "yellow",
+            (Theme::Light, Stage::Check) => "green",
+            (Theme::Light, Stage::Playlist) => "blue",
+            (Theme::Light, Stage::Download) => "blue/black",
+            (Theme::Light, Stage::Merge) => "green/black",
+            (Theme::Light, Stage::Convert) => "magenta",
+            (Theme::Mono, _) => "",
+        }
+    }
+}
+
+/// 给模板占位符附加颜色，例如 `styled("spinner", "green")` -> `{spinner:.green}`
+fn styled(key: &str, color: &str) -> String {
+    if color.is_empty() {
+        format!("{{{}}}", key)
+    } else {
+        format!("{{{}:.{}}}", key, color)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Icon {
+    Ok,
+    Fail,
+    Warn,
+    Download,
+    Merge,
+    Done,
+}
+
+/// 终端输出：统一管理进度条样式与提示图标，非 UTF-8 终端下退化为纯 ASCII
+#[derive(Clone)]
+pub struct Ui {
+    multi: MultiProgress,
+    ascii: bool,
+    theme: Theme,
+    /// plain 模式下需要定期汇报的进度条
+    plain: Option<Arc<Mutex<Vec<ProgressBar>>>>,
+}
+
+impl Ui {
+    /// `quiet` 时不显示任何进度；`interval` 为 plain 模式的汇报间隔
+    pub fn new(
+        force_ascii: bool,
+        color: ColorChoice,
+        theme: Theme,
+        mode: ProgressMode,
+        quiet: bool,
+        interval: Duration,
+    ) -> Self {
+        let colors = match color {
+            ColorChoice::Always => Some(true),
+            ColorChoice::Never => Some(false),
+            ColorChoice::Auto if std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) => {
+                Some(false)
+            }
+            ColorChoice::Auto => None,
+        };
+        if let Some(enabled) = colors {
+            // indicatif 绘制在 stderr 上
+            console::set_colors_enabled(enabled);
+            console::set_colors_enabled_stderr(enabled);
+        }
+
+        // cron、CI 或管道中进度条只会刷出大量控制字符
+        let mode = match mode {
+            ProgressMode::Bar if !std::io::stderr().is_terminal() => ProgressMode::Plain,
+            mode => mode,
+        };
+
+        let mut plain = None;
+        let multi = match mode {
+            ProgressMode::Bar if !quiet => MultiProgress::new(),
+            ProgressMode::Plain if !quiet => {
+                let bars = Arc::new(Mutex::new(Vec::new()));
+                spawn_plain_reporter(bars.clone(), interval);
+                plain = Some(bars);
+                MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+            }
+            _ => MultiProgress::with_draw_target(ProgressDrawTarget::hidden()),
+        };
+
+        Self {
+            multi,
+            ascii: force_ascii || !terminal_supports_unicode(),
+            theme,
+            plain,
+        }
+    }
+
+    fn track(&self, pb: ProgressBar) -> ProgressBar {
+        if let Some(bars) = &self.plain {
+            bars.lock().unwrap().push(pb.clone());
+        }
+        pb
+    }
+
+    /// 立即输出 plain 模式下尚未汇报的进度，在程序退出前调用
+    pub fn flush(&self) {
+        if let Some(bars) = &self.plain {
+            report(bars);
+        }
+    }
+
+    pub fn is_ascii(&self) -> bool {
+        self.ascii
+    }
+
+    /// 只显示消息文本的多行区域，用于切片地图等自定义视图
+    pub fn text_area(&self) -> Result<ProgressBar> {
+        let pb = self.multi.add(ProgressBar::new_spinner());
+        pb.set_style(ProgressStyle::with_template("{msg}")?);
+        Ok(pb)
+    }
+
+    /// 日志输出是否应带颜色，与进度条保持一致
+    pub fn colors_enabled(&self) -> bool {
+        console::colors_enabled_stderr()
+    }
+
+    pub fn icon(&self, icon: Icon) -> &'static str {
+        match (icon, self.ascii) {
+            (Icon::Ok, false) => "✅",
+            (Icon::Fail, false) => "❌",
+            (Icon::Warn, false) => "⚠️",
+            (Icon::Download, false) => "🔽",
+            (Icon::Merge, false) => "🔗",
+            (Icon::Done, false) => "🎉",
+            (Icon::Ok, true) => "[OK]",
+            (Icon::Fail, true) => "[FAIL]",
+            (Icon::Warn, true) => "[!]",
+            (Icon::Download, true) => "[DL]",
+            (Icon::Merge, true) => "[MERGE]",
+            (Icon::Done, true) => "[DONE]",
+        }
+    }
+
+    /// 创建一个已开始转动的 spinner
+    pub fn spinner(&self, stage: Stage, msg: impl Into<Cow<'static, str>>) -> Result<ProgressBar> {
+        let pb = self.multi.add(ProgressBar::new_spinner());
+        let ticks = if self.ascii {
+            ASCII_TICKS
+        } else {
+            UNICODE_TICKS
+        };
+        pb.set_style(
+            ProgressStyle::with_template(&format!(
+                "{} {{msg}}",
+                styled("spinner", self.theme.color(stage))
+            ))?
+            .tick_strings(ticks),
+        );
+        pb.set_message(msg);
+        pb.enable_steady_tick(Duration::from_millis(100));
+        Ok(self.track(pb))
+    }
+
+    /// 创建计数进度条；进度条宽度按终端剩余宽度自适应，
+    /// 中文等宽字符的消息不会把进度条挤到下一行
+    pub fn bar(&self, len: u64, stage: Stage, eta: bool) -> Result<ProgressBar> {
+        let pb = self.multi.add(ProgressBar::new(len));
+        let template = format!(
+            "{{msg}} [{{elapsed_precise}}] {} {{pos:>7}}/{{len:7}} ({{percent}}%){}",
+            styled("wide_bar", self.theme.color(stage)),
+            if eta { " {smooth_eta}" } else { "" }
+        );
+        pb.set_style(
+            ProgressStyle::with_template(&template)?
+                .with_key("smooth_eta", SmoothEta::default())
+                .progress_chars("##-"),
+        );
+        Ok(self.track(pb))
+    }
+}
+
+/// 基于按时间加权的 EWMA 吞吐量估算剩余时间，CDN 忽快忽慢时不会大幅跳动
+#[derive(Clone, Default)]
+struct SmoothEta {
+    /// 每秒完成的数量
+    rate: f64,
+    last: Option<(u64, Instant)>,
+}
+
+impl SmoothEta {
+    /// 距离上次采样 `dt` 时，新样本所占的权重
+    fn weight(dt: Duration) -> f64 {
+        1.0 - 0.5f64.powf(dt.as_secs_f64() / ETA_HALF_LIFE.as_secs_f64())
+    }
+}
+
+impl ProgressTracker for SmoothEta {
+    fn clone_box(&self) -> Box<dyn ProgressTracker> {
+        Box::new(self.clone())
+    }
+
+    fn tick(&mut self, state: &ProgressState, now: Instant) {
+        let pos = state.pos();
+        let Some((last_pos, last_at)) = self.last else {
+            self.last = Some((pos, now));
+            return;
+        };
+        let dt = now.saturating_duration_since(last_at);
+        if dt < ETA_SAMPLE_INTERVAL {
+            return;
+        }
+        let sample = pos.saturating_sub(last_pos) as f64 / dt.as_secs_f64();
+        self.rate = if self.rate == 0.0 {
+            sample
+        } else {
+            self.rate + Self::weight(dt) * (sample - self.rate)
+        };
+        self.last = Some((pos, now));
+    }
+
+    fn reset(&mut self, _: &ProgressState, _: Instant) {
+        *self = Self::default();
+    }
+
+    fn write(&self, state: &ProgressState, w: &mut dyn fmt::Write) {
+        // 长时间没有进展时，把这段空白也计入速度，ETA 随之变长
+        let rate = match self.last {
+            Some((_, at)) if at.elapsed() > ETA_SAMPLE_INTERVAL => {
+                self.rate * (1.0 - Self::weight(at.elapsed()))
+            }
+            _ => self.rate,
+        };
+        let remaining = state.len().unwrap_or(0).saturating_sub(state.pos()) as f64;
+        match Duration::try_from_secs_f64(remaining / rate) {
+            Ok(eta) if rate > 0.0 => {
+                let _ = write!(w, "{:#}", HumanDuration(eta));
+            }
+            _ => {
+                let _ = write!(w, "--");
+            }
+        }
+    }
+}
+
+fn spawn_plain_reporter(bars: Arc<Mutex<Vec<ProgressBar>>>, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            report(&bars);
+        }
+    });
+}
+
+/// 每个进度条输出一行当前状态；已结束的进度条输出最终消息后移除
+fn report(bars: &Mutex<Vec<ProgressBar>>) {
+    bars.lock().unwrap().retain(|pb| {
+        match pb.length() {
+            Some(len) => info!("{} {}/{}", pb.message(), pb.position(), len),
+            None => info!("{}", pb.message()),
+        }
+        !pb.is_finished()
+    });
+}
+
+/// 根据 locale 判断终端能否显示 emoji 与 Braille 字符
+fn terminal_supports_unicode() -> bool {
+    if cfg!(windows) {
+        // 传统 conhost 不支持 emoji，Windows Terminal 会设置 WT_SESSION
+        return std::env::var_os("WT_SESSION").is_some();
+    }
+    if std::env::var("TERM").is_ok_and(|t| t == "linux" || t == "dumb") {
+        return false;
+    }
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .find_map(|k| std::env::var(k).ok().filter(|v| !v.is_empty()))
+        .unwrap_or_default()
+        .to_lowercase();
+    locale.contains("utf-8") || locale.contains("utf8")
+}
@@ -0,0 +1,84 @@
+use std::path::Path;
+use tracing::{debug, info};
+
+use crate::i18n::tr;
+use crate::{Args, Timing, manifest, pipeline};
+
+/// 决定是否直接复制视频：FFprobe 查看合并后的视频编码，输出容器能直接容纳 (例如 H.264 写入 MP4)
+/// 且没有需要重新编码的选项时只封装不转码；音频是否复制由 `--audio-codec` 决定
+pub async fn copy(args: &Args, timing: &Timing, input: &str, output: &Path) -> bool {
+    if let Some(reason) = transcode_reason(args, timing) {
+        debug!("{}", tr!("重新编码视频: {}", reason));
+        return false;
+    }
+    if input == pipeline::INPUT {
+        return false;
+    }
+    let Some(path) = manifest::probe_target(Path::new(input)).await else {
+        return false;
+    };
+    let info = match manifest::probe(&path).await {
+        Ok(info) => info,
+        Err(e) => {
+            debug!("{}", tr!("无法查看源视频编码，重新编码: {:#}", e));
+            return false;
+        }
+    };
+    let ext = output
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let Some(video) = info.streams.iter().find(|s| s.codec_type == "video") else {
+        return false;
+    };
+    if !supports(&ext, &video.codec_name) {
+        info!(
+            "{}",
+            tr!("源视频为 {}，输出容器不支持，重新编码", video.codec_name)
+        );
+        return false;
+    }
+    info!(
+        "{}",
+        tr!("源视频为 {}，直接复制不重新编码", video.codec_name)
+    );
+    true
+}
+
+/// 需要重新编码视频的选项
+fn transcode_reason(args: &Args, timing: &Timing) -> Option<&'static str> {
+    if args.transcode {
+        Some(tr!("指定了 --transcode"))
+    } else if args.video_bitrate > 0 || args.two_pass {
+        Some(tr!("指定了视频码率"))
+    } else if args.preset.is_some()
+        || args.video_profile.is_some()
+        || args.tune.is_some()
+        || args.level.is_some()
+    {
+        Some(tr!("指定了编码参数"))
+    } else if args.scale.is_some() || args.fps.is_some() || args.deinterlace {
+        Some(tr!("需要缩放、改变帧率或去隔行"))
+    } else if args.tonemap.is_some() {
+        Some(tr!("需要色调映射"))
+    } else if timing.subtitles.is_some() {
+        Some(tr!("需要烧录字幕"))
+    } else if timing.failover {
+        // 换用的备用变体流与原变体流的分辨率、编码参数不同
+        Some(tr!("部分切片来自备用变体流，编码参数不同"))
+    } else if timing.clip.is_some() {
+        // 复制时只能在关键帧处裁剪
+        Some(tr!("需要精确裁剪"))
+    } else {
+        None
+    }
+}
+
+/// 输出容器能否直接容纳这种视频编码
+fn supports(ext: &str, codec: &str) -> bool {
+    match ext {
+        "mkv" => true,
+        "webm" => matches!(codec, "vp8" | "vp9" | "av1"),
+        _ => codec == "h264",
+    }
+}
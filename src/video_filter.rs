@@ -0,0 +1,110 @@
+use crate::i18n::tr;
+use crate::{AccelType, Args};
+
+/// `--scale` 的目标尺寸，-2 表示按原画面比例计算 (取偶数)
+#[derive(Clone, Copy, Debug)]
+pub struct Scale {
+    width: i32,
+    height: i32,
+}
+
+/// 解析 `--scale`：`1280x720`，省略一边 (`x720`、`1280x`) 时按原画面比例计算
+pub fn parse_scale(text: &str) -> Result<Scale, String> {
+    let invalid = || tr!("无效的尺寸: {} (例如 1280x720、x720)", text);
+    let (width, height) = text.trim().split_once(['x', 'X']).ok_or_else(invalid)?;
+    let side = |value: &str| -> Result<i32, String> {
+        if value.is_empty() {
+            return Ok(-2);
+        }
+        match value.parse() {
+            Ok(n) if n > 0 => Ok(n),
+            _ => Err(invalid()),
+        }
+    };
+    let scale = Scale {
+        width: side(width)?,
+        height: side(height)?,
+    };
+    if scale.width < 0 && scale.height < 0 {
+        return Err(invalid());
+    }
+    Ok(scale)
+}
+
+/// 解析 `--fps`：`30`、`29.97` 或 `30000/1001`
+pub fn parse_fps(text: &str) -> Result<String, String> {
+    let text = text.trim();
+    let positive = |value: &str| value.parse::<f64>().is_ok_and(|n| n > 0.0);
+    let valid = match text.split_once('/') {
+        Some((num, den)) => positive(num) && positive(den),
+        None => positive(text),
+    };
+    if valid {
+        Ok(text.to_string())
+    } else {
+        Err(tr!("无效的帧率: {} (例如 30、29.97、30000/1001)", text))
+    }
+}
+
+/// 解码后的画面所在位置
+#[derive(Clone, Copy, PartialEq)]
+pub enum Frames {
+    System,
+    /// NVDEC 解码，画面留在显存
+    Cuda,
+    /// Quick Sync 解码，画面留在显存
+    Qsv,
+}
+
+/// 画面留在显存时要用对应硬件的滤镜。`software` 表示有只能处理内存中画面的滤镜
+/// (烧录字幕、色调映射)；Quick Sync 只在需要缩放或去隔行时才改用硬件解码
+pub fn frames(accel: AccelType, args: &Args, software: bool) -> Frames {
+    match accel {
+        _ if software => Frames::System,
+        AccelType::Nvidia => Frames::Cuda,
+        AccelType::Intel if args.scale.is_some() || args.deinterlace => Frames::Qsv,
+        _ => Frames::System,
+    }
+}
+
+/// `--deinterlace` / `--fps` / `--scale`、色调映射与烧录字幕组成的 `-vf` 滤镜链，没有滤镜时为 `None`。
+/// 顺序为去隔行、色调映射、帧率、缩放，字幕最后烧录，按输出尺寸渲染
+pub fn chain(
+    frames: Frames,
+    args: &Args,
+    tonemap: Option<&str>,
+    subtitles: Option<String>,
+) -> Option<String> {
+    let mut filters = Vec::new();
+    match frames {
+        Frames::System | Frames::Cuda => {
+            let cuda = if frames == Frames::Cuda { "_cuda" } else { "" };
+            // 只处理标记为隔行的画面，逐行扫描的部分保持不变
+            if args.deinterlace {
+                filters.push(format!("yadif{}=deint=interlaced", cuda));
+            }
+            filters.extend(tonemap.map(String::from));
+            if let Some(fps) = &args.fps {
+                filters.push(format!("fps={}", fps));
+            }
+            if let Some(scale) = args.scale {
+                filters.push(format!("scale{}={}:{}", cuda, scale.width, scale.height));
+            }
+        }
+        Frames::Qsv => {
+            let mut options = Vec::new();
+            if args.deinterlace {
+                options.push("deinterlace=2".to_string());
+            }
+            if let Some(fps) = &args.fps {
+                options.push(format!("framerate={}", fps));
+            }
+            if let Some(scale) = args.scale {
+                options.push(format!("w={}:h={}", scale.width, scale.height));
+            }
+            filters.push(format!("vpp_qsv={}", options.join(":")));
+        }
+    }
+    filters.extend(subtitles);
+    (!filters.is_empty()).then(|| filters.join(","))
+}
@@ -0,0 +1,96 @@
+use anyhow::{Result, bail};
+use indicatif::ProgressBar;
+use m3u8_rs::{Playlist, parse_playlist};
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+use crate::control::Cancelled;
+use crate::i18n::tr;
+use crate::{Args, Job, Shared, dash, download_playlist, extract};
+
+/// `--wait`：预约的直播开始前，播放列表通常返回 404 或没有切片。按 `--wait-interval` 轮询，
+/// 直到下载到有切片 (或有变体流) 的播放列表；超过 `--wait-timeout` 时放弃
+pub async fn until_live(
+    url: &str,
+    job: &Job,
+    args: &Args,
+    shared: &Shared,
+    pb: &ProgressBar,
+) -> Result<Vec<u8>> {
+    let started = Instant::now();
+    let mut warned = false;
+    loop {
+        let reason = match download_playlist(url, args).await {
+            Ok(content) => match pending(url, &content) {
+                None => {
+                    if warned {
+                        info!("{}", tr!("直播已开始，等待了 {}", elapsed(started)));
+                    }
+                    return Ok(content);
+                }
+                Some(reason) => reason,
+            },
+            Err(e) => format!("{:#}", e),
+        };
+        if !warned {
+            info!(
+                "{}",
+                tr!("直播尚未开始 ({})，每 {} 检查一次", reason, interval(args))
+            );
+            warned = true;
+        } else {
+            debug!("{}", tr!("直播尚未开始: {}", reason));
+        }
+        if let Some(timeout) = args.wait_timeout
+            && started.elapsed() >= timeout
+        {
+            bail!(tr!("等待 {} 后直播仍未开始: {}", elapsed(started), reason));
+        }
+        pb.set_message(tr!("等待直播开始... 已等待 {}", elapsed(started)));
+        let wait = match args.wait_timeout {
+            Some(timeout) => args
+                .wait_interval
+                .min(timeout.saturating_sub(started.elapsed())),
+            None => args.wait_interval,
+        };
+        tokio::select! {
+            _ = shared.control.cancelled(job.id) => bail!(Cancelled),
+            _ = tokio::time::sleep(wait) => {}
+        }
+    }
+}
+
+/// 还不能开始下载的原因。网页、DASH 清单交给后续流程处理；HLS 播放列表需要有切片或变体流
+fn pending(url: &str, content: &[u8]) -> Option<String> {
+    if extract::is_html(content) || dash::is_mpd(url, content) {
+        return None;
+    }
+    match parse_playlist(content) {
+        Ok((_, Playlist::MasterPlaylist(master))) if master.variants.is_empty() => {
+            Some(tr!("播放列表中还没有变体流").to_string())
+        }
+        Ok((_, Playlist::MediaPlaylist(media))) if media.segments.is_empty() => {
+            Some(tr!("播放列表中还没有切片").to_string())
+        }
+        Ok(_) => None,
+        Err(e) => Some(tr!("无法解析播放列表: {}", format!("{:?}", e))),
+    }
+}
+
+fn elapsed(started: Instant) -> String {
+    format_duration(started.elapsed())
+}
+
+fn interval(args: &Args) -> String {
+    format_duration(args.wait_interval)
+}
+
+/// 例如 `45s`、`5m30s`、`2h05m`
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..60 => format!("{}s", secs),
+        60..3600 => format!("{}m{:02}s", secs / 60, secs % 60),
+        _ => format!("{}h{:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
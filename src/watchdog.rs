@@ -0,0 +1,131 @@
+use anyhow::Result;
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::broadcast::{Receiver, error::RecvError};
+use tokio::time::Instant;
+
+use crate::control::{Control, State};
+use crate::events::JobEvent;
+use crate::i18n::tr;
+use crate::recovery::Failure;
+
+/// 超过 `--max-runtime` 退出时的退出码，与 coreutils `timeout` 相同
+pub const EXIT_MAX_RUNTIME: i32 = 124;
+
+/// 运行时间超过 `--max-runtime`，已下载的切片保留在 `.parts` 目录中供下次继续
+#[derive(Debug)]
+pub struct RuntimeExceeded(pub Duration);
+
+impl fmt::Display for RuntimeExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            tr!(
+                "运行时间超过 --max-runtime ({}s)，已下载的切片已保留，重新运行即可继续",
+                self.0.as_secs()
+            )
+        )
+    }
+}
+
+/// 解析时长，例如 `4h`、`1h30m`、`90m`、`45s`，纯数字按秒计
+pub fn parse_runtime(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    if let Ok(secs) = text.parse::<u64>() {
+        return Ok(Duration::from_secs(secs));
+    }
+    let invalid = || tr!("无效的时长: {} (例如 4h、1h30m、90m)", text);
+    let mut total = 0u64;
+    let mut number = String::new();
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let value: u64 = number.parse().map_err(|_| invalid())?;
+        number.clear();
+        let unit = match c.to_ascii_lowercase() {
+            'd' => 86_400,
+            'h' => 3_600,
+            'm' => 60,
+            's' => 1,
+            _ => return Err(tr!("无效的时长单位 {}: {}", c, text)),
+        };
+        // 数值过大时按无效处理，不能溢出
+        total = value
+            .checked_mul(unit)
+            .and_then(|secs| total.checked_add(secs))
+            .ok_or_else(invalid)?;
+    }
+    if !number.is_empty() || total == 0 {
+        return Err(invalid());
+    }
+    Ok(Duration::from_secs(total))
+}
+
+/// 执行任务的同时监视其进展：超过 `idle` 没有切片完成、合并或转码进展时放弃任务，
+/// 返回 `Failure::Stalled`，可以配合 `--recover stalled=...` 重跑；暂停期间不计时
+pub async fn guard<T>(
+    work: impl Future<Output = Result<T>>,
+    mut rx: Receiver<JobEvent>,
+    control: &Control,
+    job: usize,
+    idle: Duration,
+) -> Result<T> {
+    tokio::pin!(work);
+    let deadline = tokio::time::sleep(idle);
+    tokio::pin!(deadline);
+    loop {
+        tokio::select! {
+            result = &mut work => return result,
+            event = rx.recv() => match event {
+                Ok(
+                    JobEvent::SegmentDone { job: id, .. }
+                    | JobEvent::Merge { job: id, .. }
+                    | JobEvent::Transcode { job: id, .. },
+                ) if id == job => deadline.as_mut().reset(Instant::now() + idle),
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return work.await,
+            },
+            _ = &mut deadline => {
+                if control.state(job) != State::Paused {
+                    return Err(anyhow::anyhow!(Failure::Stalled(idle.as_secs() / 60)));
+                }
+                deadline.as_mut().reset(Instant::now() + idle);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_runtime_accepts_units_and_seconds() {
+        assert_eq!(parse_runtime("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_runtime(" 45s "), Ok(Duration::from_secs(45)));
+        assert_eq!(parse_runtime("1h30m"), Ok(Duration::from_secs(5_400)));
+        assert_eq!(parse_runtime("2D4H"), Ok(Duration::from_secs(187_200)));
+    }
+
+    #[test]
+    fn parse_runtime_rejects_invalid_input() {
+        for text in ["", "0m", "h", "4x", "1h30", "4 h", "-5m"] {
+            assert!(parse_runtime(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn parse_runtime_rejects_overflow() {
+        assert!(parse_runtime("999999999999999999d").is_err());
+        assert!(parse_runtime("99999999999999999999s").is_err());
+        assert!(parse_runtime(&format!("{}s1s", u64::MAX)).is_err());
+        assert_eq!(
+            parse_runtime(&format!("{}s", u64::MAX)),
+            Ok(Duration::from_secs(u64::MAX))
+        );
+    }
+}